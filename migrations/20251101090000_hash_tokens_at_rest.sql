@@ -0,0 +1,20 @@
+-- Migration 0007: Store only SHA-256 digests of bearer tokens
+
+-- Existing rows hold plaintext tokens which we can't trust anymore, so they are
+-- invalidated. Users have to log in again and request new verification / reset links.
+DELETE FROM refresh_tokens;
+DELETE FROM email_verification_tokens;
+DELETE FROM password_reset_tokens;
+
+ALTER TABLE refresh_tokens RENAME COLUMN token TO token_hash;
+ALTER TABLE email_verification_tokens RENAME COLUMN token TO token_hash;
+ALTER TABLE password_reset_tokens RENAME COLUMN token TO token_hash;
+
+-- hex encoded SHA-256 is always 64 characters
+ALTER TABLE refresh_tokens ALTER COLUMN token_hash TYPE CHAR(64);
+ALTER TABLE email_verification_tokens ALTER COLUMN token_hash TYPE CHAR(64);
+ALTER TABLE password_reset_tokens ALTER COLUMN token_hash TYPE CHAR(64);
+
+ALTER INDEX idx_refresh_tokens_token RENAME TO idx_refresh_tokens_token_hash;
+ALTER INDEX idx_email_verification_tokens_token RENAME TO idx_email_verification_tokens_token_hash;
+ALTER INDEX idx_password_reset_tokens_token RENAME TO idx_password_reset_tokens_token_hash;
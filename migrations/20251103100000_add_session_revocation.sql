@@ -0,0 +1,11 @@
+-- Migration 0008: Bind access tokens to sessions and a per-user token version
+
+-- bumping the version invalidates every access token issued before
+ALTER TABLE users
+ADD COLUMN token_version INTEGER NOT NULL DEFAULT 0;
+
+-- a session is the chain of rotated refresh tokens started by a single login
+ALTER TABLE refresh_tokens
+ADD COLUMN session_id UUID NOT NULL DEFAULT uuid_generate_v4();
+
+CREATE INDEX idx_refresh_tokens_session_id ON refresh_tokens(session_id);
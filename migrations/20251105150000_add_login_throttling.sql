@@ -0,0 +1,27 @@
+-- Migration 0009: Track failed logins and lock accounts after too many
+
+CREATE TABLE failed_login_attempts (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    -- lowercased login email, also tracked when no such account exists
+    email VARCHAR(255) NOT NULL,
+    ip_address VARCHAR(45) NOT NULL,
+    attempted_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_failed_login_attempts_email ON failed_login_attempts(email, attempted_at);
+CREATE INDEX idx_failed_login_attempts_ip_address ON failed_login_attempts(ip_address, attempted_at);
+
+ALTER TABLE users
+ADD COLUMN locked_until TIMESTAMP WITH TIME ZONE;
+
+CREATE TABLE account_unlock_tokens (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    token_hash CHAR(64) UNIQUE NOT NULL,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+CREATE INDEX idx_account_unlock_tokens_token_hash ON account_unlock_tokens(token_hash);
+CREATE INDEX idx_account_unlock_tokens_user_id ON account_unlock_tokens(user_id);
+CREATE INDEX idx_account_unlock_tokens_expires_at ON account_unlock_tokens(expires_at);
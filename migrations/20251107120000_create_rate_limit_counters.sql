@@ -0,0 +1,12 @@
+-- Migration 0010: Shared rate limit counters, so limits hold across instances
+
+CREATE TABLE rate_limit_counters (
+    -- "<route group>:ip:<address>" or "<route group>:user:<id>"
+    key VARCHAR(255) NOT NULL,
+    window_start TIMESTAMP WITH TIME ZONE NOT NULL,
+    count BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (key, window_start)
+);
+
+-- Index for cleaning up old windows
+CREATE INDEX idx_rate_limit_counters_window_start ON rate_limit_counters(window_start);
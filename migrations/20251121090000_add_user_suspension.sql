@@ -0,0 +1,20 @@
+-- Migration 0016: Account suspension and an audit log of what admins did to which account
+
+ALTER TABLE users ADD COLUMN suspended_at TIMESTAMP WITH TIME ZONE;
+ALTER TABLE users ADD COLUMN suspension_reason TEXT;
+
+CREATE TABLE audit_events (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    -- dotted name like admin.suspended or admin.role_granted
+    event_type VARCHAR(50) NOT NULL,
+    -- the account the event is about, kept when it's deleted, the record matters more than the link
+    user_id UUID REFERENCES users(id) ON DELETE SET NULL,
+    -- who did it when that's someone else, like the admin suspending an account
+    actor_id UUID REFERENCES users(id) ON DELETE SET NULL,
+    -- whatever else is worth knowing, e.g. the suspension reason or the role that was granted
+    metadata JSONB NOT NULL DEFAULT '{}',
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_audit_events_user_id ON audit_events(user_id, created_at DESC);
+CREATE INDEX idx_audit_events_actor_id ON audit_events(actor_id, created_at DESC);
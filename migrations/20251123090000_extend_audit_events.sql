@@ -0,0 +1,13 @@
+-- Migration 0017: Security audit log, audit_events records logins, tokens and the rest too
+
+-- user_id stays NULL e.g. for a failed login with an unknown email
+ALTER TABLE audit_events ADD COLUMN ip_address VARCHAR(45);
+ALTER TABLE audit_events ADD COLUMN user_agent TEXT;
+
+CREATE INDEX idx_audit_events_event_type ON audit_events(event_type, created_at DESC);
+CREATE INDEX idx_audit_events_created_at ON audit_events(created_at DESC);
+
+INSERT INTO role_permissions (role_id, permission)
+SELECT id, 'audit:read'
+FROM roles
+WHERE name = 'admin';
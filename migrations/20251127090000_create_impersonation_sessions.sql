@@ -0,0 +1,20 @@
+-- Migration 0019: Admins signed in as another user, for support
+
+CREATE TABLE impersonation_sessions (
+    -- doubles as the sid of the access token, there are no refresh tokens behind it
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    admin_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    reason TEXT NOT NULL,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    ended_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_impersonation_sessions_user_id ON impersonation_sessions(user_id);
+CREATE INDEX idx_impersonation_sessions_admin_id ON impersonation_sessions(admin_id);
+
+INSERT INTO role_permissions (role_id, permission)
+SELECT id, 'users:impersonate'
+FROM roles
+WHERE name = 'admin';
@@ -0,0 +1,13 @@
+-- Migration 0020: When the user last entered their password in a session, for step-up checks
+
+ALTER TABLE refresh_tokens ADD COLUMN authenticated_at TIMESTAMP WITH TIME ZONE;
+
+-- the login that started the session, rotated tokens keep the first one's time
+UPDATE refresh_tokens r
+SET authenticated_at = (
+    SELECT COALESCE(MIN(created_at), NOW()) FROM refresh_tokens s WHERE s.session_id = r.session_id
+);
+
+ALTER TABLE refresh_tokens
+    ALTER COLUMN authenticated_at SET NOT NULL,
+    ALTER COLUMN authenticated_at SET DEFAULT NOW();
@@ -0,0 +1,13 @@
+-- Migration 0021: Users deleting their account, purged for good after a grace period
+
+ALTER TABLE users ADD COLUMN deleted_at TIMESTAMP WITH TIME ZONE;
+
+CREATE INDEX idx_users_deleted_at ON users(deleted_at) WHERE deleted_at IS NOT NULL;
+
+-- Every table with a user_id cascades already, except the audit log which kept the event and
+-- dropped the name. A purge has to take the user's own history along. What they did as an admin
+-- (actor_id) stays, without saying who it was.
+ALTER TABLE audit_events
+    DROP CONSTRAINT audit_events_user_id_fkey,
+    ADD CONSTRAINT audit_events_user_id_fkey
+        FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE;
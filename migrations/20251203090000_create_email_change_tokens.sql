@@ -0,0 +1,19 @@
+-- Migration 0022: Changing the email only after the new address is confirmed
+
+-- shown to the user until it's confirmed, users.email stays the login until then
+ALTER TABLE users ADD COLUMN pending_email VARCHAR(255);
+
+-- same shape as email_verification_tokens, plus the address following the link switches to
+CREATE TABLE email_change_tokens (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- confirm goes to the new address, revert to the old one
+    purpose VARCHAR(10) NOT NULL CHECK (purpose IN ('confirm', 'revert')),
+    email VARCHAR(255) NOT NULL,
+    token_hash CHAR(64) UNIQUE NOT NULL,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_email_change_tokens_user_id ON email_change_tokens(user_id);
+CREATE INDEX idx_email_change_tokens_expires_at ON email_change_tokens(expires_at);
@@ -0,0 +1,33 @@
+-- Migration 0023: Invite codes for invite-only registration
+
+-- NULL means INVITE_DEFAULT_QUOTA, counted in people, not codes
+ALTER TABLE users ADD COLUMN invite_quota INTEGER CHECK (invite_quota >= 0);
+
+CREATE TABLE invites (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    created_by UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- the code is shown once when it's created, like a personal access token
+    code_hash CHAR(64) UNIQUE NOT NULL,
+    max_uses INTEGER NOT NULL CHECK (max_uses > 0),
+    uses INTEGER NOT NULL DEFAULT 0 CHECK (uses <= max_uses),
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    revoked_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_invites_created_by ON invites(created_by);
+
+-- who joined with which invite, the inviter is the invite's created_by
+CREATE TABLE invite_redemptions (
+    invite_id UUID NOT NULL REFERENCES invites(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL UNIQUE REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_invite_redemptions_invite_id ON invite_redemptions(invite_id);
+
+-- admins hand out invites without a quota
+INSERT INTO role_permissions (role_id, permission)
+SELECT id, 'invites:manage'
+FROM roles
+WHERE name = 'admin';
@@ -0,0 +1,47 @@
+-- Migration 0024: Usernames and emails unique regardless of case
+
+-- Refuses to run while accounts differ only in case, the unique indexes below couldn't be
+-- built. Rename or merge the listed accounts and run it again.
+DO $$
+DECLARE
+    collisions TEXT;
+BEGIN
+    SELECT string_agg(matches, '; ') INTO collisions
+    FROM (
+        SELECT LOWER(email) || ' (' || string_agg(username, ', ') || ')' AS matches
+        FROM users
+        GROUP BY LOWER(email)
+        HAVING COUNT(*) > 1
+    ) emails;
+
+    IF collisions IS NOT NULL THEN
+        RAISE EXCEPTION 'Accounts whose emails differ only in case: %', collisions;
+    END IF;
+
+    SELECT string_agg(matches, '; ') INTO collisions
+    FROM (
+        SELECT LOWER(username) || ' (' || string_agg(email, ', ') || ')' AS matches
+        FROM users
+        GROUP BY LOWER(username)
+        HAVING COUNT(*) > 1
+    ) usernames;
+
+    IF collisions IS NOT NULL THEN
+        RAISE EXCEPTION 'Accounts whose usernames differ only in case: %', collisions;
+    END IF;
+END
+$$;
+
+-- lookups go through LOWER() now, the plain indexes wouldn't be used anymore
+DROP INDEX idx_users_email;
+DROP INDEX idx_users_username;
+
+CREATE UNIQUE INDEX idx_users_email_lower ON users(LOWER(email));
+CREATE UNIQUE INDEX idx_users_username_lower ON users(LOWER(username));
+
+-- Lowercased UTS #39 skeleton of the NFKC username, so look-alike names can't both exist.
+-- Postgres can't compute it, the app fills it in for existing accounts on startup and logs the
+-- ones that collide with another account.
+ALTER TABLE users ADD COLUMN username_skeleton VARCHAR(255);
+
+CREATE UNIQUE INDEX idx_users_username_skeleton ON users(username_skeleton);
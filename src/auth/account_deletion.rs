@@ -0,0 +1,25 @@
+use std::env;
+
+use chrono::{DateTime, Duration, Utc};
+
+// how long a deleted account can still be restored by logging in
+pub struct AccountDeletionConfig {
+    pub grace_period: Duration,
+}
+
+impl AccountDeletionConfig {
+    pub fn from_env() -> Self {
+        let grace_days = env::var("ACCOUNT_DELETION_GRACE_DAYS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(30);
+
+        Self {
+            grace_period: Duration::days(grace_days),
+        }
+    }
+
+    pub fn purge_at(&self, deleted_at: DateTime<Utc>) -> DateTime<Utc> {
+        deleted_at + self.grace_period
+    }
+}
@@ -0,0 +1,45 @@
+use crate::{models::NewAuditEvent, state::AppState};
+
+// Event types written to audit_events. Plain strings so adding one needs no migration, keep
+// the names stable though, the admin endpoint filters on them.
+pub const LOGIN_SUCCEEDED: &str = "login.succeeded";
+pub const LOGIN_FAILED: &str = "login.failed";
+pub const USER_REGISTERED: &str = "user.registered";
+pub const USER_DELETION_REQUESTED: &str = "user.deletion_requested";
+pub const USER_RESTORED: &str = "user.restored";
+pub const USER_PURGED: &str = "user.purged";
+pub const USER_DATA_EXPORTED: &str = "user.data_exported";
+pub const EMAIL_VERIFIED: &str = "email.verified";
+pub const EMAIL_CHANGE_REQUESTED: &str = "email.change_requested";
+pub const EMAIL_CHANGED: &str = "email.changed";
+pub const EMAIL_CHANGE_REVERTED: &str = "email.change_reverted";
+pub const PASSWORD_RESET_REQUESTED: &str = "password_reset.requested";
+pub const PASSWORD_RESET_COMPLETED: &str = "password_reset.completed";
+pub const PASSWORD_CHANGED: &str = "password.changed";
+pub const REFRESH_TOKEN_REUSED: &str = "refresh_token.reused";
+pub const SESSION_REVOKED: &str = "session.revoked";
+pub const ALL_SESSIONS_REVOKED: &str = "session.revoked_all";
+pub const SIGN_IN_REPORTED: &str = "session.reported";
+pub const SESSION_REAUTHENTICATED: &str = "session.reauthenticated";
+pub const INVITE_CREATED: &str = "invite.created";
+pub const INVITE_REVOKED: &str = "invite.revoked";
+
+pub const ADMIN_ROLE_GRANTED: &str = "admin.role_granted";
+pub const ADMIN_ROLE_REVOKED: &str = "admin.role_revoked";
+pub const ADMIN_EMAIL_VERIFIED: &str = "admin.email_verified";
+pub const ADMIN_PASSWORD_RESET_SENT: &str = "admin.password_reset_sent";
+pub const ADMIN_SUSPENDED: &str = "admin.suspended";
+pub const ADMIN_UNSUSPENDED: &str = "admin.unsuspended";
+pub const ADMIN_LOGGED_OUT: &str = "admin.logged_out";
+pub const ADMIN_IMPERSONATION_STARTED: &str = "admin.impersonation_started";
+pub const ADMIN_IMPERSONATION_STOPPED: &str = "admin.impersonation_stopped";
+pub const ADMIN_IMPERSONATION_EXPIRED: &str = "admin.impersonation_expired";
+pub const ADMIN_INVITE_QUOTA_CHANGED: &str = "admin.invite_quota_changed";
+
+// A lost audit record shouldn't cost the user their login, so failures only get logged.
+// Admin actions go through the repository directly and fail the request instead.
+pub async fn record(state: &AppState, event: NewAuditEvent) {
+    if let Err(e) = state.audit_event_repository.record(&event).await {
+        eprintln!("Failed to record {} audit event: {}", event.event_type, e);
+    }
+}
@@ -0,0 +1,168 @@
+use std::env;
+
+use axum::http::{HeaderMap, StatusCode};
+use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
+use subtle::ConstantTimeEq;
+
+use crate::auth::tokens::generate_secure_token;
+
+pub const REFRESH_TOKEN_COOKIE: &str = "refresh_token";
+pub const CSRF_COOKIE: &str = "csrf_token";
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+pub const MAGIC_LINK_NONCE_COOKIE: &str = "magic_link_nonce";
+pub const OAUTH_STATE_COOKIE: &str = "oauth_state";
+
+// the refresh cookie is only ever needed by /api/auth/refresh and /api/auth/logout
+const REFRESH_TOKEN_COOKIE_PATH: &str = "/api/auth";
+
+const MAGIC_LINK_NONCE_COOKIE_PATH: &str = "/api/auth/magic-link";
+const OAUTH_STATE_COOKIE_PATH: &str = "/api/auth/oauth";
+
+// matches the refresh_tokens.expires_at default
+const REFRESH_TOKEN_MAX_AGE_DAYS: i64 = 7;
+
+// Optional browser mode: the refresh token lives in an HttpOnly cookie instead of the JSON body,
+// so scripts can't read it. Because browsers attach cookies on their own, the cookie path is
+// protected with a double-submit CSRF token that the frontend echoes back in X-CSRF-Token.
+#[derive(Clone)]
+pub struct CookieConfig {
+    pub enabled: bool,
+    pub secure: bool,
+    pub same_site: SameSite,
+}
+
+impl CookieConfig {
+    pub fn from_env() -> Self {
+        let enabled = env::var("AUTH_COOKIES")
+            .map(|value| value == "true")
+            .unwrap_or(false);
+
+        // only turn off for plain http development setups
+        let secure = env::var("AUTH_COOKIE_SECURE")
+            .map(|value| value != "false")
+            .unwrap_or(true);
+
+        let same_site = match env::var("AUTH_COOKIE_SAME_SITE").as_deref() {
+            Ok("Lax") | Ok("lax") => SameSite::Lax,
+            Ok("None") | Ok("none") => SameSite::None,
+            _ => SameSite::Strict,
+        };
+
+        Self {
+            enabled,
+            secure,
+            same_site,
+        }
+    }
+
+    pub fn set_session_cookies(&self, jar: CookieJar, refresh_token: &str) -> CookieJar {
+        let max_age = time::Duration::days(REFRESH_TOKEN_MAX_AGE_DAYS);
+
+        let refresh_cookie = Cookie::build((REFRESH_TOKEN_COOKIE, refresh_token.to_string()))
+            .path(REFRESH_TOKEN_COOKIE_PATH)
+            .http_only(true)
+            .secure(self.secure)
+            .same_site(self.same_site)
+            .max_age(max_age);
+
+        // readable by the frontend on purpose, it has to copy it into the header
+        let csrf_cookie = Cookie::build((CSRF_COOKIE, generate_secure_token()))
+            .path("/")
+            .http_only(false)
+            .secure(self.secure)
+            .same_site(self.same_site)
+            .max_age(max_age);
+
+        jar.add(refresh_cookie).add(csrf_cookie)
+    }
+
+    pub fn clear_session_cookies(&self, jar: CookieJar) -> CookieJar {
+        jar.remove(Cookie::build(REFRESH_TOKEN_COOKIE).path(REFRESH_TOKEN_COOKIE_PATH))
+            .remove(Cookie::build(CSRF_COOKIE).path("/"))
+    }
+
+    // reads the refresh token from its cookie, but only if the CSRF header matches the cookie
+    pub fn refresh_token_from_cookies(
+        &self,
+        jar: &CookieJar,
+        headers: &HeaderMap,
+    ) -> Result<String, StatusCode> {
+        if !self.enabled {
+            return Err(StatusCode::UNAUTHORIZED);
+        }
+
+        let refresh_token = jar
+            .get(REFRESH_TOKEN_COOKIE)
+            .ok_or(StatusCode::UNAUTHORIZED)?;
+
+        let csrf_cookie = jar.get(CSRF_COOKIE).ok_or(StatusCode::FORBIDDEN)?;
+
+        let csrf_header = headers
+            .get(CSRF_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .ok_or(StatusCode::FORBIDDEN)?;
+
+        if !bool::from(csrf_cookie.value().as_bytes().ct_eq(csrf_header.as_bytes())) {
+            return Err(StatusCode::FORBIDDEN);
+        }
+
+        Ok(refresh_token.value().to_string())
+    }
+
+    // Ties a sign-in link to the browser that asked for it, a leaked or forwarded link is useless
+    // anywhere else. Set no matter whether AUTH_COOKIES is on, and at most Lax because the link
+    // is opened from a mail client, a Strict cookie wouldn't be sent along.
+    pub fn set_magic_link_nonce(
+        &self,
+        jar: CookieJar,
+        nonce: &str,
+        max_age: chrono::Duration,
+    ) -> CookieJar {
+        jar.add(self.navigation_cookie(
+            MAGIC_LINK_NONCE_COOKIE,
+            nonce,
+            MAGIC_LINK_NONCE_COOKIE_PATH,
+            max_age,
+        ))
+    }
+
+    pub fn clear_magic_link_nonce(&self, jar: CookieJar) -> CookieJar {
+        jar.remove(Cookie::build(MAGIC_LINK_NONCE_COOKIE).path(MAGIC_LINK_NONCE_COOKIE_PATH))
+    }
+
+    // the provider redirects back to the callback, which must come from the same browser
+    pub fn set_oauth_state(
+        &self,
+        jar: CookieJar,
+        state: &str,
+        max_age: chrono::Duration,
+    ) -> CookieJar {
+        jar.add(self.navigation_cookie(OAUTH_STATE_COOKIE, state, OAUTH_STATE_COOKIE_PATH, max_age))
+    }
+
+    pub fn clear_oauth_state(&self, jar: CookieJar) -> CookieJar {
+        jar.remove(Cookie::build(OAUTH_STATE_COOKIE).path(OAUTH_STATE_COOKIE_PATH))
+    }
+
+    // for cookies that have to survive a cross-site top level navigation
+    fn navigation_cookie(
+        &self,
+        name: &'static str,
+        value: &str,
+        path: &'static str,
+        max_age: chrono::Duration,
+    ) -> Cookie<'static> {
+        let same_site = match self.same_site {
+            SameSite::Strict => SameSite::Lax,
+            same_site => same_site,
+        };
+
+        Cookie::build((name, value.to_string()))
+            .path(path)
+            .http_only(true)
+            .secure(self.secure)
+            .same_site(same_site)
+            .max_age(time::Duration::seconds(max_age.num_seconds()))
+            .build()
+    }
+}
@@ -0,0 +1,5 @@
+// Admins can get a short lived access token for another user to see what they see. Routes that
+// could take over the account (password, tokens, linked logins) opt out with a NoImpersonation
+// layer, RequireAuth and friends turn impersonation tokens away there with a 403.
+#[derive(Clone, Copy)]
+pub struct NoImpersonation;
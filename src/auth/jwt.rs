@@ -1,39 +1,235 @@
-use chrono::{Duration, Utc};
-use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation, decode, encode};
+use std::{env, fmt};
+
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::{
+    Header, Validation, decode, decode_header, encode,
+    errors::{Error, ErrorKind},
+};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::auth::keys::JwtKeyStore;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Claims {
-    pub sub: String, // user id
-    pub exp: usize,  // expiration
-    pub iat: usize,  // issued at
+    pub sub: String,        // user id
+    pub sid: String,        // session id (shared by all refresh tokens of one login)
+    pub token_version: i32, // must match users.token_version, bumped to revoke everything
+    pub iss: String,        // issuer, differs between staging and production
+    pub aud: String,        // audience
+    pub jti: String,        // unique token id
+    pub exp: usize,         // expiration
+    pub nbf: usize,         // not valid before
+    pub iat: usize,         // issued at
+    // only on tokens issued to third-party clients, which are limited to the granted scopes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    // what the UI may show, RequireRole still asks the database
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roles: Vec<String>,
+    // when the user last entered their password, RequireRecentAuth checks it (OIDC auth_time)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_time: Option<usize>,
+    // the admin behind an impersonation token, sub is the user being impersonated (RFC 8693)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub act: Option<Actor>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Actor {
+    pub sub: String,
+}
+
+// changed from 24h to 15min when using refresh tokens
+pub const ACCESS_TOKEN_TTL_MINUTES: i64 = 15;
+
+// impersonation tokens can't be refreshed, support has to ask for a new one
+pub const IMPERSONATION_TOKEN_TTL_MINUTES: i64 = 10;
+
+// claim values and validation rules, the same for every token
+pub struct JwtConfig {
+    pub issuer: String,
+    pub audience: String,
+    pub leeway_seconds: u64,
+}
+
+impl JwtConfig {
+    // no defaults, environments sharing keys must not accept each other's tokens
+    pub fn from_env() -> Result<Self, &'static str> {
+        let issuer = env::var("JWT_ISSUER").map_err(|_| "JWT_ISSUER must be set")?;
+
+        let audience = env::var("JWT_AUDIENCE").map_err(|_| "JWT_AUDIENCE must be set")?;
+
+        // allowed clock skew between us and whoever verifies our tokens
+        let leeway_seconds = env::var("JWT_LEEWAY_SECONDS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(30);
+
+        Ok(Self {
+            issuer,
+            audience,
+            leeway_seconds,
+        })
+    }
+}
+
+// why a token got rejected, only meant for logs. clients always just get a 401
+#[derive(Debug)]
+pub enum TokenError {
+    Expired,
+    NotYetValid,
+    BadSignature,
+    UnknownKey,
+    WrongIssuer,
+    WrongAudience,
+    MissingClaim(String),
+    Malformed(Error),
+}
+
+impl fmt::Display for TokenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenError::Expired => write!(f, "token expired"),
+            TokenError::NotYetValid => write!(f, "token not valid yet"),
+            TokenError::BadSignature => write!(f, "bad signature"),
+            TokenError::UnknownKey => write!(f, "unknown signing key"),
+            TokenError::WrongIssuer => write!(f, "wrong issuer"),
+            TokenError::WrongAudience => write!(f, "wrong audience"),
+            TokenError::MissingClaim(claim) => write!(f, "missing claim {}", claim),
+            TokenError::Malformed(e) => write!(f, "malformed token: {}", e),
+        }
+    }
+}
+
+impl From<Error> for TokenError {
+    fn from(e: Error) -> Self {
+        match e.kind() {
+            ErrorKind::ExpiredSignature => TokenError::Expired,
+            ErrorKind::ImmatureSignature => TokenError::NotYetValid,
+            ErrorKind::InvalidSignature | ErrorKind::InvalidAlgorithm => TokenError::BadSignature,
+            ErrorKind::InvalidIssuer => TokenError::WrongIssuer,
+            ErrorKind::InvalidAudience => TokenError::WrongAudience,
+            ErrorKind::MissingRequiredClaim(claim) => TokenError::MissingClaim(claim.clone()),
+            _ => TokenError::Malformed(e),
+        }
+    }
+}
+
+pub fn generate_token(
+    user_id: &Uuid,
+    session_id: &Uuid,
+    token_version: i32,
+    roles: Vec<String>,
+    auth_time: DateTime<Utc>,
+    keys: &JwtKeyStore,
+    config: &JwtConfig,
+) -> Result<String, Error> {
+    let claims = Claims {
+        roles,
+        auth_time: Some(auth_time.timestamp() as usize),
+        ..new_claims(user_id, session_id, token_version, config)
+    };
+
+    sign(&claims, keys)
+}
+
+// access token for a session granted to a third-party client
+pub fn generate_client_token(
+    user_id: &Uuid,
+    session_id: &Uuid,
+    token_version: i32,
+    client_id: &Uuid,
+    scope: &str,
+    keys: &JwtKeyStore,
+    config: &JwtConfig,
+) -> Result<String, Error> {
+    let claims = Claims {
+        client_id: Some(client_id.to_string()),
+        scope: Some(scope.to_string()),
+        ..new_claims(user_id, session_id, token_version, config)
+    };
+
+    sign(&claims, keys)
+}
+
+// access token for an admin acting as another user, never carries roles
+pub fn generate_impersonation_token(
+    user_id: &Uuid,
+    session_id: &Uuid,
+    token_version: i32,
+    admin_id: &Uuid,
+    expires_at: DateTime<Utc>,
+    keys: &JwtKeyStore,
+    config: &JwtConfig,
+) -> Result<String, Error> {
+    let claims = Claims {
+        exp: expires_at.timestamp() as usize,
+        act: Some(Actor {
+            sub: admin_id.to_string(),
+        }),
+        ..new_claims(user_id, session_id, token_version, config)
+    };
+
+    sign(&claims, keys)
 }
 
-pub fn generate_token(user_id: &Uuid, secret: &str) -> Result<String, jsonwebtoken::errors::Error> {
+fn new_claims(user_id: &Uuid, session_id: &Uuid, token_version: i32, config: &JwtConfig) -> Claims {
     let now = Utc::now();
-    // changed from 24h to 15min when using refresh tokens
-    let exp = (now + Duration::minutes(15)).timestamp() as usize;
+    let exp = (now + Duration::minutes(ACCESS_TOKEN_TTL_MINUTES)).timestamp() as usize;
     let iat = now.timestamp() as usize;
 
-    let claims = Claims {
+    Claims {
         sub: user_id.to_string(),
+        sid: session_id.to_string(),
+        token_version,
+        iss: config.issuer.clone(),
+        aud: config.audience.clone(),
+        jti: Uuid::new_v4().to_string(),
         exp,
+        nbf: iat,
         iat,
-    };
+        client_id: None,
+        scope: None,
+        roles: Vec::new(),
+        auth_time: None,
+        act: None,
+    }
+}
+
+fn sign(claims: &Claims, keys: &JwtKeyStore) -> Result<String, Error> {
+    let (kid, algorithm, key) = keys
+        .signing_key()
+        .ok_or_else(|| Error::from(ErrorKind::InvalidKeyFormat))?;
 
-    encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(secret.as_ref()),
-    )
+    let mut header = Header::new(algorithm);
+    header.kid = kid;
+
+    encode(&header, claims, &key)
 }
 
-pub fn validate_token(token: &str, secret: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
-    decode::<Claims>(
-        &token,
-        &DecodingKey::from_secret(secret.as_ref()),
-        &Validation::default(),
-    )
-    .map(|data| data.claims)
+pub fn validate_token(
+    token: &str,
+    keys: &JwtKeyStore,
+    config: &JwtConfig,
+) -> Result<Claims, TokenError> {
+    // the kid picks the key, the key dictates the algorithm. never trust the alg header
+    let header = decode_header(token)?;
+
+    let (algorithm, key) = keys
+        .verification_key(header.kid.as_deref())
+        .ok_or(TokenError::UnknownKey)?;
+
+    let mut validation = Validation::new(algorithm);
+    validation.set_issuer(&[&config.issuer]);
+    validation.set_audience(&[&config.audience]);
+    validation.set_required_spec_claims(&["exp", "nbf", "iss", "aud", "sub"]);
+    validation.validate_nbf = true;
+    validation.leeway = config.leeway_seconds;
+
+    let claims = decode::<Claims>(token, &key, &validation)?.claims;
+
+    Ok(claims)
 }
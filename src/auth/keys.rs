@@ -0,0 +1,358 @@
+use std::{
+    collections::HashSet,
+    env, fs,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use base64::{
+    Engine,
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+};
+use jsonwebtoken::{
+    Algorithm, DecodingKey, EncodingKey, Header, Validation, decode, encode,
+    jwk::{
+        AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm,
+        OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+    },
+};
+use rsa::{RsaPublicKey, pkcs8::DecodePublicKey, traits::PublicKeyParts};
+use serde_json::json;
+
+type KeyError = Box<dyn std::error::Error + Send + Sync>;
+
+// DER prefix of an Ed25519 SubjectPublicKeyInfo, the raw 32 byte key follows
+const ED25519_SPKI_PREFIX: [u8; 12] = [
+    0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00,
+];
+
+struct SigningKey {
+    kid: Option<String>,
+    algorithm: Algorithm,
+    key: EncodingKey,
+}
+
+struct VerificationKey {
+    kid: Option<String>,
+    algorithm: Algorithm,
+    key: DecodingKey,
+    // None for the shared secret, that one must never be published
+    jwk: Option<Jwk>,
+}
+
+struct KeySet {
+    signing: SigningKey,
+    verification: Vec<VerificationKey>,
+}
+
+enum KeySource {
+    // legacy HS256 mode, only this service can verify its tokens
+    Secret(String),
+    // <kid>.pem holds a private key, <kid>.pub.pem the matching public key
+    Directory {
+        path: PathBuf,
+        publish_delay: Duration,
+    },
+}
+
+// All keys used to sign and verify access tokens.
+//
+// With JWT_KEY_DIR set, every public key in the directory is accepted and published via JWKS,
+// and tokens are signed with the newest private key that has been published for at least
+// JWT_KEY_PUBLISH_DELAY_SECONDS, so downstream caches know a key before the first token using it
+// shows up. Rotating means dropping a new key pair in, retiring means deleting the private key
+// first and the public key once the last tokens signed with it expired.
+pub struct JwtKeyStore {
+    source: KeySource,
+    keys: RwLock<KeySet>,
+}
+
+impl JwtKeyStore {
+    pub fn from_env() -> Result<Self, KeyError> {
+        let source = match env::var("JWT_KEY_DIR") {
+            Ok(path) => {
+                let publish_delay = env::var("JWT_KEY_PUBLISH_DELAY_SECONDS")
+                    .ok()
+                    .and_then(|value| value.parse().ok())
+                    .unwrap_or(3600);
+
+                KeySource::Directory {
+                    path: PathBuf::from(path),
+                    publish_delay: Duration::from_secs(publish_delay),
+                }
+            }
+            Err(_) => KeySource::Secret(
+                env::var("JWT_SECRET").map_err(|_| "JWT_KEY_DIR or JWT_SECRET must be set")?,
+            ),
+        };
+
+        let keys = source.load()?;
+
+        Ok(Self {
+            source,
+            keys: RwLock::new(keys),
+        })
+    }
+
+    pub fn reload(&self) -> Result<(), KeyError> {
+        let keys = self.source.load()?;
+
+        *self.keys.write().map_err(|_| "key store lock poisoned")? = keys;
+
+        Ok(())
+    }
+
+    // picks up new, promoted and retired keys without a restart
+    pub fn spawn_rotation(self: &Arc<Self>, every: Duration) {
+        if matches!(self.source, KeySource::Secret(_)) {
+            return;
+        }
+
+        let store = Arc::clone(self);
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(every);
+            // the first tick completes immediately, keys were just loaded
+            interval.tick().await;
+
+            loop {
+                interval.tick().await;
+
+                if let Err(e) = store.reload() {
+                    eprintln!("Failed to reload JWT keys, keeping current ones: {}", e);
+                }
+            }
+        });
+    }
+
+    pub fn signing_key(&self) -> Option<(Option<String>, Algorithm, EncodingKey)> {
+        let keys = self.keys.read().ok()?;
+
+        Some((
+            keys.signing.kid.clone(),
+            keys.signing.algorithm,
+            keys.signing.key.clone(),
+        ))
+    }
+
+    pub fn verification_key(&self, kid: Option<&str>) -> Option<(Algorithm, DecodingKey)> {
+        let keys = self.keys.read().ok()?;
+
+        keys.verification
+            .iter()
+            .find(|key| key.kid.as_deref() == kid)
+            .map(|key| (key.algorithm, key.key.clone()))
+    }
+
+    pub fn jwks(&self) -> JwkSet {
+        let keys = match self.keys.read() {
+            Ok(keys) => keys,
+            Err(_) => return JwkSet { keys: Vec::new() },
+        };
+
+        JwkSet {
+            keys: keys
+                .verification
+                .iter()
+                .filter_map(|key| key.jwk.clone())
+                .collect(),
+        }
+    }
+}
+
+impl KeySource {
+    fn load(&self) -> Result<KeySet, KeyError> {
+        match self {
+            KeySource::Secret(secret) => Ok(KeySet {
+                signing: SigningKey {
+                    kid: None,
+                    algorithm: Algorithm::HS256,
+                    key: EncodingKey::from_secret(secret.as_ref()),
+                },
+                verification: vec![VerificationKey {
+                    kid: None,
+                    algorithm: Algorithm::HS256,
+                    key: DecodingKey::from_secret(secret.as_ref()),
+                    jwk: None,
+                }],
+            }),
+            KeySource::Directory {
+                path,
+                publish_delay,
+            } => load_key_directory(path, *publish_delay),
+        }
+    }
+}
+
+fn load_key_directory(dir: &Path, publish_delay: Duration) -> Result<KeySet, KeyError> {
+    let mut verification = Vec::new();
+    // (kid, private key file, modified at)
+    let mut private_keys = Vec::new();
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let Some(file_name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+
+        if let Some(kid) = file_name.strip_suffix(".pub.pem") {
+            verification.push(load_public_key(kid, &path)?);
+        } else if let Some(kid) = file_name.strip_suffix(".pem") {
+            let modified = fs::metadata(&path)?.modified()?;
+            private_keys.push((kid.to_string(), path, modified));
+        }
+    }
+
+    let mut signing_keys = Vec::new();
+    for (kid, path, modified) in private_keys {
+        // a private key without its public half could never be verified
+        let Some(public_key) = verification
+            .iter()
+            .find(|key| key.kid.as_deref() == Some(kid.as_str()))
+        else {
+            continue;
+        };
+
+        let private_key = load_private_key(&kid, &path)?;
+        check_key_pair(&private_key, public_key)?;
+
+        signing_keys.push((private_key, modified));
+    }
+
+    let now = SystemTime::now();
+    let published = |modified: &SystemTime| {
+        now.duration_since(*modified)
+            .map(|age| age >= publish_delay)
+            .unwrap_or(false)
+    };
+
+    // newest published key wins, on a fresh setup fall back to the newest key at all
+    let newest_published = signing_keys
+        .iter()
+        .enumerate()
+        .filter(|(_, (_, modified))| published(modified))
+        .max_by_key(|(_, (_, modified))| *modified)
+        .map(|(index, _)| index);
+
+    let index = newest_published
+        .or_else(|| {
+            signing_keys
+                .iter()
+                .enumerate()
+                .max_by_key(|(_, (_, modified))| *modified)
+                .map(|(index, _)| index)
+        })
+        .ok_or_else(|| format!("no usable signing key in {}", dir.display()))?;
+
+    let (signing, _) = signing_keys.swap_remove(index);
+
+    Ok(KeySet {
+        signing,
+        verification,
+    })
+}
+
+// <kid>.pem and <kid>.pub.pem from different pairs would sign tokens nobody can verify
+fn check_key_pair(private_key: &SigningKey, public_key: &VerificationKey) -> Result<(), KeyError> {
+    let mismatch = || {
+        format!(
+            "private and public key of {} don't belong together",
+            private_key.kid.as_deref().unwrap_or_default()
+        )
+    };
+
+    if private_key.algorithm != public_key.algorithm {
+        return Err(mismatch().into());
+    }
+
+    let mut header = Header::new(private_key.algorithm);
+    header.kid = private_key.kid.clone();
+
+    let probe = encode(&header, &json!({ "probe": true }), &private_key.key)?;
+
+    let mut validation = Validation::new(public_key.algorithm);
+    validation.required_spec_claims = HashSet::new();
+    validation.validate_exp = false;
+    validation.validate_aud = false;
+
+    decode::<serde_json::Value>(&probe, &public_key.key, &validation).map_err(|_| mismatch())?;
+
+    Ok(())
+}
+
+fn load_private_key(kid: &str, path: &Path) -> Result<SigningKey, KeyError> {
+    let pem = fs::read(path)?;
+
+    let (algorithm, key) = match EncodingKey::from_ed_pem(&pem) {
+        Ok(key) => (Algorithm::EdDSA, key),
+        Err(_) => (Algorithm::RS256, EncodingKey::from_rsa_pem(&pem)?),
+    };
+
+    Ok(SigningKey {
+        kid: Some(kid.to_string()),
+        algorithm,
+        key,
+    })
+}
+
+fn load_public_key(kid: &str, path: &Path) -> Result<VerificationKey, KeyError> {
+    let pem = fs::read_to_string(path)?;
+
+    let (algorithm, parameters) = match ed25519_public_key(&pem) {
+        Some(x) => (
+            Algorithm::EdDSA,
+            AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                key_type: OctetKeyPairType::OctetKeyPair,
+                curve: EllipticCurve::Ed25519,
+                x: URL_SAFE_NO_PAD.encode(x),
+            }),
+        ),
+        None => {
+            let public_key = RsaPublicKey::from_public_key_pem(&pem)
+                .map_err(|e| format!("{} is neither Ed25519 nor RSA: {}", path.display(), e))?;
+
+            (
+                Algorithm::RS256,
+                AlgorithmParameters::RSA(RSAKeyParameters {
+                    key_type: RSAKeyType::RSA,
+                    n: URL_SAFE_NO_PAD.encode(public_key.n().to_bytes_be()),
+                    e: URL_SAFE_NO_PAD.encode(public_key.e().to_bytes_be()),
+                }),
+            )
+        }
+    };
+
+    let jwk = Jwk {
+        common: CommonParameters {
+            public_key_use: Some(PublicKeyUse::Signature),
+            key_algorithm: Some(match algorithm {
+                Algorithm::EdDSA => KeyAlgorithm::EdDSA,
+                _ => KeyAlgorithm::RS256,
+            }),
+            key_id: Some(kid.to_string()),
+            ..Default::default()
+        },
+        algorithm: parameters,
+    };
+
+    Ok(VerificationKey {
+        kid: Some(kid.to_string()),
+        algorithm,
+        key: DecodingKey::from_jwk(&jwk)?,
+        jwk: Some(jwk),
+    })
+}
+
+fn ed25519_public_key(pem: &str) -> Option<Vec<u8>> {
+    let body: String = pem
+        .lines()
+        .filter(|line| !line.starts_with("-----"))
+        .collect();
+
+    let der = STANDARD.decode(body.trim()).ok()?;
+
+    der.strip_prefix(&ED25519_SPKI_PREFIX)
+        .filter(|key| key.len() == 32)
+        .map(|key| key.to_vec())
+}
@@ -0,0 +1,27 @@
+use std::env;
+
+use chrono::Duration;
+
+pub struct MagicLinkConfig {
+    pub ttl: Duration,
+    // following the link proves access to the inbox just like the verification email does
+    pub verifies_email: bool,
+}
+
+impl MagicLinkConfig {
+    pub fn from_env() -> Self {
+        let ttl_minutes = env::var("MAGIC_LINK_TTL_MINUTES")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(15);
+
+        let verifies_email = env::var("MAGIC_LINK_VERIFIES_EMAIL")
+            .map(|value| value != "false")
+            .unwrap_or(true);
+
+        Self {
+            ttl: Duration::minutes(ttl_minutes),
+            verifies_email,
+        }
+    }
+}
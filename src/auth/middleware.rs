@@ -1,12 +1,25 @@
-use std::env;
+use std::marker::PhantomData;
 
 use axum::{
     extract::{FromRef, FromRequestParts},
     http::{HeaderMap, StatusCode, request::Parts},
 };
+use chrono::{Duration, Utc};
 use uuid::Uuid;
 
-use crate::{auth::jwt::validate_token, models::User, state::AppState};
+use crate::{
+    auth::{
+        impersonation::NoImpersonation,
+        jwt::{Claims, validate_token},
+        oauth_server::{RequiredScope, has_scope},
+        roles::{Permission, Role, UsersImpersonate},
+        tokens::PERSONAL_ACCESS_TOKEN_PREFIX,
+        verified_email::EMAIL_NOT_VERIFIED,
+    },
+    errors::ApiError,
+    models::User,
+    state::AppState,
+};
 
 // use for protected routes, requires valid JWT
 pub struct RequireAuth(pub User);
@@ -14,6 +27,31 @@ pub struct RequireAuth(pub User);
 // for optional auth, extracts user if present
 pub struct OptionalAuth(pub Option<User>);
 
+// like RequireAuth, for handlers that need to know which session the request came from
+pub struct RequireSession {
+    pub user: User,
+    pub session_id: Uuid,
+}
+
+// Like RequireSession, for changes a stolen access token alone must not be enough for. The
+// password has to have been entered within MAX_AGE_MINUTES, at login or through
+// POST /api/auth/reauthenticate, otherwise it's a 403 with reauthentication_required.
+pub struct RequireRecentAuth<const MAX_AGE_MINUTES: i64 = 10> {
+    pub user: User,
+    pub session_id: Uuid,
+}
+
+// Like RequireAuth, for anything that creates something. Whether an unverified email is enough
+// depends on UNVERIFIED_EMAIL_ACCESS, the rejection is a 403 with email_not_verified.
+pub struct RequireVerified(pub User);
+
+// For staff only routes. The role claim lets everyone else be turned away without a query, the
+// database has the final word so a revoked role stops working right away.
+pub struct RequireRole<R: Role>(pub User, pub PhantomData<R>);
+
+// like RequireRole, for anything one of the user's roles grants
+pub struct RequirePermission<P: Permission>(pub User, pub PhantomData<P>);
+
 impl<S> FromRequestParts<S> for RequireAuth
 where
     AppState: FromRef<S>,
@@ -28,23 +66,164 @@ where
         // important ? questionmark op unwraps the value and forwards error. forget it and you will have a result wrapped :)
         let token = extract_token_from_headers(headers).ok_or(StatusCode::UNAUTHORIZED)?;
 
-        let jwt_secret = env::var("JWT_SECRET").map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        // scripts and CI send a personal access token instead of a session's JWT
+        if token.starts_with(PERSONAL_ACCESS_TOKEN_PREFIX) {
+            let user = load_personal_access_token_user(&app_state, parts, &token).await?;
+            return Ok(RequireAuth(user));
+        }
 
-        let claims = validate_token(&token, &jwt_secret).map_err(|_| StatusCode::UNAUTHORIZED)?;
+        let claims =
+            validate_token(&token, &app_state.jwt_keys, &app_state.jwt_config).map_err(|e| {
+                eprintln!("Rejected access token: {}", e);
+                StatusCode::UNAUTHORIZED
+            })?;
 
-        let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::UNAUTHORIZED)?;
+        if !scope_allows(parts, claims.scope.as_deref()) || !impersonation_allows(parts, &claims) {
+            return Err(StatusCode::FORBIDDEN);
+        }
 
-        let user = app_state
-            .user_repository
-            .find_by_id(user_id)
-            .await
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        let user = load_session_user(&app_state, &claims)
+            .await?
             .ok_or(StatusCode::UNAUTHORIZED)?;
 
         Ok(RequireAuth(user))
     }
 }
 
+impl<S> FromRequestParts<S> for RequireSession
+where
+    AppState: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let app_state = AppState::from_ref(state);
+
+        let (user, claims) = authenticate_session(&app_state, parts).await?;
+
+        // load_session_user already parsed it successfully
+        let session_id = Uuid::parse_str(&claims.sid).map_err(|_| StatusCode::UNAUTHORIZED)?;
+
+        Ok(RequireSession { user, session_id })
+    }
+}
+
+impl<S, const MAX_AGE_MINUTES: i64> FromRequestParts<S> for RequireRecentAuth<MAX_AGE_MINUTES>
+where
+    AppState: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let app_state = AppState::from_ref(state);
+
+        let (user, claims) = authenticate_session(&app_state, parts).await?;
+
+        let session_id = Uuid::parse_str(&claims.sid).map_err(|_| StatusCode::UNAUTHORIZED)?;
+
+        // tokens from before auth_time existed count as stale
+        let cutoff = (Utc::now() - Duration::minutes(MAX_AGE_MINUTES)).timestamp();
+        let recent = claims
+            .auth_time
+            .is_some_and(|auth_time| auth_time as i64 >= cutoff);
+
+        if !recent {
+            return Err(ApiError::Forbidden {
+                error: "reauthentication_required",
+            });
+        }
+
+        Ok(RequireRecentAuth { user, session_id })
+    }
+}
+
+impl<S> FromRequestParts<S> for RequireVerified
+where
+    AppState: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let app_state = AppState::from_ref(state);
+
+        let RequireAuth(user) = RequireAuth::from_request_parts(parts, state).await?;
+
+        if !app_state.verified_email_policy.allows_writes(&user) {
+            return Err(ApiError::Forbidden {
+                error: EMAIL_NOT_VERIFIED,
+            });
+        }
+
+        Ok(RequireVerified(user))
+    }
+}
+
+impl<S, R> FromRequestParts<S> for RequireRole<R>
+where
+    AppState: FromRef<S>,
+    S: Send + Sync,
+    R: Role,
+{
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let app_state = AppState::from_ref(state);
+
+        let (user, claims) = authenticate_session(&app_state, parts).await?;
+
+        if !claims.roles.iter().any(|role| role == R::NAME) {
+            return Err(StatusCode::FORBIDDEN);
+        }
+
+        let has_role = app_state
+            .role_repository
+            .user_has_role(user.id, R::NAME)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+        if !has_role {
+            return Err(StatusCode::FORBIDDEN);
+        }
+
+        Ok(RequireRole(user, PhantomData))
+    }
+}
+
+impl<S, P> FromRequestParts<S> for RequirePermission<P>
+where
+    AppState: FromRef<S>,
+    S: Send + Sync,
+    P: Permission,
+{
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let app_state = AppState::from_ref(state);
+
+        let (user, claims) = authenticate_session(&app_state, parts).await?;
+
+        // no role, no permissions
+        if claims.roles.is_empty() {
+            return Err(StatusCode::FORBIDDEN);
+        }
+
+        let has_permission = app_state
+            .role_repository
+            .user_has_permission(user.id, P::NAME)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+        if !has_permission {
+            return Err(StatusCode::FORBIDDEN);
+        }
+
+        Ok(RequirePermission(user, PhantomData))
+    }
+}
+
 impl<S> FromRequestParts<S> for OptionalAuth
 where
     AppState: FromRef<S>,
@@ -62,33 +241,238 @@ where
             None => return Ok(OptionalAuth(None)),
         };
 
-        let jwt_secret = env::var("JWT_SECRET").map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        if token.starts_with(PERSONAL_ACCESS_TOKEN_PREFIX) {
+            return match load_personal_access_token_user(&app_state, parts, &token).await {
+                Ok(user) => Ok(OptionalAuth(Some(user))),
+                Err(StatusCode::INTERNAL_SERVER_ERROR) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+                Err(_) => Ok(OptionalAuth(None)),
+            };
+        }
 
-        let claims = match validate_token(&token, &jwt_secret) {
+        let claims = match validate_token(&token, &app_state.jwt_keys, &app_state.jwt_config) {
             Ok(claims) => claims,
-            Err(_) => return Ok(OptionalAuth(None)),
+            Err(e) => {
+                eprintln!("Ignoring invalid access token: {}", e);
+                return Ok(OptionalAuth(None));
+            }
         };
 
-        let user_id = match Uuid::parse_str(&claims.sub) {
-            Ok(id) => id,
-            Err(_) => return Ok(OptionalAuth(None)),
-        };
+        if !scope_allows(parts, claims.scope.as_deref()) || !impersonation_allows(parts, &claims) {
+            return Ok(OptionalAuth(None));
+        }
+
+        let user = load_session_user(&app_state, &claims).await?;
+
+        Ok(OptionalAuth(user))
+    }
+}
+
+// Our own sessions may do anything. Client and personal access tokens only get into routes
+// that declare a RequiredScope, and only with that scope granted.
+fn scope_allows(parts: &Parts, granted: Option<&str>) -> bool {
+    let Some(granted) = granted else {
+        return true;
+    };
+
+    parts
+        .extensions
+        .get::<RequiredScope>()
+        .is_some_and(|RequiredScope(required)| has_scope(granted, required))
+}
+
+fn impersonation_allows(parts: &Parts, claims: &Claims) -> bool {
+    claims.act.is_none() || parts.extensions.get::<NoImpersonation>().is_none()
+}
+
+// JWT of one of our own sessions, or a client token the route's scope allows
+async fn authenticate_session(
+    app_state: &AppState,
+    parts: &Parts,
+) -> Result<(User, Claims), StatusCode> {
+    let token = extract_token_from_headers(&parts.headers).ok_or(StatusCode::UNAUTHORIZED)?;
+
+    // there's no session behind a personal access token
+    if token.starts_with(PERSONAL_ACCESS_TOKEN_PREFIX) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let claims =
+        validate_token(&token, &app_state.jwt_keys, &app_state.jwt_config).map_err(|e| {
+            eprintln!("Rejected access token: {}", e);
+            StatusCode::UNAUTHORIZED
+        })?;
+
+    if !scope_allows(parts, claims.scope.as_deref()) || !impersonation_allows(parts, &claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let user = load_session_user(app_state, &claims)
+        .await?
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    Ok((user, claims))
+}
+
+async fn load_personal_access_token_user(
+    app_state: &AppState,
+    parts: &Parts,
+    token: &str,
+) -> Result<User, StatusCode> {
+    let access_token = app_state
+        .personal_access_token_repository
+        .find_by_token(token)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .filter(|access_token| !access_token.is_expired())
+        .ok_or(StatusCode::UNAUTHORIZED)?;
 
-        let user = app_state
-            .user_repository
-            .find_by_id(user_id)
+    if !scope_allows(parts, Some(&access_token.scopes.join(" "))) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let user = app_state
+        .user_repository
+        .find_by_id(access_token.user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .filter(|user| !user.is_suspended() && !user.is_deleted())
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    // minute precision is plenty, no need for a write on every request of a busy script
+    if access_token
+        .last_used_at
+        .is_none_or(|last_used_at| Utc::now() - last_used_at > Duration::minutes(1))
+    {
+        app_state
+            .personal_access_token_repository
+            .update_last_used(access_token.id)
             .await
             .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
 
-        Ok(OptionalAuth(user))
+    Ok(user)
+}
+
+// resolves the user behind validated claims, None if the token was revoked in the meantime
+pub(crate) async fn load_session_user(
+    app_state: &AppState,
+    claims: &Claims,
+) -> Result<Option<User>, StatusCode> {
+    let (Ok(user_id), Ok(session_id)) =
+        (Uuid::parse_str(&claims.sub), Uuid::parse_str(&claims.sid))
+    else {
+        return Ok(None);
+    };
+
+    let user = match app_state
+        .user_repository
+        .find_by_id(user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    {
+        Some(user) => user,
+        None => return Ok(None),
+    };
+
+    // password reset, logout-all or token reuse bumped the version
+    if claims.token_version != user.token_version {
+        return Ok(None);
+    }
+
+    // suspending and deleting bump the version as well, this covers tokens signed in the meantime
+    if user.is_suspended() || user.is_deleted() {
+        return Ok(None);
+    }
+
+    let active = match claims.act {
+        Some(_) => is_impersonation_active(app_state, session_id).await?,
+        None => is_session_active(app_state, session_id).await?,
+    };
+
+    if !active {
+        return Ok(None);
+    }
+
+    Ok(Some(user))
+}
+
+async fn is_session_active(app_state: &AppState, session_id: Uuid) -> Result<bool, StatusCode> {
+    if let Some(active) = app_state.session_cache.get(&session_id) {
+        return Ok(active);
     }
+
+    let active = app_state
+        .refresh_token_repository
+        .is_session_active(session_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    app_state.session_cache.insert(session_id, active);
+
+    Ok(active)
+}
+
+// ended by an admin or expired, there's no refresh token behind these. The admin acting has to
+// still be allowed to impersonate, a suspension or a lost role ends it as well
+async fn is_impersonation_active(
+    app_state: &AppState,
+    session_id: Uuid,
+) -> Result<bool, StatusCode> {
+    if let Some(active) = app_state.session_cache.get(&session_id) {
+        return Ok(active);
+    }
+
+    let session = app_state
+        .impersonation_repository
+        .find_by_id(session_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let active = match session {
+        Some(session) if session.is_active() => {
+            can_impersonate(app_state, session.admin_id).await?
+        }
+        _ => false,
+    };
+
+    app_state.session_cache.insert(session_id, active);
+
+    Ok(active)
 }
 
-fn extract_token_from_headers(headers: &HeaderMap) -> Option<String> {
-    headers
+async fn can_impersonate(app_state: &AppState, admin_id: Uuid) -> Result<bool, StatusCode> {
+    let admin = app_state
+        .user_repository
+        .find_by_id(admin_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let Some(admin) = admin else {
+        return Ok(false);
+    };
+
+    if admin.is_suspended() || admin.is_deleted() {
+        return Ok(false);
+    }
+
+    app_state
+        .role_repository
+        .user_has_permission(admin.id, UsersImpersonate::NAME)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+pub(crate) fn extract_token_from_headers(headers: &HeaderMap) -> Option<String> {
+    let (scheme, token) = headers
         .get("Authorization")?
         .to_str()
         .ok()?
-        .strip_prefix("Token ")
-        .map(|token| token.to_string())
+        .split_once(' ')?;
+
+    // "Token" is what the RealWorld spec uses, "Bearer" what every generic client sends
+    if scheme.eq_ignore_ascii_case("Bearer") || scheme.eq_ignore_ascii_case("Token") {
+        Some(token.trim().to_string())
+    } else {
+        None
+    }
 }
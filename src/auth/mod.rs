@@ -1,4 +1,20 @@
+pub mod account_deletion;
+pub mod audit;
+pub mod cookies;
+pub mod impersonation;
 pub mod jwt;
+pub mod keys;
+pub mod magic_link;
 pub mod middleware;
+pub mod new_device;
+pub mod oauth_server;
 pub mod password;
+pub mod password_policy;
+pub mod registration;
+pub mod revocation;
+pub mod roles;
+pub mod social_login;
+pub mod throttle;
 pub mod tokens;
+pub mod username_policy;
+pub mod verified_email;
@@ -0,0 +1,123 @@
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+
+use crate::{
+    auth::tokens::{generate_secure_token, hash_token},
+    models::User,
+    state::AppState,
+    utils::RequestMeta,
+};
+
+// as long as the session it can end stays refreshable
+pub const REPORT_TOKEN_TTL: Duration = Duration::days(7);
+
+// Same IP and same browser means same device. Coarse, a new network or a browser update
+// counts as new, but it never needs anything stored on the device itself.
+pub fn device_fingerprint(request: &RequestMeta) -> String {
+    hash_token(&format!(
+        "{}|{}",
+        request.ip_address,
+        request.user_agent.as_deref().unwrap_or_default()
+    ))
+}
+
+// "Firefox on Windows", good enough for someone to recognize their own device
+pub fn describe_device(user_agent: Option<&str>) -> String {
+    let Some(user_agent) = user_agent else {
+        return "Unknown device".to_string();
+    };
+
+    // order matters, Edge and Opera claim to be Chrome and Chrome claims to be Safari
+    let browser = [
+        ("Edg/", "Edge"),
+        ("OPR/", "Opera"),
+        ("Firefox/", "Firefox"),
+        ("Chrome/", "Chrome"),
+        ("Safari/", "Safari"),
+        ("curl/", "curl"),
+    ]
+    .iter()
+    .find(|(token, _)| user_agent.contains(token))
+    .map(|(_, name)| *name);
+
+    let os = [
+        ("Android", "Android"),
+        ("iPhone", "iOS"),
+        ("iPad", "iPadOS"),
+        ("Windows", "Windows"),
+        ("Mac OS X", "macOS"),
+        ("CrOS", "ChromeOS"),
+        ("Linux", "Linux"),
+    ]
+    .iter()
+    .find(|(token, _)| user_agent.contains(token))
+    .map(|(_, name)| *name);
+
+    match (browser, os) {
+        (Some(browser), Some(os)) => format!("{} on {}", browser, os),
+        (Some(browser), None) => browser.to_string(),
+        (None, Some(os)) => format!("Unknown browser on {}", os),
+        (None, None) => "Unknown device".to_string(),
+    }
+}
+
+// Emails the user when a sign-in comes from a device we haven't seen for them. The very first
+// device of an account is just remembered, nobody needs a mail about their own registration.
+pub async fn check_new_device(
+    state: &AppState,
+    user: &User,
+    session_id: Uuid,
+    request: &RequestMeta,
+) -> Result<(), sqlx::Error> {
+    let has_devices = state.known_device_repository.has_devices(user.id).await?;
+
+    let is_new = state
+        .known_device_repository
+        .remember(
+            user.id,
+            &device_fingerprint(request),
+            &request.ip_address.to_string(),
+            request.user_agent.as_deref(),
+        )
+        .await?;
+
+    if !is_new || !has_devices {
+        return Ok(());
+    }
+
+    let report_token = generate_secure_token();
+    let signed_in_at = Utc::now();
+
+    state
+        .known_device_repository
+        .create_report_token(
+            user.id,
+            session_id,
+            &report_token,
+            signed_in_at + REPORT_TOKEN_TTL,
+        )
+        .await?;
+
+    let email_service = state.email_service.clone();
+    let (email, username) = (user.email.clone(), user.username.clone());
+    let device = describe_device(request.user_agent.as_deref());
+    let ip_address = request.ip_address;
+
+    tokio::spawn(async move {
+        if let Err(e) = email_service
+            .send_new_sign_in_email(
+                &email,
+                &username,
+                signed_in_at,
+                &device,
+                ip_address,
+                &report_token,
+            )
+            .await
+        {
+            eprintln!("Failed to send new sign-in email: {}", e);
+        }
+    });
+
+    Ok(())
+}
@@ -1,7 +1,14 @@
+use std::sync::LazyLock;
+
 use bcrypt::{DEFAULT_COST, hash, verify};
 
 // TODO: what about argon instead of bcrypt
 
+// verified against when the account doesn't exist, so both cases cost the same bcrypt work
+static DUMMY_HASH: LazyLock<String> = LazyLock::new(|| {
+    hash_password("not-a-real-password").expect("failed to create dummy password hash")
+});
+
 pub fn hash_password(password: &str) -> Result<String, bcrypt::BcryptError> {
     // cost factor 12 is a good balance between performance and security
 
@@ -11,3 +18,7 @@ pub fn hash_password(password: &str) -> Result<String, bcrypt::BcryptError> {
 pub fn verify_password(password: &str, hash: &str) -> Result<bool, bcrypt::BcryptError> {
     verify(password, hash)
 }
+
+pub fn dummy_verify_password(password: &str) {
+    let _ = verify(password, &DUMMY_HASH);
+}
@@ -0,0 +1,358 @@
+use std::{
+    cmp::Ordering,
+    env,
+    fs::File,
+    io::{self, BufRead, BufReader, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use sha1::{Digest, Sha1};
+
+// bcrypt ignores everything after this, so longer passwords only look stronger than they are
+const BCRYPT_MAX_BYTES: usize = 72;
+
+// checked after lowercasing, undoing leetspeak and dropping trailing digits/symbols,
+// so "P@ssw0rd123!" is caught as well
+#[rustfmt::skip]
+const COMMON_PASSWORDS: &[&str] = &[
+    "password", "passwort", "qwerty", "qwertz", "azerty", "letmein", "welcome", "admin",
+    "administrator", "login", "iloveyou", "monkey", "dragon", "master", "sunshine", "princess",
+    "football", "baseball", "soccer", "hockey", "superman", "batman", "trustno", "shadow",
+    "michael", "jennifer", "hunter", "secret", "changeme", "default", "starwars", "whatever",
+    "freedom", "computer", "internet", "abc", "abcdef", "qwertyuiop", "asdfgh", "asdfghjkl",
+    "zxcvbn", "zxcvbnm", "passw", "pass", "test", "guest", "root", "user", "hello", "love",
+    "summer", "winter", "spring", "autumn", "flower", "cookie", "cheese", "pokemon", "naruto",
+    "ninja", "mustang", "killer", "charlie", "jordan", "ashley", "daniel", "thomas", "access",
+];
+
+// keyboard rows, runs along them are as guessable as "abc"
+const KEYBOARD_ROWS: &[&str] = &["qwertyuiop", "asdfghjkl", "zxcvbnm", "1234567890"];
+
+// Rules every new password has to pass. Applied on registration, reset and change, so the
+// same limits hold no matter how a password gets set.
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub max_length: usize,
+    // 0 (guessable in seconds) to 4 (out of reach for offline attacks)
+    pub min_strength: u8,
+    pub breached_passwords: Option<BreachedPasswords>,
+}
+
+pub struct PasswordStrength {
+    pub score: u8,
+    pub feedback: Vec<String>,
+}
+
+impl PasswordPolicy {
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        let min_length = env_or("PASSWORD_MIN_LENGTH", 8);
+        let max_length = env_or("PASSWORD_MAX_LENGTH", 64);
+        let min_strength = env_or("PASSWORD_MIN_STRENGTH", 2).min(4);
+
+        let breached_passwords = match env::var("PASSWORD_BREACHED_FILE") {
+            Ok(path) => Some(BreachedPasswords::open(
+                PathBuf::from(path),
+                env_or("PASSWORD_BREACHED_MIN_COUNT", 1),
+            )?),
+            Err(_) => None,
+        };
+
+        Ok(Self {
+            min_length,
+            max_length,
+            min_strength,
+            breached_passwords,
+        })
+    }
+
+    // every rule the password breaks, empty if it's fine
+    pub async fn check(&self, password: &str, username: &str, email: &str) -> Vec<String> {
+        let mut problems = Vec::new();
+        let length = password.chars().count();
+
+        if length < self.min_length {
+            problems.push(format!(
+                "Password must be at least {} characters",
+                self.min_length
+            ));
+        }
+
+        if length > self.max_length || password.len() > BCRYPT_MAX_BYTES {
+            problems.push(format!(
+                "Password must be at most {} characters",
+                self.max_length
+            ));
+        }
+
+        let lowercase = password.to_lowercase();
+        let email_name = email.split('@').next().unwrap_or_default();
+
+        if contains_identity(&lowercase, username) {
+            problems.push("Password must not contain your username".to_string());
+        }
+
+        if contains_identity(&lowercase, email_name) {
+            problems.push("Password must not contain your email address".to_string());
+        }
+
+        let strength = estimate_strength(password);
+        if strength.score < self.min_strength {
+            problems.push("Password is too weak".to_string());
+            problems.extend(strength.feedback);
+        }
+
+        if let Some(breached_passwords) = &self.breached_passwords {
+            match breached_passwords.is_breached(password).await {
+                Ok(true) => problems.push(
+                    "This password has appeared in a data breach, please choose another one"
+                        .to_string(),
+                ),
+                Ok(false) => {}
+                // a broken lookup shouldn't keep people from signing up
+                Err(e) => eprintln!("Breached password lookup failed: {}", e),
+            }
+        }
+
+        problems
+    }
+}
+
+fn contains_identity(lowercase_password: &str, identity: &str) -> bool {
+    // very short names would match half of all passwords
+    identity.chars().count() >= 3 && lowercase_password.contains(&identity.to_lowercase())
+}
+
+// Rough entropy estimate: character pool size per character, with repeats, sequences,
+// keyboard runs and common passwords counting for next to nothing.
+pub fn estimate_strength(password: &str) -> PasswordStrength {
+    let mut feedback = Vec::new();
+
+    if is_common_password(password) {
+        return PasswordStrength {
+            score: 0,
+            feedback: vec![
+                "This is a very common password".to_string(),
+                "Avoid common words, even with numbers or symbols swapped in".to_string(),
+            ],
+        };
+    }
+
+    let chars: Vec<char> = password.chars().collect();
+
+    let has_lower = chars.iter().any(|c| c.is_ascii_lowercase());
+    let has_upper = chars.iter().any(|c| c.is_ascii_uppercase());
+    let has_digit = chars.iter().any(|c| c.is_ascii_digit());
+    let has_symbol = chars
+        .iter()
+        .any(|c| c.is_ascii() && !c.is_ascii_alphanumeric());
+    let has_other = chars.iter().any(|c| !c.is_ascii());
+
+    let pool = [
+        (has_lower, 26),
+        (has_upper, 26),
+        (has_digit, 10),
+        (has_symbol, 33),
+        (has_other, 100),
+    ]
+    .iter()
+    .filter(|(present, _)| *present)
+    .map(|(_, size)| size)
+    .sum::<u32>()
+    .max(1);
+
+    let bits_per_char = (pool as f64).log2();
+    let mut bits = 0.0;
+    let mut repeated = false;
+    let mut sequential = false;
+
+    // pairs like "ll" or "er" are everywhere in normal words, only runs of three count
+    for (i, c) in chars.iter().enumerate() {
+        let run = (i >= 2).then(|| (chars[i - 2], chars[i - 1]));
+
+        if run.is_some_and(|(pp, p)| pp == p && p == *c) {
+            repeated = true;
+            bits += 1.0;
+        } else if run.is_some_and(|(pp, p)| follows(pp, p) && follows(p, *c)) {
+            sequential = true;
+            bits += 1.0;
+        } else {
+            bits += bits_per_char;
+        }
+    }
+
+    if repeated {
+        feedback.push("Avoid repeated characters like \"aaa\"".to_string());
+    }
+
+    if sequential {
+        feedback.push("Avoid sequences like \"abc\", \"123\" or \"qwerty\"".to_string());
+    }
+
+    let score = match bits {
+        b if b < 28.0 => 0,
+        b if b < 36.0 => 1,
+        b if b < 60.0 => 2,
+        b if b < 80.0 => 3,
+        _ => 4,
+    };
+
+    if score < 3 {
+        feedback.push("Add another word or two, longer passwords are stronger".to_string());
+
+        if [has_lower, has_upper, has_digit, has_symbol]
+            .iter()
+            .filter(|x| **x)
+            .count()
+            < 3
+        {
+            feedback.push("Mix upper and lower case letters, digits and symbols".to_string());
+        }
+    }
+
+    PasswordStrength { score, feedback }
+}
+
+fn follows(previous: char, current: char) -> bool {
+    let (p, c) = (previous.to_ascii_lowercase(), current.to_ascii_lowercase());
+
+    if p.is_ascii_alphanumeric() && c.is_ascii_alphanumeric() {
+        let distance = c as i32 - p as i32;
+        if distance == 1 || distance == -1 {
+            return true;
+        }
+    }
+
+    let pair = format!("{}{}", p, c);
+    let reversed = format!("{}{}", c, p);
+
+    KEYBOARD_ROWS
+        .iter()
+        .any(|row| row.contains(&pair) || row.contains(&reversed))
+}
+
+fn is_common_password(password: &str) -> bool {
+    // "123456", "11111111" and friends
+    if password.chars().all(|c| c.is_ascii_digit()) {
+        return true;
+    }
+
+    let lowercase = password.to_lowercase();
+    let without_suffix = undo_leetspeak(trim_suffix(&lowercase));
+    let leet_without_suffix = undo_leetspeak(&lowercase);
+
+    [without_suffix.as_str(), trim_suffix(&leet_without_suffix)]
+        .iter()
+        .any(|candidate| COMMON_PASSWORDS.contains(candidate))
+}
+
+fn trim_suffix(password: &str) -> &str {
+    password.trim_end_matches(|c: char| !c.is_alphabetic())
+}
+
+fn undo_leetspeak(password: &str) -> String {
+    password
+        .chars()
+        .map(|c| match c {
+            '0' => 'o',
+            '1' | '!' => 'i',
+            '3' => 'e',
+            '4' | '@' => 'a',
+            '5' | '$' => 's',
+            '7' => 't',
+            c => c,
+        })
+        .collect()
+}
+
+// Offline breached password lookup against the Have I Been Pwned SHA-1 dump: one
+// "<SHA-1 in upper case hex>:<count>" line per password, ordered by hash. The file is
+// binary searched on disk, it's far too big to load.
+pub struct BreachedPasswords {
+    path: Arc<PathBuf>,
+    // passwords seen fewer times than this are let through
+    min_count: u64,
+}
+
+impl BreachedPasswords {
+    pub fn open(path: PathBuf, min_count: u64) -> io::Result<Self> {
+        // fail at startup instead of on the first registration
+        File::open(&path)?;
+
+        Ok(Self {
+            path: Arc::new(path),
+            min_count: min_count.max(1),
+        })
+    }
+
+    pub async fn is_breached(&self, password: &str) -> io::Result<bool> {
+        let hash = hex::encode_upper(Sha1::digest(password.as_bytes()));
+        let path = Arc::clone(&self.path);
+
+        // every step of the search is a disk seek, keep them off the async workers
+        let count = tokio::task::spawn_blocking(move || find(&path, &hash))
+            .await
+            .map_err(io::Error::other)??;
+
+        Ok(count.is_some_and(|count| count >= self.min_count))
+    }
+}
+
+fn find(path: &Path, hash: &str) -> io::Result<Option<u64>> {
+    let file = File::open(path)?;
+    let mut low = 0;
+    let mut high = file.metadata()?.len();
+    let mut reader = BufReader::new(file);
+
+    // [low, high) holds the start offsets of all lines that could still match
+    while low < high {
+        let mid = low + (high - low) / 2;
+
+        let Some((line_start, line)) = line_starting_from(&mut reader, mid)? else {
+            high = mid;
+            continue;
+        };
+
+        let entry = line.trim_end();
+        let (entry_hash, count) = entry.split_once(':').unwrap_or((entry, "1"));
+
+        match hash.cmp(entry_hash.to_ascii_uppercase().as_str()) {
+            Ordering::Equal => return Ok(Some(count.trim().parse().unwrap_or(1))),
+            Ordering::Less => high = mid,
+            Ordering::Greater => low = line_start + line.len() as u64,
+        }
+    }
+
+    Ok(None)
+}
+
+// the first full line starting at or after offset
+fn line_starting_from(
+    reader: &mut BufReader<File>,
+    offset: u64,
+) -> io::Result<Option<(u64, String)>> {
+    let mut line_start = offset;
+
+    if offset > 0 {
+        // skip the rest of the line offset points into, unless offset is already a line start
+        reader.seek(SeekFrom::Start(offset - 1))?;
+        let mut partial = Vec::new();
+        line_start = offset - 1 + reader.read_until(b'\n', &mut partial)? as u64;
+    } else {
+        reader.seek(SeekFrom::Start(0))?;
+    }
+
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+
+    Ok(Some((line_start, line)))
+}
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
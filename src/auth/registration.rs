@@ -0,0 +1,83 @@
+use std::env;
+
+use chrono::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegistrationMode {
+    Open,
+    // an invite code from POST /api/user/invites is required
+    Invite,
+    // only emails at REGISTRATION_ALLOWED_DOMAINS
+    Domain,
+}
+
+// how POST /api/users behaves
+pub struct RegistrationConfig {
+    // Registering a taken email answers exactly like a fresh registration and the owner gets a
+    // "you already have an account" email instead. New accounts then have to verify and log in,
+    // since handing out tokens would give the difference away.
+    pub enumeration_protection: bool,
+    pub mode: RegistrationMode,
+    // lowercase, without the @
+    pub allowed_domains: Vec<String>,
+    // how many people someone without invites:manage may invite, users.invite_quota overrides it
+    pub default_invite_quota: i32,
+    pub invite_ttl: Duration,
+}
+
+impl RegistrationConfig {
+    pub fn from_env() -> Self {
+        let enumeration_protection = env::var("REGISTRATION_ENUMERATION_PROTECTION")
+            .map(|value| value == "true")
+            .unwrap_or(false);
+
+        let mode = match env::var("REGISTRATION_MODE").as_deref() {
+            Ok("invite") => RegistrationMode::Invite,
+            Ok("domain") => RegistrationMode::Domain,
+            _ => RegistrationMode::Open,
+        };
+
+        let allowed_domains: Vec<String> = env::var("REGISTRATION_ALLOWED_DOMAINS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|domain| domain.trim().trim_start_matches('@').to_lowercase())
+            .filter(|domain| !domain.is_empty())
+            .collect();
+
+        if mode == RegistrationMode::Domain && allowed_domains.is_empty() {
+            eprintln!(
+                "REGISTRATION_MODE=domain without REGISTRATION_ALLOWED_DOMAINS, nobody can sign up"
+            );
+        }
+
+        let default_invite_quota = env::var("INVITE_DEFAULT_QUOTA")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(0);
+
+        let invite_ttl_days = env::var("INVITE_TTL_DAYS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(7);
+
+        Self {
+            enumeration_protection,
+            mode,
+            allowed_domains,
+            default_invite_quota,
+            invite_ttl: Duration::days(invite_ttl_days),
+        }
+    }
+
+    pub fn allows_email(&self, email: &str) -> bool {
+        if self.mode != RegistrationMode::Domain {
+            return true;
+        }
+
+        let Some((_, domain)) = email.rsplit_once('@') else {
+            return false;
+        };
+
+        self.allowed_domains.contains(&domain.to_lowercase())
+    }
+}
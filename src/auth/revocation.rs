@@ -0,0 +1,52 @@
+use std::{
+    collections::HashMap,
+    sync::RwLock,
+    time::{Duration, Instant},
+};
+
+use uuid::Uuid;
+
+// past this size expired entries get swept on insert
+const PRUNE_THRESHOLD: usize = 10_000;
+
+// Short lived in-memory view of which sessions are still alive, so RequireAuth doesn't have to hit
+// refresh_tokens on every request. Revocations done by this instance apply immediately, the ones
+// done by other instances show up once the cached entry expires.
+pub struct SessionRevocationCache {
+    ttl: Duration,
+    entries: RwLock<HashMap<Uuid, (bool, Instant)>>,
+}
+
+impl SessionRevocationCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: RwLock::new(HashMap::new()),
+        }
+    }
+
+    // None means unknown or stale, the caller has to ask the database
+    pub fn get(&self, session_id: &Uuid) -> Option<bool> {
+        let entries = self.entries.read().ok()?;
+
+        entries
+            .get(session_id)
+            .filter(|(_, cached_at)| cached_at.elapsed() < self.ttl)
+            .map(|(active, _)| *active)
+    }
+
+    pub fn insert(&self, session_id: Uuid, active: bool) {
+        if let Ok(mut entries) = self.entries.write() {
+            if entries.len() >= PRUNE_THRESHOLD {
+                let ttl = self.ttl;
+                entries.retain(|_, (_, cached_at)| cached_at.elapsed() < ttl);
+            }
+
+            entries.insert(session_id, (active, Instant::now()));
+        }
+    }
+
+    pub fn revoke(&self, session_id: Uuid) {
+        self.insert(session_id, false);
+    }
+}
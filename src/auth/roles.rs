@@ -0,0 +1,59 @@
+// Roles and permissions the code checks for, as types so a typo is a compile error:
+// RequireRole<Admin>, RequirePermission<UsersWrite>. The names match the roles and
+// role_permissions tables.
+
+pub trait Role: Send + Sync {
+    const NAME: &'static str;
+}
+
+pub trait Permission: Send + Sync {
+    const NAME: &'static str;
+}
+
+pub struct Admin;
+
+impl Role for Admin {
+    const NAME: &'static str = "admin";
+}
+
+pub struct Moderator;
+
+impl Role for Moderator {
+    const NAME: &'static str = "moderator";
+}
+
+pub struct UsersRead;
+
+impl Permission for UsersRead {
+    const NAME: &'static str = "users:read";
+}
+
+pub struct UsersWrite;
+
+impl Permission for UsersWrite {
+    const NAME: &'static str = "users:write";
+}
+
+pub struct UsersImpersonate;
+
+impl Permission for UsersImpersonate {
+    const NAME: &'static str = "users:impersonate";
+}
+
+pub struct InvitesManage;
+
+impl Permission for InvitesManage {
+    const NAME: &'static str = "invites:manage";
+}
+
+pub struct RolesManage;
+
+impl Permission for RolesManage {
+    const NAME: &'static str = "roles:manage";
+}
+
+pub struct AuditRead;
+
+impl Permission for AuditRead {
+    const NAME: &'static str = "audit:read";
+}
@@ -0,0 +1,532 @@
+use std::{
+    collections::HashMap,
+    env, fmt,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation, decode, decode_header, jwk::JwkSet};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tokio::sync::OnceCell;
+use url::Url;
+
+// what a provider told us about the person signing in
+#[derive(Debug, Clone)]
+pub struct ExternalIdentity {
+    pub subject: String,
+    pub email: Option<String>,
+    pub email_verified: bool,
+    pub preferred_username: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum SocialLoginError {
+    // the provider is unreachable or answered with something unusable
+    Provider(String),
+    // the code exchange or the ID token didn't check out
+    Rejected(String),
+}
+
+impl fmt::Display for SocialLoginError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SocialLoginError::Provider(reason) => write!(f, "provider error: {}", reason),
+            SocialLoginError::Rejected(reason) => write!(f, "rejected: {}", reason),
+        }
+    }
+}
+
+impl From<reqwest::Error> for SocialLoginError {
+    fn from(e: reqwest::Error) -> Self {
+        SocialLoginError::Provider(e.to_string())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProviderKind {
+    // discovery, ID tokens and nonces
+    Oidc,
+    // plain OAuth2, the identity comes from the REST API
+    Github,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+    userinfo_endpoint: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    id_token: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct IdTokenClaims {
+    sub: String,
+    nonce: Option<String>,
+    email: Option<String>,
+    // some providers send "true" as a string
+    email_verified: Option<serde_json::Value>,
+    preferred_username: Option<String>,
+    name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GithubUser {
+    id: u64,
+    login: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GithubEmail {
+    email: String,
+    primary: bool,
+    verified: bool,
+}
+
+pub struct OAuthProvider {
+    pub name: String,
+    pub kind: ProviderKind,
+    client_id: String,
+    client_secret: String,
+    issuer: Option<String>,
+    scopes: String,
+    // fetched from the issuer on first use, so a provider being down doesn't block startup
+    metadata: OnceCell<ProviderMetadata>,
+    jwks: RwLock<Option<JwkSet>>,
+}
+
+// Sign in with external providers, configured through
+// OAUTH_PROVIDERS=google,github and OAUTH_<NAME>_CLIENT_ID / _CLIENT_SECRET / _ISSUER.
+// Any OpenID Connect provider works through discovery, github is built in since it only
+// speaks plain OAuth2.
+pub struct SocialLoginConfig {
+    providers: HashMap<String, Arc<OAuthProvider>>,
+    redirect_base_url: String,
+    http: reqwest::Client,
+}
+
+impl SocialLoginConfig {
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        let providers = env::var("OAUTH_PROVIDERS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|name| name.trim().to_lowercase())
+            .filter(|name| !name.is_empty())
+            .map(|name| OAuthProvider::from_env(&name))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let redirect_base_url = env::var("OAUTH_REDIRECT_BASE_URL")
+            .or_else(|_| env::var("BASE_URL"))
+            .unwrap_or_else(|_| "http://localhost:3000".to_string());
+
+        Self::new(providers, &redirect_base_url)
+    }
+
+    pub fn new(
+        providers: Vec<OAuthProvider>,
+        redirect_base_url: &str,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .user_agent("rw-axum-api")
+            .build()?;
+
+        Ok(Self {
+            providers: providers
+                .into_iter()
+                .map(|provider| (provider.name.clone(), Arc::new(provider)))
+                .collect(),
+            redirect_base_url: redirect_base_url.trim_end_matches('/').to_string(),
+            http,
+        })
+    }
+
+    pub fn provider(&self, name: &str) -> Option<Arc<OAuthProvider>> {
+        self.providers.get(name).cloned()
+    }
+
+    pub fn redirect_uri(&self, provider: &OAuthProvider) -> String {
+        format!(
+            "{}/api/auth/oauth/{}/callback",
+            self.redirect_base_url, provider.name
+        )
+    }
+
+    pub async fn authorization_url(
+        &self,
+        provider: &OAuthProvider,
+        state: &str,
+        code_verifier: &str,
+        nonce: &str,
+    ) -> Result<String, SocialLoginError> {
+        let endpoint = match provider.kind {
+            ProviderKind::Oidc => self
+                .metadata(provider)
+                .await?
+                .authorization_endpoint
+                .clone(),
+            ProviderKind::Github => "https://github.com/login/oauth/authorize".to_string(),
+        };
+
+        let redirect_uri = self.redirect_uri(provider);
+        let code_challenge = pkce_challenge(code_verifier);
+
+        let mut params = vec![
+            ("response_type", "code"),
+            ("client_id", provider.client_id.as_str()),
+            ("redirect_uri", redirect_uri.as_str()),
+            ("scope", provider.scopes.as_str()),
+            ("state", state),
+            ("code_challenge", code_challenge.as_str()),
+            ("code_challenge_method", "S256"),
+        ];
+
+        if provider.kind == ProviderKind::Oidc {
+            params.push(("nonce", nonce));
+        }
+
+        let url = Url::parse_with_params(&endpoint, &params)
+            .map_err(|e| SocialLoginError::Provider(e.to_string()))?;
+
+        Ok(url.into())
+    }
+
+    // trades the code from the callback for the identity behind it
+    pub async fn exchange_code(
+        &self,
+        provider: &OAuthProvider,
+        code: &str,
+        code_verifier: &str,
+        nonce: &str,
+    ) -> Result<ExternalIdentity, SocialLoginError> {
+        let token_endpoint = match provider.kind {
+            ProviderKind::Oidc => self.metadata(provider).await?.token_endpoint.clone(),
+            ProviderKind::Github => "https://github.com/login/oauth/access_token".to_string(),
+        };
+
+        let redirect_uri = self.redirect_uri(provider);
+
+        let response = self
+            .http
+            .post(&token_endpoint)
+            .header("Accept", "application/json")
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", redirect_uri.as_str()),
+                ("client_id", provider.client_id.as_str()),
+                ("client_secret", provider.client_secret.as_str()),
+                ("code_verifier", code_verifier),
+            ])
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(SocialLoginError::Rejected(format!(
+                "token endpoint answered {}",
+                response.status()
+            )));
+        }
+
+        let tokens: TokenResponse = response
+            .json()
+            .await
+            .map_err(|e| SocialLoginError::Rejected(format!("unexpected token response: {}", e)))?;
+
+        match provider.kind {
+            ProviderKind::Oidc => self.oidc_identity(provider, &tokens, nonce).await,
+            ProviderKind::Github => self.github_identity(&tokens.access_token).await,
+        }
+    }
+
+    async fn oidc_identity(
+        &self,
+        provider: &OAuthProvider,
+        tokens: &TokenResponse,
+        nonce: &str,
+    ) -> Result<ExternalIdentity, SocialLoginError> {
+        let id_token = tokens
+            .id_token
+            .as_deref()
+            .ok_or_else(|| SocialLoginError::Rejected("no ID token".to_string()))?;
+
+        let claims = self.validate_id_token(provider, id_token).await?;
+
+        // the nonce ties the ID token to the authorization request we started
+        if claims.nonce.as_deref() != Some(nonce) {
+            return Err(SocialLoginError::Rejected("nonce mismatch".to_string()));
+        }
+
+        let mut identity = ExternalIdentity {
+            subject: claims.sub,
+            email: claims.email,
+            email_verified: is_true(claims.email_verified.as_ref()),
+            preferred_username: claims.preferred_username.or(claims.name),
+        };
+
+        // not every provider puts the email into the ID token
+        if identity.email.is_none()
+            && let Some(userinfo_endpoint) = &self.metadata(provider).await?.userinfo_endpoint
+        {
+            let userinfo: IdTokenClaims = self
+                .http
+                .get(userinfo_endpoint)
+                .bearer_auth(&tokens.access_token)
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+
+            if userinfo.sub == identity.subject {
+                identity.email = userinfo.email;
+                identity.email_verified = is_true(userinfo.email_verified.as_ref());
+            }
+        }
+
+        Ok(identity)
+    }
+
+    async fn validate_id_token(
+        &self,
+        provider: &OAuthProvider,
+        id_token: &str,
+    ) -> Result<IdTokenClaims, SocialLoginError> {
+        let metadata = self.metadata(provider).await?;
+
+        let header = decode_header(id_token)
+            .map_err(|e| SocialLoginError::Rejected(format!("malformed ID token: {}", e)))?;
+
+        // the alg comes from the token, never let it pick a shared secret scheme
+        if matches!(
+            header.alg,
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+        ) {
+            return Err(SocialLoginError::Rejected(format!(
+                "unsupported ID token algorithm {:?}",
+                header.alg
+            )));
+        }
+
+        let key = match self.find_key(provider, header.kid.as_deref())? {
+            Some(key) => key,
+            // unknown kid, the provider probably rotated its keys
+            None => {
+                self.refresh_jwks(provider, &metadata.jwks_uri).await?;
+                self.find_key(provider, header.kid.as_deref())?
+                    .ok_or_else(|| SocialLoginError::Rejected("unknown ID token key".to_string()))?
+            }
+        };
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&metadata.issuer]);
+        validation.set_audience(&[&provider.client_id]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+        validation.leeway = 60;
+
+        let data = decode::<IdTokenClaims>(id_token, &key, &validation)
+            .map_err(|e| SocialLoginError::Rejected(format!("invalid ID token: {}", e)))?;
+
+        Ok(data.claims)
+    }
+
+    fn find_key(
+        &self,
+        provider: &OAuthProvider,
+        kid: Option<&str>,
+    ) -> Result<Option<DecodingKey>, SocialLoginError> {
+        let jwks = provider
+            .jwks
+            .read()
+            .map_err(|_| SocialLoginError::Provider("JWKS lock poisoned".to_string()))?;
+
+        let Some(jwks) = jwks.as_ref() else {
+            return Ok(None);
+        };
+
+        let jwk = match kid {
+            Some(kid) => jwks.find(kid),
+            // without a kid only an unambiguous key set works
+            None if jwks.keys.len() == 1 => jwks.keys.first(),
+            None => None,
+        };
+
+        jwk.map(DecodingKey::from_jwk)
+            .transpose()
+            .map_err(|e| SocialLoginError::Provider(format!("unusable JWK: {}", e)))
+    }
+
+    async fn refresh_jwks(
+        &self,
+        provider: &OAuthProvider,
+        jwks_uri: &str,
+    ) -> Result<(), SocialLoginError> {
+        let jwks: JwkSet = self
+            .http
+            .get(jwks_uri)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        *provider
+            .jwks
+            .write()
+            .map_err(|_| SocialLoginError::Provider("JWKS lock poisoned".to_string()))? =
+            Some(jwks);
+
+        Ok(())
+    }
+
+    async fn metadata<'a>(
+        &self,
+        provider: &'a OAuthProvider,
+    ) -> Result<&'a ProviderMetadata, SocialLoginError> {
+        let issuer = provider
+            .issuer
+            .as_deref()
+            .ok_or_else(|| SocialLoginError::Provider("no issuer configured".to_string()))?;
+
+        provider
+            .metadata
+            .get_or_try_init(|| async {
+                let discovery_url = format!(
+                    "{}/.well-known/openid-configuration",
+                    issuer.trim_end_matches('/')
+                );
+
+                let metadata: ProviderMetadata = self
+                    .http
+                    .get(&discovery_url)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await?;
+
+                // a document for some other issuer would make every ID token check meaningless
+                if metadata.issuer.trim_end_matches('/') != issuer.trim_end_matches('/') {
+                    return Err(SocialLoginError::Provider(format!(
+                        "discovery document is for issuer {}",
+                        metadata.issuer
+                    )));
+                }
+
+                Ok(metadata)
+            })
+            .await
+    }
+
+    async fn github_identity(
+        &self,
+        access_token: &str,
+    ) -> Result<ExternalIdentity, SocialLoginError> {
+        let user: GithubUser = self
+            .http
+            .get("https://api.github.com/user")
+            .bearer_auth(access_token)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        // the profile email is whatever the user made public, the list says which are verified
+        let emails: Vec<GithubEmail> = self
+            .http
+            .get("https://api.github.com/user/emails")
+            .bearer_auth(access_token)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let primary = emails.into_iter().find(|email| email.primary);
+
+        Ok(ExternalIdentity {
+            subject: user.id.to_string(),
+            email_verified: primary.as_ref().is_some_and(|email| email.verified),
+            email: primary.map(|email| email.email),
+            preferred_username: Some(user.login),
+        })
+    }
+}
+
+impl OAuthProvider {
+    // any OpenID Connect provider, everything else comes from its discovery document
+    pub fn oidc(name: &str, client_id: &str, client_secret: &str, issuer: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            kind: ProviderKind::Oidc,
+            client_id: client_id.to_string(),
+            client_secret: client_secret.to_string(),
+            issuer: Some(issuer.to_string()),
+            scopes: "openid email profile".to_string(),
+            metadata: OnceCell::new(),
+            jwks: RwLock::new(None),
+        }
+    }
+
+    fn from_env(name: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let var = |suffix: &str| env::var(format!("OAUTH_{}_{}", name.to_uppercase(), suffix));
+
+        let kind = match var("KIND").as_deref() {
+            Ok("github") => ProviderKind::Github,
+            Ok("oidc") => ProviderKind::Oidc,
+            Ok(other) => return Err(format!("unknown provider kind {} for {}", other, name).into()),
+            Err(_) if name == "github" => ProviderKind::Github,
+            Err(_) => ProviderKind::Oidc,
+        };
+
+        let issuer = match (kind, var("ISSUER")) {
+            (_, Ok(issuer)) => Some(issuer),
+            (ProviderKind::Oidc, Err(_)) if name == "google" => {
+                Some("https://accounts.google.com".to_string())
+            }
+            (ProviderKind::Oidc, Err(_)) => {
+                return Err(format!("OAUTH_{}_ISSUER must be set", name.to_uppercase()).into());
+            }
+            (ProviderKind::Github, Err(_)) => None,
+        };
+
+        let default_scopes = match kind {
+            ProviderKind::Oidc => "openid email profile",
+            ProviderKind::Github => "read:user user:email",
+        };
+
+        Ok(Self {
+            name: name.to_string(),
+            kind,
+            client_id: var("CLIENT_ID")
+                .map_err(|_| format!("OAUTH_{}_CLIENT_ID must be set", name.to_uppercase()))?,
+            client_secret: var("CLIENT_SECRET")
+                .map_err(|_| format!("OAUTH_{}_CLIENT_SECRET must be set", name.to_uppercase()))?,
+            issuer,
+            scopes: var("SCOPES").unwrap_or_else(|_| default_scopes.to_string()),
+            metadata: OnceCell::new(),
+            jwks: RwLock::new(None),
+        })
+    }
+}
+
+pub fn pkce_challenge(code_verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
+
+fn is_true(value: Option<&serde_json::Value>) -> bool {
+    match value {
+        Some(serde_json::Value::Bool(value)) => *value,
+        Some(serde_json::Value::String(value)) => value == "true",
+        _ => false,
+    }
+}
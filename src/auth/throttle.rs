@@ -0,0 +1,76 @@
+use std::env;
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::models::LoginFailureStats;
+
+// Brute force protection for login. Failures are counted per email and per IP within a sliding
+// window: after a few free attempts every further one has to wait exponentially longer, at the
+// threshold the account gets locked until it expires or the owner uses the unlock link.
+pub struct LoginThrottleConfig {
+    pub window: Duration,
+    pub free_attempts: i64,
+    pub max_delay_seconds: i64,
+    pub lockout_threshold: i64,
+    pub lockout_duration: Duration,
+    pub ip_threshold: i64,
+}
+
+impl LoginThrottleConfig {
+    pub fn from_env() -> Self {
+        Self {
+            window: Duration::minutes(env_or("LOGIN_ATTEMPT_WINDOW_MINUTES", 15)),
+            free_attempts: env_or("LOGIN_FREE_ATTEMPTS", 3),
+            max_delay_seconds: env_or("LOGIN_MAX_DELAY_SECONDS", 60),
+            lockout_threshold: env_or("LOGIN_LOCKOUT_THRESHOLD", 10),
+            lockout_duration: Duration::minutes(env_or("LOGIN_LOCKOUT_MINUTES", 30)),
+            ip_threshold: env_or("LOGIN_IP_THRESHOLD", 100),
+        }
+    }
+
+    pub fn window_start(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        now - self.window
+    }
+
+    // seconds the next attempt for this account has to wait, if any
+    pub fn account_retry_after(
+        &self,
+        stats: &LoginFailureStats,
+        now: DateTime<Utc>,
+    ) -> Option<u64> {
+        if stats.count < self.free_attempts {
+            return None;
+        }
+
+        let exponent = (stats.count - self.free_attempts).min(16) as u32;
+        let delay = 2_i64.pow(exponent).min(self.max_delay_seconds);
+
+        seconds_until(stats.last_attempt_at? + Duration::seconds(delay), now)
+    }
+
+    // an IP over the threshold waits until its oldest failure leaves the window
+    pub fn ip_retry_after(&self, stats: &LoginFailureStats, now: DateTime<Utc>) -> Option<u64> {
+        if stats.count < self.ip_threshold {
+            return None;
+        }
+
+        seconds_until(stats.first_attempt_at? + self.window, now)
+    }
+
+    pub fn should_lock(&self, failures: i64) -> bool {
+        failures >= self.lockout_threshold
+    }
+}
+
+pub fn seconds_until(until: DateTime<Utc>, now: DateTime<Utc>) -> Option<u64> {
+    let remaining = (until - now).num_seconds();
+
+    (remaining > 0).then_some(remaining as u64)
+}
+
+fn env_or(name: &str, default: i64) -> i64 {
+    env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
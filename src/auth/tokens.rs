@@ -1,5 +1,34 @@
-use uuid::Uuid;
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use rand::{RngCore, rngs::OsRng};
+use sha2::{Digest, Sha256};
+
+// 256 bits from the OS CSPRNG, uuid v4 only gives us 122
+const TOKEN_BYTES: usize = 32;
+
+pub fn generate_secure_token() -> String {
+    let mut bytes = [0u8; TOKEN_BYTES];
+    OsRng.fill_bytes(&mut bytes);
+
+    URL_SAFE_NO_PAD.encode(bytes)
+}
 
 pub fn generate_refresh_token() -> String {
-    Uuid::new_v4().to_string()
+    generate_secure_token()
+}
+
+// only the digest of a bearer secret is ever stored, so a leaked table can't be replayed
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+// lets RequireAuth tell them from JWTs without trying to decode them, and secret scanners find
+// them in leaked code
+pub const PERSONAL_ACCESS_TOKEN_PREFIX: &str = "pat_";
+
+pub fn generate_personal_access_token() -> String {
+    format!(
+        "{}{}",
+        PERSONAL_ACCESS_TOKEN_PREFIX,
+        generate_secure_token()
+    )
 }
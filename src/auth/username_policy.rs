@@ -0,0 +1,89 @@
+use std::{collections::HashSet, env};
+
+use unicode_normalization::UnicodeNormalization;
+use unicode_security::{
+    GeneralSecurityProfile, RestrictionLevel, RestrictionLevelDetection, skeleton,
+};
+
+const MIN_LENGTH: usize = 3;
+const MAX_LENGTH: usize = 50;
+
+// they'd pass for staff or system accounts, compared by skeleton so "supp0rt" is out as well
+#[rustfmt::skip]
+const RESERVED_USERNAMES: &[&str] = &[
+    "admin", "administrator", "root", "system", "sysadmin", "superuser", "staff", "team",
+    "moderator", "mod", "support", "help", "helpdesk", "security", "abuse", "api", "auth",
+    "oauth", "login", "logout", "register", "signup", "signin", "settings", "account", "user",
+    "users", "me", "billing", "info", "contact", "official", "owner", "postmaster", "webmaster",
+    "hostmaster", "noreply", "no-reply", "mail", "email", "www", "static", "null", "undefined",
+    "anonymous",
+];
+
+// Usernames are shown to everyone, so two that look the same would be one account pretending
+// to be another. NFKC first, then UTS #39: only characters allowed in identifiers, no mixing
+// of scripts within a name, and no name whose skeleton matches a reserved one.
+pub struct UsernamePolicy {
+    reserved: HashSet<String>,
+}
+
+impl UsernamePolicy {
+    pub fn from_env() -> Self {
+        // RESERVED_USERNAMES adds to the built-in list
+        let extra = env::var("RESERVED_USERNAMES").unwrap_or_default();
+
+        let reserved = RESERVED_USERNAMES
+            .iter()
+            .copied()
+            .chain(extra.split(','))
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(username_skeleton)
+            .collect();
+
+        Self { reserved }
+    }
+
+    // empty if the (already normalized) username is fine, otherwise what's wrong with it
+    pub fn check(&self, username: &str) -> Vec<String> {
+        let mut messages = Vec::new();
+
+        let length = username.chars().count();
+        if !(MIN_LENGTH..=MAX_LENGTH).contains(&length) {
+            messages.push(format!(
+                "Username must be between {} and {} characters",
+                MIN_LENGTH, MAX_LENGTH
+            ));
+        }
+
+        let allowed_chars = username.chars().all(|c| {
+            c.identifier_allowed() && (c.is_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        });
+
+        if !allowed_chars {
+            messages
+                .push("Username can only contain letters, digits, '-', '_' and '.'".to_string());
+        } else if !username.check_restriction_level(RestrictionLevel::HighlyRestrictive) {
+            // e.g. a Cyrillic "а" in an otherwise Latin name
+            messages.push("Username can't mix letters from different alphabets".to_string());
+        }
+
+        if self.reserved.contains(&username_skeleton(username)) {
+            messages.push("This username is reserved".to_string());
+        }
+
+        messages
+    }
+}
+
+// what gets stored and looked up, "ｂｏｂ " and "bob" are the same name
+pub fn normalize_username(username: &str) -> String {
+    username.trim().nfkc().collect()
+}
+
+// Equal for usernames that only differ in case or in characters that look alike ("bob1",
+// "BOBl", "bоb1" with a Cyrillic о). Stored in users.username_skeleton with a unique index.
+pub fn username_skeleton(username: &str) -> String {
+    let lowercase: String = normalize_username(username).to_lowercase();
+
+    skeleton(&lowercase).collect::<String>().to_lowercase()
+}
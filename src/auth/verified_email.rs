@@ -0,0 +1,41 @@
+use std::env;
+
+use crate::models::User;
+
+// the error code the frontend looks for to show "verify your email"
+pub const EMAIL_NOT_VERIFIED: &str = "email_not_verified";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnverifiedAccess {
+    // no difference to a verified account
+    Full,
+    // can log in and read, anything behind RequireVerified is a 403
+    ReadOnly,
+    // can't log in at all until the link in the email was followed
+    None,
+}
+
+// what an account with an unverified email may do
+pub struct VerifiedEmailPolicy {
+    pub unverified_access: UnverifiedAccess,
+}
+
+impl VerifiedEmailPolicy {
+    pub fn from_env() -> Self {
+        let unverified_access = match env::var("UNVERIFIED_EMAIL_ACCESS").as_deref() {
+            Ok("read_only") => UnverifiedAccess::ReadOnly,
+            Ok("none") => UnverifiedAccess::None,
+            _ => UnverifiedAccess::Full,
+        };
+
+        Self { unverified_access }
+    }
+
+    pub fn allows_login(&self, user: &User) -> bool {
+        user.email_verified || self.unverified_access != UnverifiedAccess::None
+    }
+
+    pub fn allows_writes(&self, user: &User) -> bool {
+        user.email_verified || self.unverified_access == UnverifiedAccess::Full
+    }
+}
@@ -0,0 +1,89 @@
+// Grants a role from the command line, for the first admin when nobody can use the API yet:
+//
+//   cargo run --bin grant-role -- admin@example.com admin
+//   cargo run --bin grant-role -- --revoke admin@example.com admin
+use std::{env, process};
+
+use rw_axum_api::{
+    auth::audit,
+    models::NewAuditEvent,
+    repositories::{
+        AuditEventRepository, AuditEventRepositoryTrait, RoleRepository, RoleRepositoryTrait,
+        UserRepository, UserRepositoryTrait,
+    },
+};
+use serde_json::json;
+use sqlx::PgPool;
+
+#[tokio::main]
+async fn main() {
+    dotenvy::dotenv().ok();
+
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let revoke = args.first().is_some_and(|arg| arg == "--revoke");
+    if revoke {
+        args.remove(0);
+    }
+
+    let [email, role] = args.as_slice() else {
+        eprintln!("Usage: grant-role [--revoke] <email> <role>");
+        process::exit(2);
+    };
+
+    if let Err(e) = run(email, role, revoke).await {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+async fn run(email: &str, role: &str, revoke: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let database = env::var("DATABASE_URL").map_err(|_| "DATABASE_URL must be defined")?;
+    let db = PgPool::connect(&database).await?;
+
+    // the roles table might not exist yet on a fresh database
+    sqlx::migrate!("./migrations").run(&db).await?;
+
+    let user_repository = UserRepository::new(db.clone());
+    let role_repository = RoleRepository::new(db.clone());
+    let audit_event_repository = AuditEventRepository::new(db);
+
+    let user = user_repository
+        .find_by_email(email)
+        .await?
+        .ok_or_else(|| format!("No user with email {}", email))?;
+
+    role_repository
+        .find_by_name(role)
+        .await?
+        .ok_or_else(|| format!("No role named {}", role))?;
+
+    let event_type = if revoke {
+        if !role_repository.revoke_role(user.id, role).await? {
+            println!("{} didn't have {}", user.username, role);
+            return Ok(());
+        }
+        println!("Revoked {} from {}", role, user.username);
+        audit::ADMIN_ROLE_REVOKED
+    } else {
+        if !role_repository.grant_role(user.id, role, None).await? {
+            println!("{} already has {}", user.username, role);
+            return Ok(());
+        }
+        println!(
+            "Granted {} to {}, it takes effect with their next login or token refresh",
+            role, user.username
+        );
+        audit::ADMIN_ROLE_GRANTED
+    };
+
+    // no actor, whoever has shell access to the database did it
+    audit_event_repository
+        .record(
+            &NewAuditEvent::new(event_type)
+                .user(user.id)
+                .metadata(json!({ "role": role, "via": "cli" })),
+        )
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,64 @@
+use axum::{
+    Json,
+    http::{HeaderValue, StatusCode, header::RETRY_AFTER},
+    response::{IntoResponse, Response},
+};
+use serde_json::json;
+
+// Handler error for responses that need more than a bare status code. Plain StatusCodes convert
+// into it, so `?` keeps working on the usual `.map_err(|_| StatusCode::...)` calls.
+#[derive(Debug)]
+pub enum ApiError {
+    Status(StatusCode),
+    TooManyRequests {
+        retry_after_secs: u64,
+    },
+    // 422 with {"errors": {"<field>": [...]}}, the RealWorld error format
+    Validation {
+        field: &'static str,
+        messages: Vec<String>,
+    },
+    // 403 with {"error": "<code>"} so the frontend knows what to ask the user for, e.g.
+    // reauthentication_required
+    Forbidden {
+        error: &'static str,
+    },
+    // {"error": "<code>"} as RFC 6749 wants it from the token, revocation and introspection
+    // endpoints, e.g. invalid_grant or invalid_client
+    OAuth {
+        status: StatusCode,
+        error: &'static str,
+    },
+}
+
+impl From<StatusCode> for ApiError {
+    fn from(status: StatusCode) -> Self {
+        ApiError::Status(status)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        match self {
+            ApiError::Status(status) => status.into_response(),
+            ApiError::TooManyRequests { retry_after_secs } => {
+                let mut response = StatusCode::TOO_MANY_REQUESTS.into_response();
+                response
+                    .headers_mut()
+                    .insert(RETRY_AFTER, HeaderValue::from(retry_after_secs.max(1)));
+                response
+            }
+            ApiError::Validation { field, messages } => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                Json(json!({ "errors": { field: messages } })),
+            )
+                .into_response(),
+            ApiError::Forbidden { error } => {
+                (StatusCode::FORBIDDEN, Json(json!({ "error": error }))).into_response()
+            }
+            ApiError::OAuth { status, error } => {
+                (status, Json(json!({ "error": error }))).into_response()
+            }
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use axum::{
+    Json,
+    extract::State,
+    http::{StatusCode, header},
+    response::IntoResponse,
+};
+use chrono::Utc;
+use serde_json::json;
+
+use crate::{
+    auth::{
+        audit,
+        middleware::RequireRecentAuth,
+        roles::{Admin, Role},
+    },
+    errors::ApiError,
+    handlers::invite::invites_response,
+    models::NewAuditEvent,
+    schemas::{
+        AccessTokenData, AccountExport, DeleteAccountResponse, ExportDeviceData, ExportProfileData,
+        ExportSessionData, OAuthClientData, SecurityEventData, UserIdentityData,
+    },
+    state::AppState,
+    utils::RequestMeta,
+};
+
+// security events are read in pages of this size, the export has all of them
+const EXPORT_EVENT_PAGE_SIZE: i64 = 1000;
+
+// Soft delete. Every session ends right away, the data stays until the purge job removes it once
+// the grace period is over. Logging in before that restores the account.
+pub async fn delete_account(
+    State(state): State<AppState>,
+    RequireRecentAuth { user, .. }: RequireRecentAuth,
+    request: RequestMeta,
+) -> Result<Json<DeleteAccountResponse>, ApiError> {
+    // nobody could grant it back without going through the command line
+    let roles = state
+        .role_repository
+        .find_role_names_for_user(user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if roles.iter().any(|role| role == Admin::NAME) {
+        let admins = state
+            .role_repository
+            .count_users_with_role(Admin::NAME)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+        if admins <= 1 {
+            return Err(StatusCode::CONFLICT.into());
+        }
+    }
+
+    let deleted_at = state
+        .user_repository
+        .schedule_deletion(user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let purge_at = state.account_deletion.purge_at(deleted_at);
+
+    state
+        .refresh_token_repository
+        .delete_all_user_tokens(user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    state
+        .personal_access_token_repository
+        .delete_all_user_tokens(user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    state
+        .user_repository
+        .increment_token_version(user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    audit::record(
+        &state,
+        NewAuditEvent::new(audit::USER_DELETION_REQUESTED)
+            .user(user.id)
+            .request(&request)
+            .metadata(json!({ "purge_at": purge_at })),
+    )
+    .await;
+
+    if let Err(e) = state
+        .email_service
+        .send_account_deletion_email(&user.email, &user.username, purge_at)
+        .await
+    {
+        eprintln!("Failed to send account deletion email: {}", e);
+        // the account is deleted already, don't fail the request
+    }
+
+    Ok(Json(DeleteAccountResponse {
+        message: "Account deleted, log in again to restore it".to_string(),
+        purge_at,
+    }))
+}
+
+// the whole account as one JSON file, secrets like password and token hashes left out
+pub async fn export_account(
+    State(state): State<AppState>,
+    RequireRecentAuth { user, .. }: RequireRecentAuth,
+    request: RequestMeta,
+) -> Result<impl IntoResponse, ApiError> {
+    let roles = state
+        .role_repository
+        .find_role_names_for_user(user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let sessions = state
+        .refresh_token_repository
+        .find_active_sessions(user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let identities = state
+        .user_identity_repository
+        .find_by_user(user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let access_tokens = state
+        .personal_access_token_repository
+        .find_by_user(user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let oauth_clients = state
+        .oauth_client_repository
+        .find_by_owner(user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let known_devices = state
+        .known_device_repository
+        .find_by_user(user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let invites = invites_response(&state, user.id).await?;
+
+    let mut security_events = Vec::new();
+    loop {
+        let page = state
+            .audit_event_repository
+            .find_by_user(
+                user.id,
+                EXPORT_EVENT_PAGE_SIZE,
+                security_events.len() as i64,
+            )
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+        let last_page = (page.len() as i64) < EXPORT_EVENT_PAGE_SIZE;
+        security_events.extend(page.into_iter().map(SecurityEventData::from_event));
+
+        if last_page {
+            break;
+        }
+    }
+
+    audit::record(
+        &state,
+        NewAuditEvent::new(audit::USER_DATA_EXPORTED)
+            .user(user.id)
+            .request(&request),
+    )
+    .await;
+
+    let exported_at = Utc::now();
+    let filename = format!(
+        "attachment; filename=\"{}-export-{}.json\"",
+        user.username,
+        exported_at.format("%Y-%m-%d")
+    );
+
+    let export = AccountExport {
+        exported_at,
+        profile: ExportProfileData::from_user(user),
+        roles,
+        sessions: sessions
+            .into_iter()
+            .map(ExportSessionData::from_refresh_token)
+            .collect(),
+        identities: identities
+            .into_iter()
+            .map(UserIdentityData::from_identity)
+            .collect(),
+        access_tokens: access_tokens
+            .into_iter()
+            .map(AccessTokenData::from_access_token)
+            .collect(),
+        oauth_clients: oauth_clients
+            .into_iter()
+            .map(OAuthClientData::from_client)
+            .collect(),
+        known_devices: known_devices
+            .into_iter()
+            .map(ExportDeviceData::from_device)
+            .collect(),
+        invites: invites.invites,
+        invited_by: invites.invited_by,
+        security_events,
+    };
+
+    Ok(([(header::CONTENT_DISPOSITION, filename)], Json(export)))
+}
@@ -0,0 +1,679 @@
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+};
+use chrono::{Duration, Utc};
+use serde_json::json;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    auth::{
+        audit,
+        jwt::{IMPERSONATION_TOKEN_TTL_MINUTES, generate_impersonation_token},
+        middleware::RequirePermission,
+        roles::{
+            Admin, AuditRead, InvitesManage, Permission, Role, RolesManage, UsersImpersonate,
+            UsersRead, UsersWrite,
+        },
+    },
+    handlers::{auth::send_password_reset, invite::invites_response},
+    models::{AuditEventFilter, NewAuditEvent, User},
+    schemas::{
+        AdminSessionData, AdminUserData, AdminUserDetailResponse, AdminUserResponse,
+        AdminUsersResponse, AuditEventData, AuditEventParams, AuditEventsResponse,
+        ImpersonateUserRequest, ImpersonationResponse, InviteQuotaRequest, InvitesResponse,
+        RoleData, RolesResponse, SuspendUserRequest, UserRolesResponse, UserSearchParams,
+    },
+    state::AppState,
+    utils::RequestMeta,
+};
+
+const DEFAULT_PAGE_SIZE: i64 = 20;
+const MAX_PAGE_SIZE: i64 = 100;
+
+// enough to see what happened lately, /api/admin/audit-events has the rest
+const RECENT_EVENTS: i64 = 20;
+
+pub async fn list_roles(
+    State(state): State<AppState>,
+    _: RequirePermission<RolesManage>,
+) -> Result<Json<RolesResponse>, StatusCode> {
+    let roles = state
+        .role_repository
+        .find_all()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(RolesResponse {
+        roles: roles.into_iter().map(RoleData::from_role).collect(),
+    }))
+}
+
+pub async fn get_user_roles(
+    State(state): State<AppState>,
+    _: RequirePermission<RolesManage>,
+    Path(user_id): Path<Uuid>,
+) -> Result<Json<UserRolesResponse>, StatusCode> {
+    state
+        .user_repository
+        .find_by_id(user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    user_roles_response(&state, user_id).await
+}
+
+// takes effect with the user's next token refresh
+pub async fn grant_role(
+    State(state): State<AppState>,
+    RequirePermission(admin, _): RequirePermission<RolesManage>,
+    request: RequestMeta,
+    Path((user_id, role)): Path<(Uuid, String)>,
+) -> Result<Json<UserRolesResponse>, StatusCode> {
+    state
+        .user_repository
+        .find_by_id(user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    state
+        .role_repository
+        .find_by_name(&role)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    state
+        .role_repository
+        .grant_role(user_id, &role, Some(admin.id))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    record_action(
+        &state,
+        NewAuditEvent::new(audit::ADMIN_ROLE_GRANTED).metadata(json!({ "role": role })),
+        &admin,
+        user_id,
+        &request,
+    )
+    .await?;
+
+    user_roles_response(&state, user_id).await
+}
+
+pub async fn revoke_role(
+    State(state): State<AppState>,
+    RequirePermission(admin, _): RequirePermission<RolesManage>,
+    request: RequestMeta,
+    Path((user_id, role)): Path<(Uuid, String)>,
+) -> Result<Json<UserRolesResponse>, StatusCode> {
+    let has_role = state
+        .role_repository
+        .user_has_role(user_id, &role)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if !has_role {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    // nobody could grant it back without going through the command line
+    if role == Admin::NAME {
+        let admins = state
+            .role_repository
+            .count_users_with_role(Admin::NAME)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+        if admins <= 1 {
+            return Err(StatusCode::CONFLICT);
+        }
+    }
+
+    state
+        .role_repository
+        .revoke_role(user_id, &role)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    // another role may still grant it
+    let can_impersonate = state
+        .role_repository
+        .user_has_permission(user_id, UsersImpersonate::NAME)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if !can_impersonate {
+        end_impersonations_by(&state, &admin, user_id, &request).await?;
+    }
+
+    record_action(
+        &state,
+        NewAuditEvent::new(audit::ADMIN_ROLE_REVOKED).metadata(json!({ "role": role })),
+        &admin,
+        user_id,
+        &request,
+    )
+    .await?;
+
+    user_roles_response(&state, user_id).await
+}
+
+pub async fn list_users(
+    State(state): State<AppState>,
+    _: RequirePermission<UsersRead>,
+    Query(params): Query<UserSearchParams>,
+) -> Result<Json<AdminUsersResponse>, StatusCode> {
+    let query = params
+        .q
+        .as_deref()
+        .map(str::trim)
+        .filter(|query| !query.is_empty());
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let offset = params.offset.unwrap_or(0).max(0);
+
+    let users = state
+        .user_repository
+        .search(query, limit, offset)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let total = state
+        .user_repository
+        .count(query)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(AdminUsersResponse {
+        users: users.into_iter().map(AdminUserData::from_user).collect(),
+        total,
+    }))
+}
+
+pub async fn get_user(
+    State(state): State<AppState>,
+    _: RequirePermission<UsersRead>,
+    Path(user_id): Path<Uuid>,
+) -> Result<Json<AdminUserDetailResponse>, StatusCode> {
+    let user = find_user(&state, user_id).await?;
+
+    let roles = state
+        .role_repository
+        .find_role_names_for_user(user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let sessions = state
+        .refresh_token_repository
+        .find_active_sessions(user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let events = state
+        .audit_event_repository
+        .find_by_user(user_id, RECENT_EVENTS, 0)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(AdminUserDetailResponse {
+        user: AdminUserData::from_user(user),
+        roles,
+        sessions: sessions
+            .into_iter()
+            .map(AdminSessionData::from_refresh_token)
+            .collect(),
+        events: events.into_iter().map(AuditEventData::from_event).collect(),
+    }))
+}
+
+// for users whose verification mail never arrives
+pub async fn verify_user_email(
+    State(state): State<AppState>,
+    RequirePermission(admin, _): RequirePermission<UsersWrite>,
+    request: RequestMeta,
+    Path(user_id): Path<Uuid>,
+) -> Result<Json<AdminUserResponse>, StatusCode> {
+    let user = find_user(&state, user_id).await?;
+
+    if !user.email_verified {
+        state
+            .email_verification_repository
+            .verify_user_email(user_id)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+        record_action(
+            &state,
+            NewAuditEvent::new(audit::ADMIN_EMAIL_VERIFIED),
+            &admin,
+            user_id,
+            &request,
+        )
+        .await?;
+    }
+
+    user_response(&state, user_id).await
+}
+
+// Sends the usual reset link to the user's own address, support never gets to pick a password
+pub async fn send_user_password_reset(
+    State(state): State<AppState>,
+    RequirePermission(admin, _): RequirePermission<UsersWrite>,
+    request: RequestMeta,
+    Path(user_id): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let user = find_user(&state, user_id).await?;
+
+    send_password_reset(&state, &user.email)
+        .await
+        .map_err(|e| {
+            eprintln!("Failed to send password reset: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    record_action(
+        &state,
+        NewAuditEvent::new(audit::ADMIN_PASSWORD_RESET_SENT),
+        &admin,
+        user_id,
+        &request,
+    )
+    .await?;
+
+    Ok(Json(
+        serde_json::json!({"message": "Password reset link sent"}),
+    ))
+}
+
+pub async fn suspend_user(
+    State(state): State<AppState>,
+    RequirePermission(admin, _): RequirePermission<UsersWrite>,
+    request: RequestMeta,
+    Path(user_id): Path<Uuid>,
+    payload: Option<Json<SuspendUserRequest>>,
+) -> Result<Json<AdminUserResponse>, StatusCode> {
+    let Json(payload) = payload.unwrap_or_default();
+    payload.validate().map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    // there would be nobody left to lift it if the last admin did this
+    if user_id == admin.id {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    find_user(&state, user_id).await?;
+
+    let reason = payload
+        .reason
+        .as_deref()
+        .map(str::trim)
+        .filter(|reason| !reason.is_empty());
+
+    state
+        .user_repository
+        .suspend(user_id, reason)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    end_all_sessions(&state, user_id).await?;
+    end_impersonations_by(&state, &admin, user_id, &request).await?;
+
+    record_action(
+        &state,
+        NewAuditEvent::new(audit::ADMIN_SUSPENDED).metadata(json!({ "reason": reason })),
+        &admin,
+        user_id,
+        &request,
+    )
+    .await?;
+
+    user_response(&state, user_id).await
+}
+
+pub async fn unsuspend_user(
+    State(state): State<AppState>,
+    RequirePermission(admin, _): RequirePermission<UsersWrite>,
+    request: RequestMeta,
+    Path(user_id): Path<Uuid>,
+) -> Result<Json<AdminUserResponse>, StatusCode> {
+    let user = find_user(&state, user_id).await?;
+
+    if !user.is_suspended() {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    state
+        .user_repository
+        .unsuspend(user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    record_action(
+        &state,
+        NewAuditEvent::new(audit::ADMIN_UNSUSPENDED),
+        &admin,
+        user_id,
+        &request,
+    )
+    .await?;
+
+    user_response(&state, user_id).await
+}
+
+// same as the user's own logout-all, for a stolen laptop or a leaked token
+pub async fn logout_user(
+    State(state): State<AppState>,
+    RequirePermission(admin, _): RequirePermission<UsersWrite>,
+    request: RequestMeta,
+    Path(user_id): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    find_user(&state, user_id).await?;
+
+    end_all_sessions(&state, user_id).await?;
+
+    record_action(
+        &state,
+        NewAuditEvent::new(audit::ADMIN_LOGGED_OUT),
+        &admin,
+        user_id,
+        &request,
+    )
+    .await?;
+
+    Ok(Json(
+        serde_json::json!({"message": "Logged out of all sessions"}),
+    ))
+}
+
+// A short lived access token for the user, with the admin in its act claim. There's no refresh
+// token, support asks for a new one once it expires.
+pub async fn impersonate_user(
+    State(state): State<AppState>,
+    RequirePermission(admin, _): RequirePermission<UsersImpersonate>,
+    request: RequestMeta,
+    Path(user_id): Path<Uuid>,
+    Json(payload): Json<ImpersonateUserRequest>,
+) -> Result<Json<ImpersonationResponse>, StatusCode> {
+    payload.validate().map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let reason = payload.reason.trim();
+    if reason.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    if user_id == admin.id {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let user = find_user(&state, user_id).await?;
+
+    // the token would be turned away on the first request anyway
+    if user.is_suspended() {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let expires_at = Utc::now() + Duration::minutes(IMPERSONATION_TOKEN_TTL_MINUTES);
+
+    let session = state
+        .impersonation_repository
+        .create(user.id, admin.id, reason, expires_at)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let access_token = generate_impersonation_token(
+        &user.id,
+        &session.id,
+        user.token_version,
+        &admin.id,
+        session.expires_at,
+        &state.jwt_keys,
+        &state.jwt_config,
+    )
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    record_action(
+        &state,
+        NewAuditEvent::new(audit::ADMIN_IMPERSONATION_STARTED).metadata(json!({
+            "session_id": session.id,
+            "reason": reason,
+            "expires_at": session.expires_at,
+        })),
+        &admin,
+        user.id,
+        &request,
+    )
+    .await?;
+
+    Ok(Json(ImpersonationResponse {
+        session_id: session.id,
+        access_token,
+        expires_at: session.expires_at,
+        user: AdminUserData::from_user(user),
+    }))
+}
+
+// any admin may end any impersonation early, the audit record says who started it
+pub async fn stop_impersonation(
+    State(state): State<AppState>,
+    RequirePermission(admin, _): RequirePermission<UsersImpersonate>,
+    request: RequestMeta,
+    Path(session_id): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let session = state
+        .impersonation_repository
+        .end(session_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    state.session_cache.revoke(session.id);
+
+    record_action(
+        &state,
+        NewAuditEvent::new(audit::ADMIN_IMPERSONATION_STOPPED).metadata(json!({
+            "session_id": session.id,
+            "started_by": session.admin_id,
+        })),
+        &admin,
+        session.user_id,
+        &request,
+    )
+    .await?;
+
+    Ok(Json(serde_json::json!({"message": "Impersonation ended"})))
+}
+
+pub async fn list_audit_events(
+    State(state): State<AppState>,
+    _: RequirePermission<AuditRead>,
+    Query(params): Query<AuditEventParams>,
+) -> Result<Json<AuditEventsResponse>, StatusCode> {
+    let filter = AuditEventFilter {
+        user_id: params.user_id,
+        actor_id: params.actor_id,
+        event_type: params
+            .event_type
+            .filter(|event_type| !event_type.is_empty()),
+        ip_address: params
+            .ip_address
+            .filter(|ip_address| !ip_address.is_empty()),
+        since: params.since,
+        until: params.until,
+    };
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let offset = params.offset.unwrap_or(0).max(0);
+
+    let events = state
+        .audit_event_repository
+        .search(&filter, limit, offset)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let total = state
+        .audit_event_repository
+        .count(&filter)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(AuditEventsResponse {
+        events: events.into_iter().map(AuditEventData::from_event).collect(),
+        total,
+    }))
+}
+
+// who the user invited and who invited them
+pub async fn get_user_invites(
+    State(state): State<AppState>,
+    _: RequirePermission<InvitesManage>,
+    Path(user_id): Path<Uuid>,
+) -> Result<Json<InvitesResponse>, StatusCode> {
+    find_user(&state, user_id).await?;
+
+    Ok(Json(invites_response(&state, user_id).await?))
+}
+
+pub async fn set_invite_quota(
+    State(state): State<AppState>,
+    RequirePermission(admin, _): RequirePermission<InvitesManage>,
+    request: RequestMeta,
+    Path(user_id): Path<Uuid>,
+    Json(payload): Json<InviteQuotaRequest>,
+) -> Result<Json<InvitesResponse>, StatusCode> {
+    payload.validate().map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    find_user(&state, user_id).await?;
+
+    state
+        .invite_repository
+        .set_quota(user_id, payload.quota)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    record_action(
+        &state,
+        NewAuditEvent::new(audit::ADMIN_INVITE_QUOTA_CHANGED)
+            .metadata(json!({ "quota": payload.quota })),
+        &admin,
+        user_id,
+        &request,
+    )
+    .await?;
+
+    Ok(Json(invites_response(&state, user_id).await?))
+}
+
+async fn find_user(state: &AppState, user_id: Uuid) -> Result<User, StatusCode> {
+    state
+        .user_repository
+        .find_by_id(user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+async fn user_response(
+    state: &AppState,
+    user_id: Uuid,
+) -> Result<Json<AdminUserResponse>, StatusCode> {
+    let user = find_user(state, user_id).await?;
+
+    Ok(Json(AdminUserResponse {
+        user: AdminUserData::from_user(user),
+    }))
+}
+
+// refresh and personal access tokens gone, the version bump takes care of the access tokens
+// already out there
+async fn end_all_sessions(state: &AppState, user_id: Uuid) -> Result<(), StatusCode> {
+    state
+        .refresh_token_repository
+        .delete_all_user_tokens(user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    state
+        .personal_access_token_repository
+        .delete_all_user_tokens(user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    state
+        .user_repository
+        .increment_token_version(user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(())
+}
+
+// The middleware turns these away too, but only once the session cache lets go of them. Recorded
+// like a stop by the admin taking the action.
+async fn end_impersonations_by(
+    state: &AppState,
+    admin: &User,
+    impersonator_id: Uuid,
+    request: &RequestMeta,
+) -> Result<(), StatusCode> {
+    let sessions = state
+        .impersonation_repository
+        .end_all_by_admin(impersonator_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    for session in sessions {
+        state.session_cache.revoke(session.id);
+
+        record_action(
+            state,
+            NewAuditEvent::new(audit::ADMIN_IMPERSONATION_STOPPED).metadata(json!({
+                "session_id": session.id,
+                "started_by": session.admin_id,
+            })),
+            admin,
+            session.user_id,
+            request,
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+// unlike audit::record this fails the request, an admin action must not go unrecorded
+async fn record_action(
+    state: &AppState,
+    event: NewAuditEvent,
+    admin: &User,
+    user_id: Uuid,
+    request: &RequestMeta,
+) -> Result<(), StatusCode> {
+    state
+        .audit_event_repository
+        .record(&event.user(user_id).actor(admin.id).request(request))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(())
+}
+
+async fn user_roles_response(
+    state: &AppState,
+    user_id: Uuid,
+) -> Result<Json<UserRolesResponse>, StatusCode> {
+    let roles = state
+        .role_repository
+        .find_role_names_for_user(user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(UserRolesResponse { roles }))
+}
@@ -1,30 +1,64 @@
 use std::collections::HashMap;
 
-use axum::{Json, extract::State, http::StatusCode};
+use axum::{
+    Json,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use axum_extra::extract::cookie::CookieJar;
 use chrono::{Duration, Utc};
+use serde_json::json;
+use uuid::Uuid;
 use validator::Validate;
 
 use crate::{
     auth::{
+        audit,
         jwt::generate_token,
-        middleware::RequireAuth,
-        password::{hash_password, verify_password},
+        middleware::{RequireAuth, RequireRecentAuth, RequireSession},
+        new_device::check_new_device,
+        password::{dummy_verify_password, hash_password, verify_password},
+        registration::RegistrationMode,
         tokens::generate_refresh_token,
+        username_policy::normalize_username,
+        verified_email::EMAIL_NOT_VERIFIED,
     },
+    errors::ApiError,
+    models::{NewAuditEvent, User},
     schemas::{
-        ForgotPasswordRequest, ForgotPasswordResponse, LoginUserRequest, LoginUserResponse,
-        LogoutRequest, LogoutResponse, RefreshTokenRequest, RefreshTokenResponse,
-        RegisterUserRequest, ResetPasswordRequest, ResetPasswordResponse, UserData,
-        auth_schemas::UserResponse,
+        ChangePasswordRequest, ChangePasswordResponse, ForgotPasswordRequest,
+        ForgotPasswordResponse, LoginUserRequest, LoginUserResponse, LogoutRequest, LogoutResponse,
+        ReauthenticateRequest, ReauthenticateResponse, RefreshTokenRequest, RefreshTokenResponse,
+        RegisterUserRequest, RegistrationPendingResponse, ResendVerificationRequest,
+        ResetPasswordRequest, ResetPasswordResponse, UserData, auth_schemas::UserResponse,
     },
     state::AppState,
-    utils::generate_verification_token,
+    utils::{RequestMeta, generate_verification_token},
 };
 
+// in cookie mode the refresh token goes into an HttpOnly cookie and never shows up in the body
+fn deliver_refresh_token(
+    state: &AppState,
+    jar: CookieJar,
+    refresh_token: String,
+) -> (CookieJar, Option<String>) {
+    if state.cookie_config.enabled {
+        (
+            state.cookie_config.set_session_cookies(jar, &refresh_token),
+            None,
+        )
+    } else {
+        (jar, Some(refresh_token))
+    }
+}
+
 pub async fn register(
     State(state): State<AppState>,
+    request: RequestMeta,
+    jar: CookieJar,
     Json(payload): Json<RegisterUserRequest>,
-) -> Result<Json<LoginUserResponse>, StatusCode> {
+) -> Result<Response, ApiError> {
     eprintln!("Registering User");
 
     // validate input data
@@ -34,27 +68,75 @@ pub async fn register(
         .validate()
         .map_err(|_| StatusCode::BAD_REQUEST)?;
 
-    // check if user already exists
-    eprintln!("Checking if email already exists...");
+    // stored the way it's looked up, "ｂｏｂ" registers as "bob"
+    let username = normalize_username(&payload.user.username);
+
+    let username_problems = state.username_policy.check(&username);
+    if !username_problems.is_empty() {
+        return Err(ApiError::Validation {
+            field: "username",
+            messages: username_problems,
+        });
+    }
+
+    check_password_policy(
+        &state,
+        &payload.user.password,
+        &username,
+        &payload.user.email,
+    )
+    .await?;
+
+    let enumeration_protection = state.registration_config.enumeration_protection;
+
+    let invite_code = payload
+        .user
+        .invite_code
+        .as_deref()
+        .map(str::trim)
+        .filter(|code| !code.is_empty());
+
+    // before anything that looks at existing accounts, a closed registration tells nothing
+    check_registration_allowed(&state, &payload.user.email, invite_code).await?;
+
+    // usernames are public anyway, checked first so the answer never depends on the email
+    eprintln!("Checking if username already exists...");
     if state
         .user_repository
-        .find_by_email(&payload.user.email)
+        .username_taken(&username)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-        .is_some()
     {
-        return Err(StatusCode::CONFLICT);
+        return Err(StatusCode::CONFLICT.into());
     }
 
-    eprintln!("Checking if username already exists...");
-    if state
+    // check if user already exists
+    eprintln!("Checking if email already exists...");
+    let existing_user = state
         .user_repository
-        .find_by_username(&payload.user.username)
+        .find_by_email(&payload.user.email)
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-        .is_some()
-    {
-        return Err(StatusCode::CONFLICT);
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if let Some(existing_user) = existing_user {
+        if !enumeration_protection {
+            return Err(StatusCode::CONFLICT.into());
+        }
+
+        // same bcrypt work as a real registration so the timing doesn't tell either
+        let _ = hash_password(&payload.user.password);
+
+        let email_service = state.email_service.clone();
+        tokio::spawn(async move {
+            if let Err(e) = email_service
+                .send_account_exists_email(&existing_user.email, &existing_user.username)
+                .await
+            {
+                eprintln!("Failed to send account exists email {}", e);
+            }
+        });
+
+        return Ok(registration_pending_response());
     }
 
     eprintln!("Hashing password...");
@@ -62,21 +144,64 @@ pub async fn register(
     let password_hash =
         hash_password(&payload.user.password).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
+    // checked above already, this takes the use for real
+    let invite = match invite_code {
+        Some(code) => Some(
+            state
+                .invite_repository
+                .redeem(code)
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+                .ok_or_else(invalid_invite_code)?,
+        ),
+        None => None,
+    };
+
     eprintln!("Creating user...");
     // add user to db
-    let user = state
+    let created = state
         .user_repository
-        .create(&payload.user.username, &payload.user.email, &password_hash)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .create(&username, &payload.user.email, &password_hash)
+        .await;
+
+    let user = match created {
+        Ok(user) => user,
+        Err(_) => {
+            if let Some(invite) = &invite
+                && let Err(e) = state.invite_repository.release(invite.id).await
+            {
+                eprintln!("Failed to give back invite use: {}", e);
+            }
+
+            return Err(StatusCode::INTERNAL_SERVER_ERROR.into());
+        }
+    };
 
     eprintln!("User created: {}", user.email);
 
+    if let Some(invite) = &invite {
+        state
+            .invite_repository
+            .record_redemption(invite.id, user.id)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+
+    audit::record(
+        &state,
+        NewAuditEvent::new(audit::USER_REGISTERED)
+            .user(user.id)
+            .request(&request)
+            .metadata(json!({
+                "invite_id": invite.as_ref().map(|invite| invite.id),
+                "invited_by": invite.as_ref().map(|invite| invite.created_by),
+            })),
+    )
+    .await;
+
     let verification_token = generate_verification_token();
     let expires_at = Utc::now() + Duration::hours(24);
 
-    eprintln!("Generated token: {}", verification_token);
-
     state
         .email_verification_repository
         .create_token(user.id, &verification_token, expires_at)
@@ -85,6 +210,24 @@ pub async fn register(
 
     eprintln!("Token saved to database");
 
+    // nothing to log in with until the email is verified
+    let can_log_in = state.verified_email_policy.allows_login(&user);
+
+    if enumeration_protection || !can_log_in {
+        // sent off the response path like the account exists email
+        let email_service = state.email_service.clone();
+        tokio::spawn(async move {
+            if let Err(e) = email_service
+                .send_verification_email(&user.email, &user.username, &verification_token)
+                .await
+            {
+                eprintln!("Failed to send verification email {}", e);
+            }
+        });
+
+        return Ok(registration_pending_response());
+    }
+
     // sending verification email
     eprintln!("Attempting to send email...");
 
@@ -99,83 +242,368 @@ pub async fn register(
 
     eprintln!("Email sent succesfully...");
 
-    // generate JWT token (15 min)
-    let jwt_secret = std::env::var("JWT_SECRET").map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    let token =
-        generate_token(&user.id, &jwt_secret).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let (jar, response) = start_session(&state, jar, user, &request, "registration").await?;
 
-    // generate refresh token
-    let refresh_token = generate_refresh_token();
+    eprintln!("Registration successful");
 
-    state
-        .refresh_token_repository
-        .create_token(user.id, &refresh_token)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok((jar, response).into_response())
+}
 
-    eprintln!("Tokens successfully generated...");
+// REGISTRATION_MODE for sign-ups with a password, social logins check it themselves
+async fn check_registration_allowed(
+    state: &AppState,
+    email: &str,
+    invite_code: Option<&str>,
+) -> Result<(), ApiError> {
+    let config = &state.registration_config;
+
+    if !config.allows_email(email) {
+        return Err(ApiError::Validation {
+            field: "email",
+            messages: vec!["Sign up with your work email address".to_string()],
+        });
+    }
 
-    // build the response
-    let response = LoginUserResponse {
-        user: UserData::from_user(user),
-        access_token: token,
-        refresh_token,
+    let Some(code) = invite_code else {
+        if config.mode == RegistrationMode::Invite {
+            return Err(ApiError::Validation {
+                field: "invite_code",
+                messages: vec!["An invite code is required".to_string()],
+            });
+        }
+
+        return Ok(());
     };
 
-    eprintln!("Registration successful");
+    let usable = state
+        .invite_repository
+        .find_by_code(code)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .is_some_and(|invite| invite.is_usable());
 
-    Ok(Json(response))
+    if !usable {
+        return Err(invalid_invite_code());
+    }
+
+    Ok(())
+}
+
+fn invalid_invite_code() -> ApiError {
+    ApiError::Validation {
+        field: "invite_code",
+        messages: vec!["This invite code is invalid, used up or expired".to_string()],
+    }
+}
+
+// same rules for every way a password gets set
+async fn check_password_policy(
+    state: &AppState,
+    password: &str,
+    username: &str,
+    email: &str,
+) -> Result<(), ApiError> {
+    let messages = state.password_policy.check(password, username, email).await;
+
+    if messages.is_empty() {
+        Ok(())
+    } else {
+        Err(ApiError::Validation {
+            field: "password",
+            messages,
+        })
+    }
+}
+
+pub(crate) fn registration_pending_response() -> Response {
+    (
+        StatusCode::ACCEPTED,
+        Json(RegistrationPendingResponse {
+            message: "Check your email to finish signing up".to_string(),
+        }),
+    )
+        .into_response()
 }
 
 pub async fn login(
     State(state): State<AppState>,
+    request: RequestMeta,
+    jar: CookieJar,
     Json(payload): Json<LoginUserRequest>,
-) -> Result<Json<LoginUserResponse>, StatusCode> {
+) -> Result<(CookieJar, Json<LoginUserResponse>), ApiError> {
     // validate input data
     payload
         .user
         .validate()
         .map_err(|_| StatusCode::BAD_REQUEST)?;
 
-    let user = state
+    let user = check_credentials(
+        &state,
+        &request,
+        &payload.user.email,
+        &payload.user.password,
+    )
+    .await?;
+
+    start_session(&state, jar, user, &request, "password").await
+}
+
+// Email and password check with the IP and per-account throttling, shared by the login and
+// the OAuth consent page.
+pub(crate) async fn check_credentials(
+    state: &AppState,
+    request: &RequestMeta,
+    email: &str,
+    password: &str,
+) -> Result<User, ApiError> {
+    let client_ip = &request.ip_address.to_string();
+    let now = Utc::now();
+    let since = state.login_throttle.window_start(now);
+    let throttle_email = email.to_lowercase();
+
+    // too many failures from this IP, no matter which accounts they targeted
+    let ip_failures = state
+        .login_attempt_repository
+        .failure_stats_for_ip(client_ip, since)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if let Some(retry_after_secs) = state.login_throttle.ip_retry_after(&ip_failures, now) {
+        return Err(ApiError::TooManyRequests { retry_after_secs });
+    }
+
+    // progressive delay per account, also applies to emails without an account
+    let account_failures = state
+        .login_attempt_repository
+        .failure_stats_for_email(&throttle_email, since)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if let Some(retry_after_secs) = state
+        .login_throttle
+        .account_retry_after(&account_failures, now)
+    {
+        return Err(ApiError::TooManyRequests { retry_after_secs });
+    }
+
+    let user = match state
         .user_repository
-        .find_by_email(&payload.user.email)
+        .find_by_email(email)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-        .ok_or(StatusCode::UNAUTHORIZED)?;
+    {
+        Some(user) => user,
+        None => {
+            // burn the same bcrypt time as a wrong password
+            dummy_verify_password(password);
+            record_failed_login(state, &throttle_email, client_ip).await?;
+            audit_failed_login(state, request, None, email, "unknown_email").await;
+            return Err(StatusCode::UNAUTHORIZED.into());
+        }
+    };
+
+    // Answered like an unknown email, a 429 with the lock's Retry-After would tell that the
+    // account exists. The owner learns about the lock from the unlock email.
+    if user.locked_for(now).is_some() {
+        dummy_verify_password(password);
+        record_failed_login(state, &throttle_email, client_ip).await?;
+        audit_failed_login(state, request, Some(user.id), email, "locked").await;
+        return Err(StatusCode::UNAUTHORIZED.into());
+    }
 
     // check for password validity
-    let valid_password = verify_password(&payload.user.password, &user.password_hash)
+    let valid_password = verify_password(password, &user.password_hash)
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
     if !valid_password {
-        return Err(StatusCode::UNAUTHORIZED);
+        record_failed_login(state, &throttle_email, client_ip).await?;
+
+        if state.login_throttle.should_lock(account_failures.count + 1) {
+            lock_account(state, &user).await?;
+        }
+
+        audit_failed_login(state, request, Some(user.id), email, "wrong_password").await;
+        return Err(StatusCode::UNAUTHORIZED.into());
+    }
+
+    // only tell whoever knows the password
+    if user.is_suspended() {
+        audit_failed_login(state, request, Some(user.id), email, "suspended").await;
+        return Err(StatusCode::FORBIDDEN.into());
     }
 
-    // generate JWT token
-    let jwt_secret = std::env::var("JWT_SECRET").map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    let access_token =
-        generate_token(&user.id, &jwt_secret).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    // a successful login starts with a clean slate
+    state
+        .login_attempt_repository
+        .clear_failures_for_email(&throttle_email)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(user)
+}
+
+// access/refresh pair for a new session, shared by every way of signing in
+// method says how the user signed in: password, magic_link, registration or the social provider
+pub(crate) async fn start_session(
+    state: &AppState,
+    jar: CookieJar,
+    user: User,
+    request: &RequestMeta,
+    method: &str,
+) -> Result<(CookieJar, Json<LoginUserResponse>), ApiError> {
+    // magic links and social logins skip check_credentials
+    if user.is_suspended() {
+        return Err(StatusCode::FORBIDDEN.into());
+    }
+
+    if !state.verified_email_policy.allows_login(&user) {
+        return Err(ApiError::Forbidden {
+            error: EMAIL_NOT_VERIFIED,
+        });
+    }
+
+    // logging in is how a deleted account gets restored, until the purge job gets to it
+    if let Some(deleted_at) = user.deleted_at {
+        if state.account_deletion.purge_at(deleted_at) <= Utc::now() {
+            return Err(StatusCode::UNAUTHORIZED.into());
+        }
+
+        state
+            .user_repository
+            .restore(user.id)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+        audit::record(
+            state,
+            NewAuditEvent::new(audit::USER_RESTORED)
+                .user(user.id)
+                .request(request)
+                .metadata(json!({ "method": method })),
+        )
+        .await;
+    }
+
+    // every login starts a new session, rotated refresh tokens keep its id
+    let session_id = Uuid::new_v4();
+    let authenticated_at = Utc::now();
+
+    let roles = state
+        .role_repository
+        .find_role_names_for_user(user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    // generate JWT token (15 min)
+    let access_token = generate_token(
+        &user.id,
+        &session_id,
+        user.token_version,
+        roles,
+        authenticated_at,
+        &state.jwt_keys,
+        &state.jwt_config,
+    )
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
     // generate refresh token
     let refresh_token = generate_refresh_token();
 
     state
         .refresh_token_repository
-        .create_token(user.id, &refresh_token)
+        .create_token(user.id, session_id, &refresh_token, authenticated_at)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    // build the response
-    let user_data = UserData::from_user(user);
+    let (jar, refresh_token) = deliver_refresh_token(state, jar, refresh_token);
+
+    audit::record(
+        state,
+        NewAuditEvent::new(audit::LOGIN_SUCCEEDED)
+            .user(user.id)
+            .request(request)
+            .metadata(json!({ "method": method, "session_id": session_id })),
+    )
+    .await;
+
+    // the user is signed in either way, a failed check only costs the notification
+    if let Err(e) = check_new_device(state, &user, session_id, request).await {
+        eprintln!("Failed to check for a new device: {}", e);
+    }
 
+    // build the response
     let response = LoginUserResponse {
-        user: user_data,
+        user: UserData::from_user(user),
         refresh_token,
         access_token,
     };
 
-    Ok(Json(response))
+    Ok((jar, Json(response)))
+}
+
+async fn audit_failed_login(
+    state: &AppState,
+    request: &RequestMeta,
+    user_id: Option<Uuid>,
+    email: &str,
+    reason: &str,
+) {
+    let mut event = NewAuditEvent::new(audit::LOGIN_FAILED)
+        .request(request)
+        .metadata(json!({ "email": email, "reason": reason }));
+
+    if let Some(user_id) = user_id {
+        event = event.user(user_id);
+    }
+
+    audit::record(state, event).await;
+}
+
+async fn record_failed_login(
+    state: &AppState,
+    throttle_email: &str,
+    client_ip: &str,
+) -> Result<(), StatusCode> {
+    state
+        .login_attempt_repository
+        .record_failure(throttle_email, client_ip)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+async fn lock_account(state: &AppState, user: &User) -> Result<(), StatusCode> {
+    let locked_until = Utc::now() + state.login_throttle.lockout_duration;
+
+    state
+        .user_repository
+        .lock_until(user.id, locked_until)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    // the unlock link stays valid as long as the lock itself
+    let unlock_token = generate_verification_token();
+
+    state
+        .account_unlock_repository
+        .create_token(user.id, &unlock_token, locked_until)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if let Err(e) = state
+        .email_service
+        .send_account_locked_email(
+            &user.email,
+            &user.username,
+            &unlock_token,
+            state.login_throttle.lockout_duration.num_minutes(),
+        )
+        .await
+    {
+        eprintln!("Failed to send account locked email: {}", e);
+        // the lock expires on its own, don't fail the request
+    }
+
+    Ok(())
 }
 
 pub async fn current_user(
@@ -191,6 +619,7 @@ pub async fn current_user(
 
 pub async fn verify_email(
     State(state): State<AppState>,
+    request: RequestMeta,
     axum::extract::Query(params): axum::extract::Query<HashMap<String, String>>,
 ) -> Result<Json<serde_json::Value>, StatusCode> {
     let token = params.get("token").ok_or(StatusCode::BAD_REQUEST)?;
@@ -228,33 +657,226 @@ pub async fn verify_email(
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
+    audit::record(
+        &state,
+        NewAuditEvent::new(audit::EMAIL_VERIFIED)
+            .user(verification_token.user_id)
+            .request(&request),
+    )
+    .await;
+
     Ok(Json(
         serde_json::json!({"message": "Email verified successfully!"}),
     ))
 }
 
-// forgot password - generate token and send email
-pub async fn forgot_password(
+// A new link for accounts whose first one expired, they can't log in to ask for it when
+// UNVERIFIED_EMAIL_ACCESS is none. Answers the same whether or not there's anything to send.
+pub async fn resend_verification(
     State(state): State<AppState>,
-    Json(payload): Json<ForgotPasswordRequest>,
-) -> Result<Json<ForgotPasswordResponse>, StatusCode> {
+    Json(payload): Json<ResendVerificationRequest>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
     payload.validate().map_err(|_| StatusCode::BAD_REQUEST)?;
 
-    // look up user by that email
+    tokio::spawn(async move {
+        if let Err(e) = send_verification_again(&state, &payload.email).await {
+            eprintln!("Failed to resend verification email {}", e);
+        }
+    });
+
+    Ok(Json(serde_json::json!({
+        "message": "If that account still needs verifying, a new link has been sent"
+    })))
+}
+
+pub(crate) async fn send_verification_again(
+    state: &AppState,
+    email: &str,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let Some(user) = state.user_repository.find_by_email(email).await? else {
+        return Ok(());
+    };
+
+    if user.email_verified || user.is_deleted() {
+        return Ok(());
+    }
+
+    let verification_token = generate_verification_token();
+    let expires_at = Utc::now() + Duration::hours(24);
+
+    state
+        .email_verification_repository
+        .create_token(user.id, &verification_token, expires_at)
+        .await?;
+
+    state
+        .email_service
+        .send_verification_email(&user.email, &user.username, &verification_token)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+pub async fn unlock_account(
+    State(state): State<AppState>,
+    axum::extract::Query(params): axum::extract::Query<HashMap<String, String>>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let token = params.get("token").ok_or(StatusCode::BAD_REQUEST)?;
+
+    let unlock_token = state
+        .account_unlock_repository
+        .find_by_token(token)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if unlock_token.is_expired() {
+        state
+            .account_unlock_repository
+            .delete_token(token)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+        return Err(StatusCode::GONE);
+    }
+
+    state
+        .user_repository
+        .unlock(unlock_token.user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    // otherwise the next typo would lock the account right away again
     let user = state
         .user_repository
-        .find_by_email(&payload.email)
+        .find_by_id(unlock_token.user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    state
+        .login_attempt_repository
+        .clear_failures_for_email(&user.email.to_lowercase())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    state
+        .account_unlock_repository
+        .delete_all_user_tokens(unlock_token.user_id)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    if user.is_none() {
-        return Ok(Json(ForgotPasswordResponse {
-            message: "If that email exists, a password reset link has been sent".to_string(),
-        }));
+    Ok(Json(
+        serde_json::json!({"message": "Account unlocked. You can log in again."}),
+    ))
+}
+
+// The "this wasn't me" link of the new sign-in email. Ends the session that sign-in started
+// and sends a password reset link, whoever signed in knew the password.
+pub async fn report_sign_in(
+    State(state): State<AppState>,
+    request: RequestMeta,
+    axum::extract::Query(params): axum::extract::Query<HashMap<String, String>>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let token = params.get("token").ok_or(StatusCode::BAD_REQUEST)?;
+
+    let report_token = state
+        .known_device_repository
+        .consume_report_token(token)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if report_token.is_expired() {
+        return Err(StatusCode::GONE);
     }
 
-    let user = user.unwrap();
+    state
+        .refresh_token_repository
+        .delete_session(report_token.session_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    state.session_cache.revoke(report_token.session_id);
+
+    let user = state
+        .user_repository
+        .find_by_id(report_token.user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    audit::record(
+        &state,
+        NewAuditEvent::new(audit::SIGN_IN_REPORTED)
+            .user(user.id)
+            .request(&request)
+            .metadata(json!({ "session_id": report_token.session_id })),
+    )
+    .await;
+
+    if let Err(e) = send_password_reset(&state, &user.email).await {
+        eprintln!("Failed to send password reset: {}", e);
+        // the session is gone already, forgot-password still works
+    }
+
+    Ok(Json(serde_json::json!({
+        "message": "That sign-in has been logged out. Check your email for a link to choose a new password."
+    })))
+}
+
+// forgot password - generate token and send email
+pub async fn forgot_password(
+    State(state): State<AppState>,
+    request: RequestMeta,
+    Json(payload): Json<ForgotPasswordRequest>,
+) -> Result<Json<ForgotPasswordResponse>, StatusCode> {
+    payload.validate().map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    // answer right away, how long the lookup and email take would tell whether the account exists
+    tokio::spawn(async move {
+        match send_password_reset(&state, &payload.email).await {
+            Ok(Some(user)) => {
+                audit::record(
+                    &state,
+                    NewAuditEvent::new(audit::PASSWORD_RESET_REQUESTED)
+                        .user(user.id)
+                        .request(&request),
+                )
+                .await;
+            }
+            Ok(None) => {}
+            Err(e) => eprintln!("Failed to sent password reset {}", e),
+        }
+    });
+
+    Ok(Json(ForgotPasswordResponse {
+        message: "If that email exists, a password reset link has been sent".to_string(),
+    }))
+}
+
+// the user the link went to, None if there's no account with that email
+pub(crate) async fn send_password_reset(
+    state: &AppState,
+    email: &str,
+) -> Result<Option<User>, Box<dyn std::error::Error + Send + Sync>> {
+    // look up user by that email
+    let Some(user) = state.user_repository.find_by_email(email).await? else {
+        return Ok(None);
+    };
+
+    send_password_reset_to(state, &user, &user.email).await?;
 
+    Ok(Some(user))
+}
+
+// for when the link has to go somewhere other than the account's current address
+pub(crate) async fn send_password_reset_to(
+    state: &AppState,
+    user: &User,
+    email: &str,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     // create reset token
     let reset_token = generate_verification_token();
     let expires_at = Utc::now() + Duration::hours(1); // 1h expiration
@@ -263,28 +885,23 @@ pub async fn forgot_password(
     state
         .password_reset_respository
         .create_token(user.id, &reset_token, expires_at)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .await?;
 
     // send email
     state
         .email_service
-        .send_password_reset_email(&user.email, &user.username, &reset_token)
+        .send_password_reset_email(email, &user.username, &reset_token)
         .await
-        .map_err(|e| {
-            eprintln!("Failed to sent password reset {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
+        .map_err(|e| e.to_string())?;
 
-    Ok(Json(ForgotPasswordResponse {
-        message: "If that email exists, a password reset link has been sent".to_string(),
-    }))
+    Ok(())
 }
 
 pub async fn reset_password(
     State(state): State<AppState>,
+    request: RequestMeta,
     Json(payload): Json<ResetPasswordRequest>,
-) -> Result<Json<ResetPasswordResponse>, StatusCode> {
+) -> Result<Json<ResetPasswordResponse>, ApiError> {
     payload.validate().map_err(|_| StatusCode::BAD_REQUEST)?;
 
     // find password reset by token
@@ -304,9 +921,18 @@ pub async fn reset_password(
             .await
             .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-        return Err(StatusCode::GONE);
+        return Err(StatusCode::GONE.into());
     }
 
+    let user = state
+        .user_repository
+        .find_by_id(reset_token.user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    check_password_policy(&state, &payload.new_password, &user.username, &user.email).await?;
+
     // create new password hash
     let new_password_hash =
         hash_password(&payload.new_password).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
@@ -325,6 +951,40 @@ pub async fn reset_password(
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
+    // whoever knew the old password must not stay logged in
+    state
+        .refresh_token_repository
+        .delete_all_user_tokens(reset_token.user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    state
+        .personal_access_token_repository
+        .delete_all_user_tokens(reset_token.user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    state
+        .user_repository
+        .increment_token_version(reset_token.user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    // proving access to the mailbox is as good as the unlock link
+    state
+        .user_repository
+        .unlock(reset_token.user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    audit::record(
+        &state,
+        NewAuditEvent::new(audit::PASSWORD_RESET_COMPLETED)
+            .user(reset_token.user_id)
+            .request(&request),
+    )
+    .await;
+
     Ok(Json(ResetPasswordResponse {
         message: "Password has been reset successfully. You can now log in with your new password"
             .to_string(),
@@ -333,21 +993,37 @@ pub async fn reset_password(
 
 pub async fn refresh_token(
     State(state): State<AppState>,
-    Json(payload): Json<RefreshTokenRequest>,
-) -> Result<Json<RefreshTokenResponse>, StatusCode> {
+    request: RequestMeta,
+    jar: CookieJar,
+    headers: HeaderMap,
+    payload: Option<Json<RefreshTokenRequest>>,
+) -> Result<(CookieJar, Json<RefreshTokenResponse>), StatusCode> {
+    // 0. API clients send the token in the body, browsers in cookie mode via cookie + CSRF header
+    let presented_token = match payload {
+        Some(Json(payload)) => payload.refresh_token,
+        None => state
+            .cookie_config
+            .refresh_token_from_cookies(&jar, &headers)?,
+    };
+
     // 1. check for the provided refresh token
     let refresh_token = state
         .refresh_token_repository
-        .find_by_token(&payload.refresh_token)
+        .find_by_token(&presented_token)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
         .ok_or(StatusCode::UNAUTHORIZED)?;
 
+    // third-party clients refresh through /api/oauth/token, here they'd lose their scope
+    if refresh_token.client_id.is_some() {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
     // 2. check if token is expired
     if refresh_token.is_expired() {
         let _ = state
             .refresh_token_repository
-            .delete_token(&payload.refresh_token)
+            .delete_token(&presented_token)
             .await;
 
         return Err(StatusCode::UNAUTHORIZED);
@@ -357,10 +1033,19 @@ pub async fn refresh_token(
     if refresh_token.is_used {
         // SECURITY BREACH DETECTED! (probably)
         // Someone is using an old token, which means it was probably stolen
-        eprintln!("TOKEN REUSE DETECTED!");
-        eprintln!("Token: {}", &payload.refresh_token);
-        eprintln!("User ID: {}", refresh_token.user_id);
-        eprintln!("Originally used at: {:?}", refresh_token.used_at);
+        eprintln!("TOKEN REUSE DETECTED for user {}", refresh_token.user_id);
+
+        audit::record(
+            &state,
+            NewAuditEvent::new(audit::REFRESH_TOKEN_REUSED)
+                .user(refresh_token.user_id)
+                .request(&request)
+                .metadata(json!({
+                    "session_id": refresh_token.session_id,
+                    "originally_used_at": refresh_token.used_at,
+                })),
+        )
+        .await;
 
         // KILL ALL user's refresh tokens and force them to login again
         state
@@ -369,6 +1054,19 @@ pub async fn refresh_token(
             .await
             .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
+        state
+            .personal_access_token_repository
+            .delete_all_user_tokens(refresh_token.user_id)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+        // access tokens already handed out die with the version bump
+        state
+            .user_repository
+            .increment_token_version(refresh_token.user_id)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
         let user = state
             .user_repository
             .find_by_id(refresh_token.user_id)
@@ -388,48 +1086,272 @@ pub async fn refresh_token(
         return Err(StatusCode::UNAUTHORIZED);
     }
 
-    // 4. mark old token as used
+    // 4. generate new refresh token within the same session
+    let new_refresh_token = generate_refresh_token();
+
     state
         .refresh_token_repository
-        .mark_token_as_used(&payload.refresh_token)
+        .create_token(
+            refresh_token.user_id,
+            refresh_token.session_id,
+            &new_refresh_token,
+            refresh_token.authenticated_at,
+        )
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    // 5. generate new refresh token
-    let new_refresh_token = generate_refresh_token();
-
+    // 5. only then mark old token as used, the other way round there's a moment where the session
+    // has no unused token and counts as ended, and it stays ended if the insert fails
     state
         .refresh_token_repository
-        .create_token(refresh_token.user_id, &new_refresh_token)
+        .mark_token_as_used(&presented_token)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    // 6. generate new access token
+    // 6. generate new access token with the current token version
+    let user = state
+        .user_repository
+        .find_by_id(refresh_token.user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::UNAUTHORIZED)?;
 
-    let jwt_secret = std::env::var("JWT_SECRET").map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    let access_token = generate_token(&refresh_token.user_id, &jwt_secret)
+    // picks up roles granted or revoked since the last refresh
+    let roles = state
+        .role_repository
+        .find_role_names_for_user(user.id)
+        .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
+    let access_token = generate_token(
+        &user.id,
+        &refresh_token.session_id,
+        user.token_version,
+        roles,
+        refresh_token.authenticated_at,
+        &state.jwt_keys,
+        &state.jwt_config,
+    )
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
     // 7. return BOTH new access token and new refresh token
-    Ok(Json(RefreshTokenResponse {
-        access_token,
-        refresh_token: new_refresh_token,
-    }))
+    let (jar, refresh_token) = deliver_refresh_token(&state, jar, new_refresh_token);
+
+    Ok((
+        jar,
+        Json(RefreshTokenResponse {
+            access_token,
+            refresh_token,
+        }),
+    ))
 }
 
 pub async fn logout(
     State(state): State<AppState>,
-    Json(payload): Json<LogoutRequest>,
-) -> Result<Json<LogoutResponse>, StatusCode> {
-    // simply delete refresh token in payload from database
+    request: RequestMeta,
+    jar: CookieJar,
+    headers: HeaderMap,
+    payload: Option<Json<LogoutRequest>>,
+) -> Result<(CookieJar, Json<LogoutResponse>), StatusCode> {
+    let presented_token = match payload {
+        Some(Json(payload)) => payload.refresh_token,
+        None => state
+            .cookie_config
+            .refresh_token_from_cookies(&jar, &headers)?,
+    };
+
+    // end the whole session, so the access token issued with it stops working too
+    let refresh_token = state
+        .refresh_token_repository
+        .find_by_token(&presented_token)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
+    if let Some(refresh_token) = refresh_token {
+        state
+            .refresh_token_repository
+            .delete_session(refresh_token.session_id)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+        state.session_cache.revoke(refresh_token.session_id);
+
+        audit::record(
+            &state,
+            NewAuditEvent::new(audit::SESSION_REVOKED)
+                .user(refresh_token.user_id)
+                .request(&request)
+                .metadata(json!({ "session_id": refresh_token.session_id })),
+        )
+        .await;
+    }
+
+    let jar = state.cookie_config.clear_session_cookies(jar);
+
+    Ok((
+        jar,
+        Json(LogoutResponse {
+            message: "Logged out successfully".to_string(),
+        }),
+    ))
+}
+
+pub async fn logout_all(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+    request: RequestMeta,
+) -> Result<Json<LogoutResponse>, StatusCode> {
     state
         .refresh_token_repository
-        .delete_token(&payload.refresh_token)
+        .delete_all_user_tokens(user.id)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
+    state
+        .personal_access_token_repository
+        .delete_all_user_tokens(user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    state
+        .user_repository
+        .increment_token_version(user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    audit::record(
+        &state,
+        NewAuditEvent::new(audit::ALL_SESSIONS_REVOKED)
+            .user(user.id)
+            .request(&request),
+    )
+    .await;
+
     Ok(Json(LogoutResponse {
-        message: "Logged out successfully".to_string(),
+        message: "Logged out of all sessions".to_string(),
+    }))
+}
+
+// Step-up for RequireRecentAuth routes. Goes through check_credentials, so wrong passwords count
+// towards the login throttle like any other.
+pub async fn reauthenticate(
+    State(state): State<AppState>,
+    RequireSession { user, session_id }: RequireSession,
+    request: RequestMeta,
+    Json(payload): Json<ReauthenticateRequest>,
+) -> Result<Json<ReauthenticateResponse>, ApiError> {
+    payload.validate().map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    // a 401 would send the frontend off to refresh its token
+    let user = match check_credentials(&state, &request, &user.email, &payload.password).await {
+        Err(ApiError::Status(StatusCode::UNAUTHORIZED)) => {
+            return Err(StatusCode::FORBIDDEN.into());
+        }
+        result => result?,
+    };
+
+    let auth_time = state
+        .refresh_token_repository
+        .mark_reauthenticated(session_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let roles = state
+        .role_repository
+        .find_role_names_for_user(user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let access_token = generate_token(
+        &user.id,
+        &session_id,
+        user.token_version,
+        roles,
+        auth_time,
+        &state.jwt_keys,
+        &state.jwt_config,
+    )
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    audit::record(
+        &state,
+        NewAuditEvent::new(audit::SESSION_REAUTHENTICATED)
+            .user(user.id)
+            .request(&request)
+            .metadata(json!({ "session_id": session_id })),
+    )
+    .await;
+
+    Ok(Json(ReauthenticateResponse {
+        access_token,
+        auth_time,
+    }))
+}
+
+pub async fn change_password(
+    State(state): State<AppState>,
+    RequireRecentAuth { user, session_id }: RequireRecentAuth,
+    request: RequestMeta,
+    Json(payload): Json<ChangePasswordRequest>,
+) -> Result<Json<ChangePasswordResponse>, ApiError> {
+    payload.validate().map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    // a stolen access token alone must not be enough to take over the account
+    let valid_password = verify_password(&payload.current_password, &user.password_hash)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if !valid_password {
+        return Err(StatusCode::FORBIDDEN.into());
+    }
+
+    check_password_policy(&state, &payload.new_password, &user.username, &user.email).await?;
+
+    let password_hash =
+        hash_password(&payload.new_password).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    state
+        .user_repository
+        .reset_password(user.id, &password_hash)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    // everyone else is logged out, the session that changed the password stays
+    let ended_sessions = state
+        .refresh_token_repository
+        .delete_other_sessions(user.id, session_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    for ended_session in &ended_sessions {
+        state.session_cache.revoke(*ended_session);
+    }
+
+    // scripts get new tokens, the old ones may be what the password leaked with
+    state
+        .personal_access_token_repository
+        .delete_all_user_tokens(user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    audit::record(
+        &state,
+        NewAuditEvent::new(audit::PASSWORD_CHANGED)
+            .user(user.id)
+            .request(&request)
+            .metadata(json!({ "ended_sessions": ended_sessions.len() })),
+    )
+    .await;
+
+    if let Err(e) = state
+        .email_service
+        .send_password_changed_email(&user.email, &user.username)
+        .await
+    {
+        eprintln!("Failed to send password changed email: {}", e);
+        // the password is changed already, don't fail the request
+    }
+
+    Ok(Json(ChangePasswordResponse {
+        message: "Password changed successfully".to_string(),
     }))
 }
@@ -0,0 +1,365 @@
+use std::collections::HashMap;
+
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::StatusCode,
+};
+use chrono::{Duration, Utc};
+use serde_json::json;
+use validator::Validate;
+
+use crate::{
+    auth::{audit, middleware::RequireRecentAuth},
+    errors::ApiError,
+    handlers::auth::send_password_reset_to,
+    models::{EMAIL_CHANGE_CONFIRM, EMAIL_CHANGE_REVERT, NewAuditEvent},
+    schemas::{ChangeEmailRequest, ChangeEmailResponse},
+    state::AppState,
+    utils::{RequestMeta, generate_verification_token},
+};
+
+const CONFIRM_TOKEN_TTL_HOURS: i64 = 24;
+// longer than the confirm link, the old address has to be able to undo a change that went through
+const REVERT_TOKEN_TTL_DAYS: i64 = 7;
+
+// Nothing changes yet. The new address gets a confirmation link, the current one a notice with
+// a link that undoes the change.
+pub async fn request_email_change(
+    State(state): State<AppState>,
+    RequireRecentAuth { user, .. }: RequireRecentAuth,
+    request: RequestMeta,
+    Json(payload): Json<ChangeEmailRequest>,
+) -> Result<(StatusCode, Json<ChangeEmailResponse>), ApiError> {
+    payload.validate().map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let new_email = payload.email.trim().to_string();
+
+    // only the case differs, lookups ignore it so it'd be the same address
+    if new_email.to_lowercase() == user.email.to_lowercase() {
+        return Err(ApiError::Validation {
+            field: "email",
+            messages: vec!["That is already your email address".to_string()],
+        });
+    }
+
+    // in domain mode an account keeps a work address, not just signs up with one
+    if !state.registration_config.allows_email(&new_email) {
+        return Err(ApiError::Validation {
+            field: "email",
+            messages: vec!["Sign up with your work email address".to_string()],
+        });
+    }
+
+    // A revert link for an address other than the current one belongs to a confirmed change. It
+    // has to keep working, and another change would give whoever made this one a revert link of
+    // their own, so the next change waits until the old address had its chance.
+    let revert_pending = state
+        .email_change_repository
+        .find_user_tokens(user.id, EMAIL_CHANGE_REVERT)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .iter()
+        .any(|revert_token| !revert_token.is_expired() && revert_token.email != user.email);
+
+    if revert_pending {
+        return Err(ApiError::Validation {
+            field: "email",
+            messages: vec![
+                "Your last email change can still be undone from the old address, try again once that link has expired".to_string(),
+            ],
+        });
+    }
+
+    let response = ChangeEmailResponse {
+        message: "Check the new address for a link to confirm the change".to_string(),
+        pending_email: new_email.clone(),
+    };
+
+    let taken = state
+        .user_repository
+        .find_by_email(&new_email)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .is_some();
+
+    if taken {
+        if !state.registration_config.enumeration_protection {
+            return Err(StatusCode::CONFLICT.into());
+        }
+
+        // same answer as a free address, the confirm step checks again anyway
+        return Ok((StatusCode::ACCEPTED, Json(response)));
+    }
+
+    // a newer request replaces one that wasn't confirmed, its links stop working
+    for purpose in [EMAIL_CHANGE_CONFIRM, EMAIL_CHANGE_REVERT] {
+        state
+            .email_change_repository
+            .delete_user_tokens(user.id, purpose)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+
+    let confirm_token = generate_verification_token();
+    state
+        .email_change_repository
+        .create_token(
+            user.id,
+            EMAIL_CHANGE_CONFIRM,
+            &new_email,
+            &confirm_token,
+            Utc::now() + Duration::hours(CONFIRM_TOKEN_TTL_HOURS),
+        )
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    // remembers the address to go back to
+    let revert_token = generate_verification_token();
+    state
+        .email_change_repository
+        .create_token(
+            user.id,
+            EMAIL_CHANGE_REVERT,
+            &user.email,
+            &revert_token,
+            Utc::now() + Duration::days(REVERT_TOKEN_TTL_DAYS),
+        )
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    state
+        .email_change_repository
+        .set_pending_email(user.id, Some(&new_email))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    state
+        .email_service
+        .send_email_change_confirmation(&new_email, &user.username, &confirm_token)
+        .await
+        .map_err(|e| {
+            eprintln!("Failed to send email change confirmation: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    if let Err(e) = state
+        .email_service
+        .send_email_change_notice(&user.email, &user.username, &new_email, &revert_token)
+        .await
+    {
+        eprintln!("Failed to send email change notice: {}", e);
+        // the change can't go through without the new address confirming it
+    }
+
+    audit::record(
+        &state,
+        NewAuditEvent::new(audit::EMAIL_CHANGE_REQUESTED)
+            .user(user.id)
+            .actor(user.id)
+            .request(&request)
+            .metadata(json!({ "new_email": new_email })),
+    )
+    .await;
+
+    Ok((StatusCode::ACCEPTED, Json(response)))
+}
+
+pub async fn confirm_email_change(
+    State(state): State<AppState>,
+    request: RequestMeta,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let token = params.get("token").ok_or(StatusCode::BAD_REQUEST)?;
+
+    let change_token = state
+        .email_change_repository
+        .consume_token(token, EMAIL_CHANGE_CONFIRM)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if change_token.is_expired() {
+        return Err(StatusCode::GONE.into());
+    }
+
+    let user = state
+        .user_repository
+        .find_by_id(change_token.user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    // reverted or replaced by a newer request in the meantime
+    if user.pending_email.as_deref() != Some(change_token.email.as_str()) {
+        return Err(StatusCode::NOT_FOUND.into());
+    }
+
+    // the allowed domains may have changed since the request
+    if !state.registration_config.allows_email(&change_token.email) {
+        state
+            .email_change_repository
+            .set_pending_email(user.id, None)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+        return Err(ApiError::Validation {
+            field: "email",
+            messages: vec!["Sign up with your work email address".to_string()],
+        });
+    }
+
+    // someone may have registered with it since the request
+    if state
+        .user_repository
+        .find_by_email(&change_token.email)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .is_some()
+    {
+        state
+            .email_change_repository
+            .set_pending_email(user.id, None)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+        return Err(StatusCode::CONFLICT.into());
+    }
+
+    state
+        .email_change_repository
+        .change_email(user.id, &change_token.email)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    audit::record(
+        &state,
+        NewAuditEvent::new(audit::EMAIL_CHANGED)
+            .user(user.id)
+            .request(&request)
+            .metadata(json!({ "old_email": user.email, "new_email": change_token.email })),
+    )
+    .await;
+
+    Ok(Json(json!({
+        "message": "Your email address has been changed"
+    })))
+}
+
+// From the notice sent to the old address. Cancels a pending change or undoes a confirmed one,
+// then treats the account as compromised: every session ends and a password reset goes out.
+// If the old address has been registered since, only the second half happens, and the link
+// stays valid for another try once that's sorted out.
+pub async fn revert_email_change(
+    State(state): State<AppState>,
+    request: RequestMeta,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let token = params.get("token").ok_or(StatusCode::BAD_REQUEST)?;
+
+    let change_token = state
+        .email_change_repository
+        .find_token(token, EMAIL_CHANGE_REVERT)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if change_token.is_expired() {
+        return Err(StatusCode::GONE);
+    }
+
+    let user = state
+        .user_repository
+        .find_by_id(change_token.user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    state
+        .email_change_repository
+        .delete_user_tokens(user.id, EMAIL_CHANGE_CONFIRM)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let original_email = change_token.email;
+    let changed_from = (user.email != original_email).then(|| user.email.clone());
+
+    // the old address was free to register once the change went through
+    let original_taken = changed_from.is_some()
+        && state
+            .user_repository
+            .find_by_email(&original_email)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            .is_some();
+
+    if !original_taken {
+        // a second click racing this one gets the 404
+        state
+            .email_change_repository
+            .consume_token(token, EMAIL_CHANGE_REVERT)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            .ok_or(StatusCode::NOT_FOUND)?;
+
+        if changed_from.is_some() {
+            state
+                .email_change_repository
+                .change_email(user.id, &original_email)
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        } else {
+            state
+                .email_change_repository
+                .set_pending_email(user.id, None)
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        }
+    }
+
+    state
+        .refresh_token_repository
+        .delete_all_user_tokens(user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    state
+        .personal_access_token_repository
+        .delete_all_user_tokens(user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    state
+        .user_repository
+        .increment_token_version(user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    audit::record(
+        &state,
+        NewAuditEvent::new(audit::EMAIL_CHANGE_REVERTED)
+            .user(user.id)
+            .request(&request)
+            .metadata(json!({
+                "email": original_email,
+                "pending_email": user.pending_email,
+                "reverted_from": changed_from,
+                "original_taken": original_taken,
+            })),
+    )
+    .await;
+
+    // to the address the owner still reads, with the change undone that's also the account's
+    if let Err(e) = send_password_reset_to(&state, &user, &original_email).await {
+        eprintln!("Failed to send password reset: {}", e);
+        // the sessions are gone already, forgot-password still works
+    }
+
+    if original_taken {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    Ok(Json(json!({
+        "message": "The email change has been undone and every device logged out. Check your email for a link to choose a new password."
+    })))
+}
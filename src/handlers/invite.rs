@@ -0,0 +1,213 @@
+use std::collections::HashMap;
+
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use chrono::{Duration, Utc};
+use serde_json::json;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    auth::{
+        audit,
+        middleware::{RequireAuth, RequireVerified},
+        roles::{InvitesManage, Permission},
+        tokens::generate_secure_token,
+    },
+    errors::ApiError,
+    models::NewAuditEvent,
+    schemas::{
+        CreateInviteRequest, CreateInviteResponse, InviteData, InviteUserData, InvitesResponse,
+    },
+    state::AppState,
+    utils::RequestMeta,
+};
+
+// Single or multi-use codes for REGISTRATION_MODE=invite. Admins (invites:manage) hand out as
+// many as they like, everyone else is limited by their quota, counted in people.
+pub async fn create_invite(
+    State(state): State<AppState>,
+    RequireVerified(user): RequireVerified,
+    request: RequestMeta,
+    payload: Option<Json<CreateInviteRequest>>,
+) -> Result<(StatusCode, Json<CreateInviteResponse>), ApiError> {
+    let Json(payload) = payload.unwrap_or_default();
+    payload.validate().map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let max_uses = payload.max_uses.unwrap_or(1);
+    let invite_ttl = state.registration_config.invite_ttl;
+    let mut ttl = payload
+        .expires_in_days
+        .map(Duration::days)
+        .unwrap_or(invite_ttl);
+
+    let unlimited = has_unlimited_invites(&state, user.id).await?;
+    if !unlimited {
+        ttl = ttl.min(invite_ttl);
+    }
+
+    let code = generate_secure_token();
+    let expires_at = Utc::now() + ttl;
+
+    // checked and inserted together, separately two requests could both fit in what's left
+    let invite = if unlimited {
+        state
+            .invite_repository
+            .create(user.id, &code, max_uses, expires_at)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    } else {
+        state
+            .invite_repository
+            .create_within_quota(
+                user.id,
+                &code,
+                max_uses,
+                expires_at,
+                state.registration_config.default_invite_quota,
+            )
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            .ok_or(ApiError::Forbidden {
+                error: "invite_quota_exceeded",
+            })?
+    };
+
+    audit::record(
+        &state,
+        NewAuditEvent::new(audit::INVITE_CREATED)
+            .user(user.id)
+            .actor(user.id)
+            .request(&request)
+            .metadata(json!({
+                "invite_id": invite.id,
+                "max_uses": invite.max_uses,
+                "expires_at": invite.expires_at,
+            })),
+    )
+    .await;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(CreateInviteResponse {
+            invite: InviteData::from_invite(invite, Vec::new()),
+            code,
+        }),
+    ))
+}
+
+pub async fn list_invites(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+) -> Result<Json<InvitesResponse>, StatusCode> {
+    Ok(Json(invites_response(&state, user.id).await?))
+}
+
+// revoked rather than deleted, the people who already joined with it stay listed
+pub async fn revoke_invite(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+    request: RequestMeta,
+    Path(invite_id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let revoked = state
+        .invite_repository
+        .revoke(invite_id, user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if !revoked {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    audit::record(
+        &state,
+        NewAuditEvent::new(audit::INVITE_REVOKED)
+            .user(user.id)
+            .actor(user.id)
+            .request(&request)
+            .metadata(json!({ "invite_id": invite_id })),
+    )
+    .await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// a user's invites, who joined with them and who invited the user, also used by the admin API
+pub(crate) async fn invites_response(
+    state: &AppState,
+    user_id: Uuid,
+) -> Result<InvitesResponse, StatusCode> {
+    let invites = state
+        .invite_repository
+        .find_by_creator(user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let redemptions = state
+        .invite_repository
+        .find_redemptions_by_creator(user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut invited_users: HashMap<Uuid, Vec<InviteUserData>> = HashMap::new();
+    for redemption in redemptions {
+        invited_users
+            .entry(redemption.invite_id)
+            .or_default()
+            .push(InviteUserData::from_redemption(redemption));
+    }
+
+    let invited_by = state
+        .invite_repository
+        .find_inviter(user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .map(InviteUserData::from_redemption);
+
+    Ok(InvitesResponse {
+        invites: invites
+            .into_iter()
+            .map(|invite| {
+                let users = invited_users.remove(&invite.id).unwrap_or_default();
+                InviteData::from_invite(invite, users)
+            })
+            .collect(),
+        remaining_invites: remaining_invites(state, user_id).await?,
+        invited_by,
+    })
+}
+
+// invites:manage, there's no quota then
+async fn has_unlimited_invites(state: &AppState, user_id: Uuid) -> Result<bool, StatusCode> {
+    state
+        .role_repository
+        .user_has_permission(user_id, InvitesManage::NAME)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+// None for invites:manage, there's no limit then
+async fn remaining_invites(state: &AppState, user_id: Uuid) -> Result<Option<i64>, StatusCode> {
+    if has_unlimited_invites(state, user_id).await? {
+        return Ok(None);
+    }
+
+    let quota = state
+        .invite_repository
+        .find_quota(user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .unwrap_or(state.registration_config.default_invite_quota);
+
+    let reserved = state
+        .invite_repository
+        .count_reserved_uses(user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Some((i64::from(quota) - reserved).max(0)))
+}
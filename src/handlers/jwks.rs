@@ -0,0 +1,9 @@
+use axum::{Json, extract::State};
+use jsonwebtoken::jwk::JwkSet;
+
+use crate::state::AppState;
+
+// public keys other services use to verify our access tokens on their own
+pub async fn jwks(State(state): State<AppState>) -> Json<JwkSet> {
+    Json(state.jwt_keys.jwks())
+}
@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::StatusCode,
+};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::Utc;
+use subtle::ConstantTimeEq;
+use validator::Validate;
+
+use crate::{
+    auth::{
+        cookies::MAGIC_LINK_NONCE_COOKIE,
+        tokens::{generate_secure_token, hash_token},
+    },
+    errors::ApiError,
+    handlers::auth::start_session,
+    schemas::{LoginUserResponse, MagicLinkRequest, MagicLinkResponse},
+    state::AppState,
+    utils::{RequestMeta, generate_verification_token},
+};
+
+pub async fn request_magic_link(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Json(payload): Json<MagicLinkRequest>,
+) -> Result<(CookieJar, Json<MagicLinkResponse>), StatusCode> {
+    payload.validate().map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    // set for unknown emails too, the response must not tell them apart
+    let nonce = generate_secure_token();
+    let jar = state
+        .cookie_config
+        .set_magic_link_nonce(jar, &nonce, state.magic_link_config.ttl);
+
+    // like forgot_password, the lookup and email happen off the response path
+    tokio::spawn(async move {
+        if let Err(e) = send_magic_link(&state, &payload.email, &nonce).await {
+            eprintln!("Failed to send magic link {}", e);
+        }
+    });
+
+    Ok((
+        jar,
+        Json(MagicLinkResponse {
+            message: "If that email exists, a sign-in link has been sent".to_string(),
+        }),
+    ))
+}
+
+async fn send_magic_link(
+    state: &AppState,
+    email: &str,
+    nonce: &str,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let Some(user) = state.user_repository.find_by_email(email).await? else {
+        return Ok(());
+    };
+
+    let token = generate_verification_token();
+    let expires_at = Utc::now() + state.magic_link_config.ttl;
+
+    state
+        .magic_link_repository
+        .create_token(user.id, &token, nonce, expires_at)
+        .await?;
+
+    state
+        .email_service
+        .send_magic_link_email(
+            &user.email,
+            &user.username,
+            &token,
+            state.magic_link_config.ttl.num_minutes(),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+pub async fn consume_magic_link(
+    State(state): State<AppState>,
+    request: RequestMeta,
+    jar: CookieJar,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<(CookieJar, Json<LoginUserResponse>), ApiError> {
+    let token = params.get("token").ok_or(StatusCode::BAD_REQUEST)?;
+
+    let magic_link_token = state
+        .magic_link_repository
+        .find_by_token(token)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    // opened in a different browser than the one that asked for it. the token stays, otherwise
+    // whoever got hold of the link could burn it for the real owner
+    let nonce = jar
+        .get(MAGIC_LINK_NONCE_COOKIE)
+        .map(|cookie| hash_token(cookie.value()))
+        .ok_or(StatusCode::FORBIDDEN)?;
+
+    if !bool::from(
+        nonce
+            .as_bytes()
+            .ct_eq(magic_link_token.nonce_hash.as_bytes()),
+    ) {
+        return Err(StatusCode::FORBIDDEN.into());
+    }
+
+    // single use, whoever deletes it first wins
+    let magic_link_token = state
+        .magic_link_repository
+        .consume_token(token)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if magic_link_token.is_expired() {
+        return Err(StatusCode::GONE.into());
+    }
+
+    let mut user = state
+        .user_repository
+        .find_by_id(magic_link_token.user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if let Some(retry_after_secs) = user.locked_for(Utc::now()) {
+        return Err(ApiError::TooManyRequests { retry_after_secs });
+    }
+
+    if state.magic_link_config.verifies_email && !user.email_verified {
+        state
+            .email_verification_repository
+            .verify_user_email(user.id)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+        user.email_verified = true;
+    }
+
+    // older links for this account are worthless now
+    state
+        .magic_link_repository
+        .delete_all_user_tokens(user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    state
+        .login_attempt_repository
+        .clear_failures_for_email(&user.email.to_lowercase())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let jar = state.cookie_config.clear_magic_link_nonce(jar);
+
+    start_session(&state, jar, user, &request, "magic_link").await
+}
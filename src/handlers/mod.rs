@@ -1,11 +1,40 @@
+pub mod account;
+pub mod admin;
 pub mod auth;
+pub mod email_change;
 pub mod health;
+pub mod invite;
+pub mod jwks;
+pub mod magic_link;
+pub mod oauth_server;
+pub mod personal_access_token;
 pub mod root;
+pub mod security_event;
+pub mod social_login;
 
+pub use account::{delete_account, export_account};
+pub use admin::{
+    get_user, get_user_invites, get_user_roles, grant_role, impersonate_user, list_audit_events,
+    list_roles, list_users, logout_user, revoke_role, send_user_password_reset, set_invite_quota,
+    stop_impersonation, suspend_user, unsuspend_user, verify_user_email,
+};
 pub use auth::{
-    current_user, forgot_password, login, logout, refresh_token, register, reset_password,
+    change_password, current_user, forgot_password, login, logout, logout_all, reauthenticate,
+    refresh_token, register, report_sign_in, resend_verification, reset_password, unlock_account,
     verify_email,
 };
-
+pub use email_change::{confirm_email_change, request_email_change, revert_email_change};
 pub use health::health_check;
+pub use invite::{create_invite, list_invites, revoke_invite};
+pub use jwks::jwks;
+pub use magic_link::{consume_magic_link, request_magic_link};
+pub use oauth_server::{
+    authorize, authorize_page, delete_client, introspect, list_clients, register_client, revoke,
+    token,
+};
+pub use personal_access_token::{create_access_token, delete_access_token, list_access_tokens};
 pub use root::root_handler;
+pub use security_event::list_security_events;
+pub use social_login::{
+    link_identity, list_identities, oauth_authorize, oauth_callback, unlink_identity,
+};
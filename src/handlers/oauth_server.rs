@@ -0,0 +1,763 @@
+use askama::Template;
+use axum::{
+    Form, Json,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{Html, IntoResponse, Redirect, Response},
+};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use chrono::Utc;
+use serde_json::json;
+use subtle::ConstantTimeEq;
+use url::Url;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    auth::{
+        audit,
+        jwt::{ACCESS_TOKEN_TTL_MINUTES, generate_client_token, validate_token},
+        middleware::{RequireAuth, RequireVerified, load_session_user},
+        oauth_server::{
+            AUTHORIZATION_CODE_TTL, is_known_scope, is_registered_redirect_uri,
+            is_valid_redirect_uri, resolve_scope, scope_description, verify_pkce,
+        },
+        tokens::{generate_refresh_token, generate_secure_token, hash_token},
+    },
+    errors::ApiError,
+    handlers::auth::check_credentials,
+    models::{NewAuditEvent, OAuthClient, User},
+    schemas::{
+        AuthorizeDecision, AuthorizeParams, IntrospectionResponse, OAuthClientData,
+        OAuthClientsResponse, RegisterClientRequest, RegisterClientResponse, TokenLookupRequest,
+        TokenRequest, TokenResponse,
+    },
+    state::AppState,
+    utils::RequestMeta,
+};
+
+#[derive(Template)]
+#[template(path = "oauth_consent.html")]
+struct ConsentTemplate {
+    app_name: String,
+    client_name: String,
+    client_id: String,
+    redirect_uri: String,
+    scope: String,
+    scope_descriptions: Vec<&'static str>,
+    state: String,
+    code_challenge: String,
+    email: String,
+    error: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "oauth_error.html")]
+struct OAuthErrorTemplate {
+    app_name: String,
+    message: &'static str,
+}
+
+// an authorization request that passed every check
+struct AuthorizationRequest {
+    client: OAuthClient,
+    redirect_uri: String,
+    scope: String,
+    state: Option<String>,
+    code_challenge: String,
+}
+
+pub async fn authorize_page(
+    State(state): State<AppState>,
+    Query(params): Query<AuthorizeParams>,
+) -> Response {
+    match check_authorization_request(&state, params).await {
+        Ok(request) => render_consent(&state, &request, "", None, StatusCode::OK),
+        Err(response) => response,
+    }
+}
+
+pub async fn authorize(
+    State(state): State<AppState>,
+    request_meta: RequestMeta,
+    Form(decision): Form<AuthorizeDecision>,
+) -> Response {
+    let request = match check_authorization_request(&state, decision.params).await {
+        Ok(request) => request,
+        Err(response) => return response,
+    };
+
+    if decision.decision != "allow" {
+        return error_redirect(
+            &request.redirect_uri,
+            request.state.as_deref(),
+            "access_denied",
+        );
+    }
+
+    let user =
+        match check_credentials(&state, &request_meta, &decision.email, &decision.password).await {
+            Ok(user) => user,
+            Err(ApiError::TooManyRequests { .. }) => {
+                return render_consent(
+                    &state,
+                    &request,
+                    &decision.email,
+                    Some("Too many failed attempts, please try again later"),
+                    StatusCode::TOO_MANY_REQUESTS,
+                );
+            }
+            Err(ApiError::Status(StatusCode::UNAUTHORIZED)) => {
+                return render_consent(
+                    &state,
+                    &request,
+                    &decision.email,
+                    Some("Wrong email or password"),
+                    StatusCode::UNAUTHORIZED,
+                );
+            }
+            Err(e) => return e.into_response(),
+        };
+
+    let code = generate_secure_token();
+
+    if state
+        .oauth_client_repository
+        .create_authorization_code(
+            &code,
+            request.client.id,
+            user.id,
+            &request.redirect_uri,
+            &request.scope,
+            &request.code_challenge,
+            user.token_version,
+            Utc::now() + AUTHORIZATION_CODE_TTL,
+        )
+        .await
+        .is_err()
+    {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+
+    redirect_with(
+        &request.redirect_uri,
+        &[("code", code.as_str())],
+        request.state.as_deref(),
+    )
+}
+
+// Until client and redirect URI check out, errors are shown to the user instead of being sent
+// anywhere, otherwise we'd be an open redirect.
+async fn check_authorization_request(
+    state: &AppState,
+    params: AuthorizeParams,
+) -> Result<AuthorizationRequest, Response> {
+    let client_id = params
+        .client_id
+        .as_deref()
+        .and_then(|client_id| Uuid::parse_str(client_id).ok())
+        .ok_or_else(|| error_page(state, StatusCode::BAD_REQUEST, "Unknown application."))?;
+
+    let client = state
+        .oauth_client_repository
+        .find_by_id(client_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?
+        .ok_or_else(|| error_page(state, StatusCode::BAD_REQUEST, "Unknown application."))?;
+
+    let redirect_uri = params
+        .redirect_uri
+        .filter(|redirect_uri| is_registered_redirect_uri(&client, redirect_uri))
+        .ok_or_else(|| {
+            error_page(
+                state,
+                StatusCode::BAD_REQUEST,
+                "The application sent an invalid redirect address.",
+            )
+        })?;
+
+    let login_state = params.state.filter(|s| !s.is_empty());
+
+    if params.response_type.as_deref() != Some("code") {
+        return Err(error_redirect(
+            &redirect_uri,
+            login_state.as_deref(),
+            "unsupported_response_type",
+        ));
+    }
+
+    // PKCE for everyone, confidential clients included. "plain" would defeat the point
+    let code_challenge = params
+        .code_challenge
+        .filter(|challenge| (43..=128).contains(&challenge.len()))
+        .filter(|_| params.code_challenge_method.as_deref() == Some("S256"))
+        .ok_or_else(|| error_redirect(&redirect_uri, login_state.as_deref(), "invalid_request"))?;
+
+    let scope = resolve_scope(&client, params.scope.as_deref())
+        .ok_or_else(|| error_redirect(&redirect_uri, login_state.as_deref(), "invalid_scope"))?
+        .join(" ");
+
+    Ok(AuthorizationRequest {
+        client,
+        redirect_uri,
+        scope,
+        state: login_state,
+        code_challenge,
+    })
+}
+
+fn render_consent(
+    state: &AppState,
+    request: &AuthorizationRequest,
+    email: &str,
+    error: Option<&str>,
+    status: StatusCode,
+) -> Response {
+    let template = ConsentTemplate {
+        app_name: state.app_name.clone(),
+        client_name: request.client.name.clone(),
+        client_id: request.client.id.to_string(),
+        redirect_uri: request.redirect_uri.clone(),
+        scope: request.scope.clone(),
+        scope_descriptions: request
+            .scope
+            .split_whitespace()
+            .filter_map(scope_description)
+            .collect(),
+        state: request.state.clone().unwrap_or_default(),
+        code_challenge: request.code_challenge.clone(),
+        email: email.to_string(),
+        error: error.map(str::to_string),
+    };
+
+    html_response(status, template.render())
+}
+
+fn error_page(state: &AppState, status: StatusCode, message: &'static str) -> Response {
+    let template = OAuthErrorTemplate {
+        app_name: state.app_name.clone(),
+        message,
+    };
+
+    html_response(status, template.render())
+}
+
+fn html_response(status: StatusCode, html: askama::Result<String>) -> Response {
+    match html {
+        // nobody gets to frame the consent page and trick users into clicking allow
+        Ok(html) => (
+            status,
+            [
+                (header::X_FRAME_OPTIONS, "DENY"),
+                (header::CONTENT_SECURITY_POLICY, "frame-ancestors 'none'"),
+                (header::CACHE_CONTROL, "no-store"),
+            ],
+            Html(html),
+        )
+            .into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to render template: {}", e),
+        )
+            .into_response(),
+    }
+}
+
+fn error_redirect(redirect_uri: &str, login_state: Option<&str>, error: &str) -> Response {
+    redirect_with(redirect_uri, &[("error", error)], login_state)
+}
+
+fn redirect_with(
+    redirect_uri: &str,
+    params: &[(&str, &str)],
+    login_state: Option<&str>,
+) -> Response {
+    let Ok(mut url) = Url::parse(redirect_uri) else {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+
+    {
+        let mut query = url.query_pairs_mut();
+        query.extend_pairs(params);
+
+        if let Some(login_state) = login_state {
+            query.append_pair("state", login_state);
+        }
+    }
+
+    Redirect::to(url.as_str()).into_response()
+}
+
+pub async fn token(
+    State(state): State<AppState>,
+    request: RequestMeta,
+    headers: HeaderMap,
+    Form(payload): Form<TokenRequest>,
+) -> Result<Response, ApiError> {
+    let client = authenticate_client(
+        &state,
+        &headers,
+        payload.client_id.as_deref(),
+        payload.client_secret.as_deref(),
+    )
+    .await?;
+
+    let response = match payload.grant_type.as_str() {
+        "authorization_code" => exchange_authorization_code(&state, &client, &payload).await?,
+        "refresh_token" => refresh_client_token(&state, &client, &payload, &request).await?,
+        _ => return Err(oauth_error("unsupported_grant_type")),
+    };
+
+    // tokens must never end up in a cache
+    Ok(([(header::CACHE_CONTROL, "no-store")], Json(response)).into_response())
+}
+
+async fn exchange_authorization_code(
+    state: &AppState,
+    client: &OAuthClient,
+    payload: &TokenRequest,
+) -> Result<TokenResponse, ApiError> {
+    let (Some(code), Some(redirect_uri), Some(code_verifier)) = (
+        payload.code.as_deref(),
+        payload.redirect_uri.as_deref(),
+        payload.code_verifier.as_deref(),
+    ) else {
+        return Err(oauth_error("invalid_request"));
+    };
+
+    let authorization_code = state
+        .oauth_client_repository
+        .consume_authorization_code(code)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or_else(|| oauth_error("invalid_grant"))?;
+
+    if authorization_code.is_expired()
+        || authorization_code.client_id != client.id
+        || authorization_code.redirect_uri != redirect_uri
+        || !verify_pkce(code_verifier, &authorization_code.code_challenge)
+    {
+        return Err(oauth_error("invalid_grant"));
+    }
+
+    let user = find_grant_user(state, authorization_code.user_id).await?;
+
+    if user.token_version != authorization_code.token_version {
+        return Err(oauth_error("invalid_grant"));
+    }
+
+    // every grant is its own session, so it can be revoked without touching the others
+    issue_tokens(
+        state,
+        client,
+        &user,
+        Uuid::new_v4(),
+        &authorization_code.scope,
+    )
+    .await
+}
+
+async fn refresh_client_token(
+    state: &AppState,
+    client: &OAuthClient,
+    payload: &TokenRequest,
+    request: &RequestMeta,
+) -> Result<TokenResponse, ApiError> {
+    let presented_token = payload
+        .refresh_token
+        .as_deref()
+        .ok_or_else(|| oauth_error("invalid_request"))?;
+
+    let refresh_token = state
+        .refresh_token_repository
+        .find_by_token(presented_token)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .filter(|refresh_token| refresh_token.client_id == Some(client.id))
+        .ok_or_else(|| oauth_error("invalid_grant"))?;
+
+    if refresh_token.is_expired() {
+        let _ = state
+            .refresh_token_repository
+            .delete_token(presented_token)
+            .await;
+
+        return Err(oauth_error("invalid_grant"));
+    }
+
+    // same reuse detection as /api/auth/refresh, but only the grant dies, the user's own
+    // sessions and other clients keep working
+    if refresh_token.is_used {
+        eprintln!(
+            "Refresh token reuse for client {} and user {}",
+            client.id, refresh_token.user_id
+        );
+
+        end_session(state, refresh_token.session_id).await?;
+
+        audit::record(
+            state,
+            NewAuditEvent::new(audit::REFRESH_TOKEN_REUSED)
+                .user(refresh_token.user_id)
+                .request(request)
+                .metadata(json!({
+                    "session_id": refresh_token.session_id,
+                    "client_id": client.id,
+                })),
+        )
+        .await;
+
+        return Err(oauth_error("invalid_grant"));
+    }
+
+    // every version bump deletes the refresh tokens, so the version needs no check here
+    let user = find_grant_user(state, refresh_token.user_id).await?;
+
+    let response = issue_tokens(
+        state,
+        client,
+        &user,
+        refresh_token.session_id,
+        refresh_token.scope.as_deref().unwrap_or_default(),
+    )
+    .await?;
+
+    // after the new refresh token exists, so the session never looks ended in between
+    state
+        .refresh_token_repository
+        .mark_token_as_used(presented_token)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(response)
+}
+
+// the same users load_session_user turns away get no new tokens either
+async fn find_grant_user(state: &AppState, user_id: Uuid) -> Result<User, ApiError> {
+    let user = state
+        .user_repository
+        .find_by_id(user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or_else(|| oauth_error("invalid_grant"))?;
+
+    if user.is_suspended() || user.is_deleted() {
+        return Err(oauth_error("invalid_grant"));
+    }
+
+    Ok(user)
+}
+
+async fn issue_tokens(
+    state: &AppState,
+    client: &OAuthClient,
+    user: &User,
+    session_id: Uuid,
+    scope: &str,
+) -> Result<TokenResponse, ApiError> {
+    let refresh_token = generate_refresh_token();
+
+    state
+        .refresh_token_repository
+        .create_client_token(user.id, session_id, client.id, scope, &refresh_token)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let access_token = generate_client_token(
+        &user.id,
+        &session_id,
+        user.token_version,
+        &client.id,
+        scope,
+        &state.jwt_keys,
+        &state.jwt_config,
+    )
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(TokenResponse {
+        access_token,
+        token_type: "Bearer",
+        expires_in: ACCESS_TOKEN_TTL_MINUTES * 60,
+        refresh_token,
+        scope: scope.to_string(),
+    })
+}
+
+// RFC 7009, answers 200 for unknown tokens too
+pub async fn revoke(
+    State(state): State<AppState>,
+    request: RequestMeta,
+    headers: HeaderMap,
+    Form(payload): Form<TokenLookupRequest>,
+) -> Result<StatusCode, ApiError> {
+    let client = authenticate_client(
+        &state,
+        &headers,
+        payload.client_id.as_deref(),
+        payload.client_secret.as_deref(),
+    )
+    .await?;
+
+    let refresh_token = state
+        .refresh_token_repository
+        .find_by_token(&payload.token)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    // either way the whole grant goes, the refresh token and every access token issued with it
+    let session = match refresh_token {
+        Some(refresh_token) => Some((refresh_token.user_id, refresh_token.session_id))
+            .filter(|_| refresh_token.client_id == Some(client.id)),
+        None => validate_token(&payload.token, &state.jwt_keys, &state.jwt_config)
+            .ok()
+            .filter(|claims| claims.client_id == Some(client.id.to_string()))
+            .and_then(|claims| {
+                Some((
+                    Uuid::parse_str(&claims.sub).ok()?,
+                    Uuid::parse_str(&claims.sid).ok()?,
+                ))
+            }),
+    };
+
+    if let Some((user_id, session_id)) = session {
+        end_session(&state, session_id).await?;
+
+        audit::record(
+            &state,
+            NewAuditEvent::new(audit::SESSION_REVOKED)
+                .user(user_id)
+                .request(&request)
+                .metadata(json!({ "session_id": session_id, "client_id": client.id })),
+        )
+        .await;
+    }
+
+    Ok(StatusCode::OK)
+}
+
+// RFC 7662, for confidential clients and only about their own tokens
+pub async fn introspect(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Form(payload): Form<TokenLookupRequest>,
+) -> Result<Json<IntrospectionResponse>, ApiError> {
+    let client = authenticate_client(
+        &state,
+        &headers,
+        payload.client_id.as_deref(),
+        payload.client_secret.as_deref(),
+    )
+    .await?;
+
+    if !client.is_confidential() {
+        return Err(invalid_client());
+    }
+
+    let client_id = client.id.to_string();
+
+    if let Ok(claims) = validate_token(&payload.token, &state.jwt_keys, &state.jwt_config)
+        && claims.client_id.as_deref() == Some(client_id.as_str())
+    {
+        let active = load_session_user(&state, &claims).await?.is_some();
+
+        if active {
+            return Ok(Json(IntrospectionResponse {
+                active,
+                scope: claims.scope,
+                client_id: Some(client_id),
+                sub: Some(claims.sub),
+                token_type: Some("access_token"),
+                exp: Some(claims.exp as i64),
+                iat: Some(claims.iat as i64),
+            }));
+        }
+    }
+
+    let refresh_token = state
+        .refresh_token_repository
+        .find_by_token(&payload.token)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .filter(|refresh_token| refresh_token.client_id == Some(client.id))
+        .filter(|refresh_token| refresh_token.is_valid());
+
+    Ok(Json(match refresh_token {
+        Some(refresh_token) => IntrospectionResponse {
+            active: true,
+            scope: refresh_token.scope,
+            client_id: Some(client_id),
+            sub: Some(refresh_token.user_id.to_string()),
+            token_type: Some("refresh_token"),
+            exp: Some(refresh_token.expires_at.timestamp()),
+            iat: Some(refresh_token.created_at.timestamp()),
+        },
+        None => IntrospectionResponse::default(),
+    }))
+}
+
+async fn end_session(state: &AppState, session_id: Uuid) -> Result<(), StatusCode> {
+    state
+        .refresh_token_repository
+        .delete_session(session_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    state.session_cache.revoke(session_id);
+
+    Ok(())
+}
+
+// HTTP Basic or client_id/client_secret in the form. Public clients only send their id
+async fn authenticate_client(
+    state: &AppState,
+    headers: &HeaderMap,
+    client_id: Option<&str>,
+    client_secret: Option<&str>,
+) -> Result<OAuthClient, ApiError> {
+    let basic = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Basic "))
+        .and_then(|credentials| STANDARD.decode(credentials.trim()).ok())
+        .and_then(|credentials| String::from_utf8(credentials).ok());
+
+    let (client_id, client_secret) = match &basic {
+        Some(credentials) => {
+            let (id, secret) = credentials.split_once(':').ok_or_else(invalid_client)?;
+            (Some(id), Some(secret))
+        }
+        None => (client_id, client_secret),
+    };
+
+    let client_id = client_id
+        .and_then(|client_id| Uuid::parse_str(client_id).ok())
+        .ok_or_else(invalid_client)?;
+
+    let client = state
+        .oauth_client_repository
+        .find_by_id(client_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or_else(invalid_client)?;
+
+    if let Some(secret_hash) = &client.secret_hash {
+        let secret = client_secret.ok_or_else(invalid_client)?;
+
+        if !bool::from(hash_token(secret).as_bytes().ct_eq(secret_hash.as_bytes())) {
+            return Err(invalid_client());
+        }
+    }
+
+    Ok(client)
+}
+
+fn oauth_error(error: &'static str) -> ApiError {
+    ApiError::OAuth {
+        status: StatusCode::BAD_REQUEST,
+        error,
+    }
+}
+
+fn invalid_client() -> ApiError {
+    ApiError::OAuth {
+        status: StatusCode::UNAUTHORIZED,
+        error: "invalid_client",
+    }
+}
+
+pub async fn register_client(
+    State(state): State<AppState>,
+    RequireVerified(user): RequireVerified,
+    Json(payload): Json<RegisterClientRequest>,
+) -> Result<(StatusCode, Json<RegisterClientResponse>), ApiError> {
+    payload.validate().map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let invalid_uris: Vec<String> = payload
+        .redirect_uris
+        .iter()
+        .filter(|uri| !is_valid_redirect_uri(uri))
+        .map(|uri| format!("{} must be an https URL (http only for localhost)", uri))
+        .collect();
+
+    if !invalid_uris.is_empty() {
+        return Err(ApiError::Validation {
+            field: "redirect_uris",
+            messages: invalid_uris,
+        });
+    }
+
+    let unknown_scopes: Vec<String> = payload
+        .scopes
+        .iter()
+        .filter(|scope| !is_known_scope(scope))
+        .map(|scope| format!("Unknown scope {}", scope))
+        .collect();
+
+    if !unknown_scopes.is_empty() {
+        return Err(ApiError::Validation {
+            field: "scopes",
+            messages: unknown_scopes,
+        });
+    }
+
+    let client_secret = payload.confidential.then(generate_secure_token);
+
+    let client = state
+        .oauth_client_repository
+        .create(
+            user.id,
+            &payload.name,
+            client_secret.as_deref(),
+            &payload.redirect_uris,
+            &payload.scopes,
+        )
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(RegisterClientResponse {
+            client: OAuthClientData::from_client(client),
+            client_secret,
+        }),
+    ))
+}
+
+pub async fn list_clients(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+) -> Result<Json<OAuthClientsResponse>, StatusCode> {
+    let clients = state
+        .oauth_client_repository
+        .find_by_owner(user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(OAuthClientsResponse {
+        clients: clients
+            .into_iter()
+            .map(OAuthClientData::from_client)
+            .collect(),
+    }))
+}
+
+// takes every grant and token of the client with it
+pub async fn delete_client(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+    Path(client_id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let ended_sessions = state
+        .oauth_client_repository
+        .delete(client_id, user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    // access tokens the client still holds stop working right away, not when the cache expires
+    for session_id in ended_sessions {
+        state.session_cache.revoke(session_id);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
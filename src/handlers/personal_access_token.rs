@@ -0,0 +1,136 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    auth::{
+        middleware::{RequireAuth, RequireRecentAuth},
+        oauth_server::is_known_scope,
+        tokens::generate_personal_access_token,
+        verified_email::EMAIL_NOT_VERIFIED,
+    },
+    errors::ApiError,
+    schemas::{
+        AccessTokenData, AccessTokensResponse, CreateAccessTokenRequest, CreateAccessTokenResponse,
+    },
+    state::AppState,
+};
+
+// nobody needs more, and it keeps a stolen session from minting tokens forever
+const MAX_TOKENS_PER_USER: usize = 50;
+
+// when the request doesn't say, a forgotten token shouldn't work forever
+const DEFAULT_EXPIRY_DAYS: i64 = 90;
+
+// Only our own sessions get here, the routes declare no scope so personal access tokens can't
+// create or delete tokens themselves. A token outlives the session that made it, so a stolen
+// access token alone must not be enough.
+pub async fn create_access_token(
+    State(state): State<AppState>,
+    RequireRecentAuth { user, .. }: RequireRecentAuth,
+    Json(payload): Json<CreateAccessTokenRequest>,
+) -> Result<(StatusCode, Json<CreateAccessTokenResponse>), ApiError> {
+    // what RequireVerified would check
+    if !state.verified_email_policy.allows_writes(&user) {
+        return Err(ApiError::Forbidden {
+            error: EMAIL_NOT_VERIFIED,
+        });
+    }
+
+    payload.validate().map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let unknown_scopes: Vec<String> = payload
+        .scopes
+        .iter()
+        .filter(|scope| !is_known_scope(scope))
+        .map(|scope| format!("Unknown scope {}", scope))
+        .collect();
+
+    if !unknown_scopes.is_empty() {
+        return Err(ApiError::Validation {
+            field: "scopes",
+            messages: unknown_scopes,
+        });
+    }
+
+    let existing = state
+        .personal_access_token_repository
+        .find_by_user(user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if existing.len() >= MAX_TOKENS_PER_USER {
+        return Err(ApiError::Validation {
+            field: "access_tokens",
+            messages: vec![format!(
+                "You can have at most {} tokens, delete one first",
+                MAX_TOKENS_PER_USER
+            )],
+        });
+    }
+
+    let token = generate_personal_access_token();
+    let expires_at =
+        Utc::now() + Duration::days(payload.expires_in_days.unwrap_or(DEFAULT_EXPIRY_DAYS));
+
+    let access_token = state
+        .personal_access_token_repository
+        .create(
+            user.id,
+            &payload.name,
+            &token,
+            &payload.scopes,
+            Some(expires_at),
+        )
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(CreateAccessTokenResponse {
+            access_token: AccessTokenData::from_access_token(access_token),
+            token,
+        }),
+    ))
+}
+
+pub async fn list_access_tokens(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+) -> Result<Json<AccessTokensResponse>, StatusCode> {
+    let access_tokens = state
+        .personal_access_token_repository
+        .find_by_user(user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(AccessTokensResponse {
+        access_tokens: access_tokens
+            .into_iter()
+            .map(AccessTokenData::from_access_token)
+            .collect(),
+    }))
+}
+
+pub async fn delete_access_token(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+    Path(token_id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let deleted = state
+        .personal_access_token_repository
+        .delete(token_id, user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if deleted {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(StatusCode::NOT_FOUND)
+    }
+}
@@ -0,0 +1,40 @@
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::StatusCode,
+};
+
+use crate::{
+    auth::middleware::RequireAuth,
+    schemas::{SecurityEventData, SecurityEventParams, SecurityEventsResponse},
+    state::AppState,
+};
+
+const DEFAULT_PAGE_SIZE: i64 = 20;
+const MAX_PAGE_SIZE: i64 = 100;
+
+// sign-ins, password changes and whatever support did to the account, newest first
+pub async fn list_security_events(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+    Query(params): Query<SecurityEventParams>,
+) -> Result<Json<SecurityEventsResponse>, StatusCode> {
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let offset = params.offset.unwrap_or(0).max(0);
+
+    let events = state
+        .audit_event_repository
+        .find_by_user(user.id, limit, offset)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(SecurityEventsResponse {
+        events: events
+            .into_iter()
+            .map(SecurityEventData::from_event)
+            .collect(),
+    }))
+}
@@ -0,0 +1,420 @@
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Redirect, Response},
+};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::{Duration, Utc};
+use rand::{Rng, rngs::OsRng};
+use subtle::ConstantTimeEq;
+use uuid::Uuid;
+
+use crate::{
+    auth::{
+        cookies::OAUTH_STATE_COOKIE,
+        middleware::RequireAuth,
+        password::hash_password,
+        registration::RegistrationMode,
+        social_login::{ExternalIdentity, SocialLoginError},
+        tokens::generate_secure_token,
+        username_policy::normalize_username,
+    },
+    errors::ApiError,
+    handlers::auth::{registration_pending_response, send_verification_again, start_session},
+    models::User,
+    schemas::{
+        AuthorizationUrlResponse, OAuthCallbackParams, UserIdentitiesResponse, UserIdentityData,
+    },
+    state::AppState,
+    utils::RequestMeta,
+};
+
+// how long the user may spend on the provider's login page
+const LOGIN_STATE_TTL_MINUTES: i64 = 10;
+
+// "Sign in with ..." buttons link here directly
+pub async fn oauth_authorize(
+    State(state): State<AppState>,
+    Path(provider): Path<String>,
+    jar: CookieJar,
+) -> Result<(CookieJar, Redirect), StatusCode> {
+    let (jar, authorization_url) = start_authorization(&state, jar, &provider, None).await?;
+
+    Ok((jar, Redirect::to(&authorization_url)))
+}
+
+// linking needs the access token, so the frontend asks for the URL and navigates itself
+pub async fn link_identity(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+    Path(provider): Path<String>,
+    jar: CookieJar,
+) -> Result<(CookieJar, Json<AuthorizationUrlResponse>), StatusCode> {
+    let (jar, authorization_url) =
+        start_authorization(&state, jar, &provider, Some(user.id)).await?;
+
+    Ok((jar, Json(AuthorizationUrlResponse { authorization_url })))
+}
+
+async fn start_authorization(
+    state: &AppState,
+    jar: CookieJar,
+    provider_name: &str,
+    link_user_id: Option<Uuid>,
+) -> Result<(CookieJar, String), StatusCode> {
+    let provider = state
+        .social_login
+        .provider(provider_name)
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let login_state = generate_secure_token();
+    let code_verifier = generate_secure_token();
+    let nonce = generate_secure_token();
+    let ttl = Duration::minutes(LOGIN_STATE_TTL_MINUTES);
+
+    let authorization_url = state
+        .social_login
+        .authorization_url(&provider, &login_state, &code_verifier, &nonce)
+        .await
+        .map_err(|e| {
+            eprintln!("Failed to start {} login: {}", provider.name, e);
+            StatusCode::BAD_GATEWAY
+        })?;
+
+    state
+        .user_identity_repository
+        .create_login_state(
+            &login_state,
+            &provider.name,
+            &code_verifier,
+            &nonce,
+            link_user_id,
+            Utc::now() + ttl,
+        )
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let jar = state.cookie_config.set_oauth_state(jar, &login_state, ttl);
+
+    Ok((jar, authorization_url))
+}
+
+pub async fn oauth_callback(
+    State(state): State<AppState>,
+    request: RequestMeta,
+    Path(provider_name): Path<String>,
+    jar: CookieJar,
+    Query(params): Query<OAuthCallbackParams>,
+) -> Result<Response, ApiError> {
+    if let Some(error) = params.error {
+        eprintln!("{} login failed: {}", provider_name, error);
+        return Err(StatusCode::UNAUTHORIZED.into());
+    }
+
+    let (Some(code), Some(login_state)) = (params.code, params.state) else {
+        return Err(StatusCode::BAD_REQUEST.into());
+    };
+
+    // without this anyone could get a victim's browser to finish a login they started
+    let cookie_state = jar
+        .get(OAUTH_STATE_COOKIE)
+        .map(|cookie| cookie.value().to_string())
+        .ok_or(StatusCode::FORBIDDEN)?;
+
+    if !bool::from(cookie_state.as_bytes().ct_eq(login_state.as_bytes())) {
+        return Err(StatusCode::FORBIDDEN.into());
+    }
+
+    let login_state = state
+        .user_identity_repository
+        .consume_login_state(&login_state)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::BAD_REQUEST)?;
+
+    if login_state.provider != provider_name {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+
+    if login_state.is_expired() {
+        return Err(StatusCode::GONE.into());
+    }
+
+    let provider = state
+        .social_login
+        .provider(&provider_name)
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let identity = state
+        .social_login
+        .exchange_code(
+            &provider,
+            &code,
+            &login_state.code_verifier,
+            &login_state.nonce,
+        )
+        .await
+        .map_err(|e| {
+            eprintln!("{} login failed: {}", provider.name, e);
+            match e {
+                SocialLoginError::Provider(_) => StatusCode::BAD_GATEWAY,
+                SocialLoginError::Rejected(_) => StatusCode::UNAUTHORIZED,
+            }
+        })?;
+
+    let jar = state.cookie_config.clear_oauth_state(jar);
+
+    let existing = state
+        .user_identity_repository
+        .find_by_subject(&provider.name, &identity.subject)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let user = match (login_state.link_user_id, existing) {
+        (Some(user_id), Some(existing)) => {
+            if existing.user_id != user_id {
+                // belongs to somebody else, they'd have to unlink it first
+                return Err(StatusCode::CONFLICT.into());
+            }
+
+            return Ok(
+                (jar, Json(serde_json::json!({"message": "Already linked"}))).into_response(),
+            );
+        }
+        (Some(user_id), None) => {
+            state
+                .user_identity_repository
+                .create(
+                    user_id,
+                    &provider.name,
+                    &identity.subject,
+                    identity.email.as_deref(),
+                )
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+            return Ok((
+                jar,
+                Json(serde_json::json!({"message": format!("Linked {}", provider.name)})),
+            )
+                .into_response());
+        }
+        (None, Some(existing)) => {
+            state
+                .user_identity_repository
+                .update_last_login(existing.id, identity.email.as_deref())
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+            state
+                .user_repository
+                .find_by_id(existing.user_id)
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+                .ok_or(StatusCode::NOT_FOUND)?
+        }
+        (None, None) => {
+            let Some(user) = create_user_from_identity(&state, &identity).await? else {
+                return Ok((jar, registration_pending_response()).into_response());
+            };
+
+            state
+                .user_identity_repository
+                .create(
+                    user.id,
+                    &provider.name,
+                    &identity.subject,
+                    identity.email.as_deref(),
+                )
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+            // Signing in straight away would tell this apart from the answer above. A verified
+            // address is the caller's own, there's nothing to hide from them.
+            if state.registration_config.enumeration_protection && !user.email_verified {
+                let state = state.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = send_verification_again(&state, &user.email).await {
+                        eprintln!("Failed to send verification email {}", e);
+                    }
+                });
+
+                return Ok((jar, registration_pending_response()).into_response());
+            }
+
+            user
+        }
+    };
+
+    if let Some(retry_after_secs) = user.locked_for(Utc::now()) {
+        return Err(ApiError::TooManyRequests { retry_after_secs });
+    }
+
+    Ok(start_session(&state, jar, user, &request, &provider_name)
+        .await?
+        .into_response())
+}
+
+// None when the address already has an account and enumeration protection is on, the owner gets
+// an email and the caller answers like a new registration
+async fn create_user_from_identity(
+    state: &AppState,
+    identity: &ExternalIdentity,
+) -> Result<Option<User>, ApiError> {
+    let email = identity.email.as_deref().ok_or(ApiError::Validation {
+        field: "email",
+        messages: vec!["The provider didn't share an email address".to_string()],
+    })?;
+
+    // there's nowhere to enter an invite code, invited people register with a password first and
+    // link the provider afterwards
+    if state.registration_config.mode == RegistrationMode::Invite {
+        return Err(ApiError::Forbidden {
+            error: "invite_required",
+        });
+    }
+
+    if !state.registration_config.allows_email(email) {
+        return Err(ApiError::Validation {
+            field: "email",
+            messages: vec!["Sign up with your work email address".to_string()],
+        });
+    }
+
+    // Taking over an existing account just because some provider vouches for the address is how
+    // accounts get stolen. The owner can log in and link the provider instead.
+    if let Some(existing_user) = state
+        .user_repository
+        .find_by_email(email)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    {
+        if !state.registration_config.enumeration_protection {
+            return Err(StatusCode::CONFLICT.into());
+        }
+
+        let email_service = state.email_service.clone();
+        tokio::spawn(async move {
+            if let Err(e) = email_service
+                .send_account_exists_email(&existing_user.email, &existing_user.username)
+                .await
+            {
+                eprintln!("Failed to send account exists email {}", e);
+            }
+        });
+
+        return Ok(None);
+    }
+
+    let username = unique_username(state, identity).await?;
+
+    // nobody knows it, "forgot password" sets a real one if they ever want it
+    let password_hash =
+        hash_password(&generate_secure_token()).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut user = state
+        .user_repository
+        .create(&username, email, &password_hash)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if identity.email_verified {
+        state
+            .email_verification_repository
+            .verify_user_email(user.id)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+        user.email_verified = true;
+    }
+
+    Ok(Some(user))
+}
+
+// the provider's username if it's free, otherwise with a number appended
+async fn unique_username(
+    state: &AppState,
+    identity: &ExternalIdentity,
+) -> Result<String, StatusCode> {
+    let source = identity
+        .preferred_username
+        .as_deref()
+        .or_else(|| {
+            identity
+                .email
+                .as_deref()
+                .and_then(|email| email.split('@').next())
+        })
+        .unwrap_or_default();
+
+    let mut base: String = normalize_username(source)
+        .chars()
+        .filter(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'))
+        .take(40)
+        .collect();
+
+    // too short, reserved or mixing alphabets
+    if !state.username_policy.check(&base).is_empty() {
+        base = "user".to_string();
+    }
+
+    let random_suffix = format!("{:06}", OsRng.gen_range(0..1_000_000));
+    let candidates = std::iter::once(base.clone())
+        .chain((2..=20).map(|n| format!("{}{}", base, n)))
+        .chain(std::iter::once(format!("{}{}", base, random_suffix)));
+
+    for candidate in candidates {
+        // "user" itself is reserved, the numbered ones aren't
+        if !state.username_policy.check(&candidate).is_empty() {
+            continue;
+        }
+
+        if !state
+            .user_repository
+            .username_taken(&candidate)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        {
+            return Ok(candidate);
+        }
+    }
+
+    Err(StatusCode::CONFLICT)
+}
+
+pub async fn list_identities(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+) -> Result<Json<UserIdentitiesResponse>, StatusCode> {
+    let identities = state
+        .user_identity_repository
+        .find_by_user(user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(UserIdentitiesResponse {
+        identities: identities
+            .into_iter()
+            .map(UserIdentityData::from_identity)
+            .collect(),
+    }))
+}
+
+pub async fn unlink_identity(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+    Path(provider): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    let deleted = state
+        .user_identity_repository
+        .delete(user.id, &provider)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if deleted {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(StatusCode::NOT_FOUND)
+    }
+}
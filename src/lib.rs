@@ -1,7 +1,9 @@
 // re-exporting all the modules for the API and testing
 pub mod auth;
+pub mod errors;
 pub mod handlers;
 pub mod models;
+pub mod rate_limit;
 pub mod repositories;
 pub mod routers;
 pub mod schemas;
@@ -1,10 +1,10 @@
-use std::env;
+use std::{env, net::SocketAddr};
 
 use axum::{Router, routing::get};
 
 use rw_axum_api::{
-    handlers::{health_check, root_handler},
-    routers::{auth_routes, create_static_asset_router, user_routes},
+    handlers::{health_check, jwks, root_handler},
+    routers::{admin_routes, auth_routes, create_static_asset_router, oauth_routes, user_routes},
     state::AppState,
 };
 use tower_http::trace::TraceLayer;
@@ -32,12 +32,16 @@ async fn main() {
         .route("/", get(root_handler))
         // health check
         .route("/health", get(health_check))
+        // public keys for verifying access tokens
+        .route("/.well-known/jwks.json", get(jwks))
         // api
         .nest(
             "/api",
             Router::new()
-                .merge(user_routes())
-                .nest("/auth", auth_routes()),
+                .merge(user_routes(&app_state))
+                .nest("/auth", auth_routes(&app_state))
+                .nest("/oauth", oauth_routes(&app_state))
+                .nest("/admin", admin_routes(&app_state)),
         )
         // serve static assets
         .merge(create_static_asset_router(&app_state.static_asset_dir))
@@ -51,12 +55,87 @@ async fn main() {
     println!("  POST /api/users                     - Register new user");
     println!("  POST /api/users/login               - Login existing user");
     println!("  GET  /api/user                      - Get current user (requires auth)");
+    println!("  POST /api/user/password             - Change password (requires auth)");
+    println!("  GET  /api/user/identities           - List linked social logins (requires auth)");
+    println!(
+        "  POST /api/user/identities/:provider - Start linking a social login (requires auth)"
+    );
+    println!("  DEL  /api/user/identities/:provider - Unlink a social login (requires auth)");
+    println!("  GET  /api/user/tokens               - List personal access tokens (requires auth)");
+    println!(
+        "  POST /api/user/tokens               - Create a personal access token (requires auth)"
+    );
+    println!(
+        "  DEL  /api/user/tokens/:id           - Revoke a personal access token (requires auth)"
+    );
+    println!(
+        "  GET  /api/user/security-events      - Recent security events of your account (requires auth)"
+    );
+    println!(
+        "  POST /api/user/export               - Download all your data as JSON (requires auth)"
+    );
+    println!("  DEL  /api/user                      - Delete your account (requires auth)");
+    println!(
+        "  PUT  /api/user/email                - Change your email, confirmed by the new address (requires auth)"
+    );
+    println!(
+        "  GET  /api/user/invites              - Your invites and who joined with them (requires auth)"
+    );
+    println!("  POST /api/user/invites              - Create an invite code (requires auth)");
+    println!("  DEL  /api/user/invites/:id          - Revoke an invite (requires auth)");
     println!("  GET  /api/auth/verify-email         - Verify email with token");
+    println!("  POST /api/auth/resend-verification  - Email a new verification link");
+    println!("  GET  /api/auth/unlock-account       - Unlock a locked account with token");
+    println!("  GET  /api/auth/report-sign-in       - Log out an unrecognized sign-in with token");
+    println!("  GET  /api/auth/confirm-email-change - Confirm a new email address with token");
+    println!("  GET  /api/auth/revert-email-change  - Undo an email change with token");
     println!("  POST /api/auth/forgot-password      - Request new password");
     println!("  POST /api/auth/reset-password       - Validate password reset token");
+    println!("  POST /api/auth/magic-link           - Email a sign-in link");
+    println!("  GET  /api/auth/magic-link/consume   - Sign in with a magic link token");
+    println!(
+        "  GET  /api/auth/oauth/:provider      - Sign in with Google, GitHub, any OIDC provider"
+    );
+    println!("  GET  /api/auth/oauth/:provider/callback - Provider redirect target");
     println!("  POST /api/auth/refresh              - Refresh Access-Token");
     println!("  POST /api/auth/logout               - Logout (delete refresh token)");
+    println!("  POST /api/auth/logout-all           - Logout of all sessions (requires auth)");
+    println!("  POST /api/auth/reauthenticate       - Confirm the password for sensitive changes");
+    println!("  GET  /api/oauth/authorize           - Consent page for third-party apps");
+    println!("  POST /api/oauth/token               - Exchange a code or refresh token (clients)");
+    println!("  POST /api/oauth/revoke              - Revoke a client token");
+    println!("  POST /api/oauth/introspect          - Inspect a client token");
+    println!("  GET  /api/oauth/clients             - List your OAuth clients (requires auth)");
+    println!("  POST /api/oauth/clients             - Register an OAuth client (requires auth)");
+    println!("  DEL  /api/oauth/clients/:id         - Delete an OAuth client (requires auth)");
+    println!("  GET  /api/admin/roles               - List roles and their permissions (admin)");
+    println!("  GET  /api/admin/users/:id/roles     - Roles of a user (admin)");
+    println!("  PUT  /api/admin/users/:id/roles/:role - Grant a role (admin)");
+    println!("  DEL  /api/admin/users/:id/roles/:role - Revoke a role (admin)");
+    println!("  GET  /api/admin/users               - Search users (admin)");
+    println!("  GET  /api/admin/users/:id           - User details, sessions and history (admin)");
+    println!("  POST /api/admin/users/:id/verify-email - Mark email as verified (admin)");
+    println!("  POST /api/admin/users/:id/password-reset - Send a password reset link (admin)");
+    println!("  PUT  /api/admin/users/:id/suspension - Suspend an account (admin)");
+    println!("  DEL  /api/admin/users/:id/suspension - Lift a suspension (admin)");
+    println!("  POST /api/admin/users/:id/logout    - End all sessions of a user (admin)");
+    println!("  POST /api/admin/users/:id/impersonate - Act as a user for 10 minutes (admin)");
+    println!("  DEL  /api/admin/impersonations/:sid - End an impersonation (admin)");
+    println!(
+        "  GET  /api/admin/users/:id/invites   - Invites of a user and who invited them (admin)"
+    );
+    println!(
+        "  PUT  /api/admin/users/:id/invite-quota - Set how many people a user may invite (admin)"
+    );
+    println!("  GET  /api/admin/audit-events        - Query the security audit log (admin)");
     println!("  GET  /health                        - Health check");
+    println!("  GET  /.well-known/jwks.json         - Public keys for token verification");
 
-    axum::serve(listener, app).await.unwrap();
+    // connect info gives handlers the peer address for login throttling
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .unwrap();
 }
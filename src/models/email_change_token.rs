@@ -0,0 +1,26 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::prelude::FromRow;
+use uuid::Uuid;
+
+// email_change_tokens.purpose
+pub const EMAIL_CHANGE_CONFIRM: &str = "confirm";
+pub const EMAIL_CHANGE_REVERT: &str = "revert";
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct EmailChangeToken {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub purpose: String,
+    // the new address for confirm, the old one for revert
+    pub email: String,
+    pub token_hash: String,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl EmailChangeToken {
+    pub fn is_expired(&self) -> bool {
+        Utc::now() > self.expires_at
+    }
+}
@@ -0,0 +1,21 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ImpersonationSession {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub admin_id: Uuid,
+    pub reason: String,
+    pub expires_at: DateTime<Utc>,
+    pub ended_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl ImpersonationSession {
+    pub fn is_active(&self) -> bool {
+        self.ended_at.is_none() && Utc::now() < self.expires_at
+    }
+}
@@ -0,0 +1,31 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Invite {
+    pub id: Uuid,
+    pub created_by: Uuid,
+    pub code_hash: String,
+    pub max_uses: i32,
+    pub uses: i32,
+    pub expires_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Invite {
+    pub fn is_usable(&self) -> bool {
+        self.revoked_at.is_none() && self.uses < self.max_uses && Utc::now() < self.expires_at
+    }
+}
+
+// one side of an invite: who joined with it, or for the inviter lookup who handed it out
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct InviteRedemption {
+    pub invite_id: Uuid,
+    pub user_id: Uuid,
+    pub username: String,
+    pub created_at: DateTime<Utc>,
+}
@@ -0,0 +1,11 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::prelude::FromRow;
+
+// aggregate over failed_login_attempts within the throttling window
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct LoginFailureStats {
+    pub count: i64,
+    pub first_attempt_at: Option<DateTime<Utc>>,
+    pub last_attempt_at: Option<DateTime<Utc>>,
+}
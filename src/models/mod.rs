@@ -2,13 +2,37 @@
 
 // A model is a Rust struct that mirrors our database table structure.
 // It’s the bridge between our SQL database and our Rust application.
+pub mod account_unlock_token;
+pub mod audit_event;
+pub mod email_change_token;
 pub mod email_verification_token;
+pub mod impersonation_session;
+pub mod invite;
+pub mod known_device;
+pub mod login_attempt;
+pub mod magic_link_token;
+pub mod oauth_client;
 pub mod password_reset_token;
+pub mod personal_access_token;
 pub mod refresh_token;
+pub mod role;
 pub mod user;
+pub mod user_identity;
 
 // This allows other parts of the application to import simply: use crate::models::User; instead of crate::models::user::User.
+pub use account_unlock_token::AccountUnlockToken;
+pub use audit_event::{AuditEvent, AuditEventFilter, NewAuditEvent};
+pub use email_change_token::{EMAIL_CHANGE_CONFIRM, EMAIL_CHANGE_REVERT, EmailChangeToken};
 pub use email_verification_token::EmailVerificationToken;
+pub use impersonation_session::ImpersonationSession;
+pub use invite::{Invite, InviteRedemption};
+pub use known_device::{KnownDevice, SignInReportToken};
+pub use login_attempt::LoginFailureStats;
+pub use magic_link_token::MagicLinkToken;
+pub use oauth_client::{OAuthAuthorizationCode, OAuthClient};
 pub use password_reset_token::PasswordResetToken;
+pub use personal_access_token::PersonalAccessToken;
 pub use refresh_token::RefreshToken;
+pub use role::Role;
 pub use user::User;
+pub use user_identity::{OAuthLoginState, UserIdentity};
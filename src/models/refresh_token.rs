@@ -7,12 +7,18 @@ use uuid::Uuid;
 pub struct RefreshToken {
     pub id: Uuid,
     pub user_id: Uuid,
-    pub token: String,
+    pub session_id: Uuid,
+    pub token_hash: String,
     pub expires_at: DateTime<Utc>,
     pub is_used: bool,
     pub used_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
     pub last_used_at: DateTime<Utc>,
+    // set for tokens handed to a third-party client, None for our own sessions
+    pub client_id: Option<Uuid>,
+    pub scope: Option<String>,
+    // last password entry in this session, the login itself or a later reauthentication
+    pub authenticated_at: DateTime<Utc>,
 }
 
 impl RefreshToken {
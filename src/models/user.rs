@@ -3,6 +3,8 @@ use serde::{Deserialize, Serialize};
 use sqlx::prelude::FromRow;
 use uuid::Uuid;
 
+use crate::auth::throttle::seconds_until;
+
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct User {
     pub id: Uuid,
@@ -14,4 +16,29 @@ pub struct User {
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub email_verified: bool,
+    pub token_version: i32,
+    pub locked_until: Option<DateTime<Utc>>,
+    pub suspended_at: Option<DateTime<Utc>>,
+    pub suspension_reason: Option<String>,
+    // the user deleted their account, logging in again before it's purged restores it
+    pub deleted_at: Option<DateTime<Utc>>,
+    // requested new address, email only changes once it's confirmed
+    pub pending_email: Option<String>,
+}
+
+impl User {
+    // seconds until a lockout after too many failed logins ends, None if there is none
+    pub fn locked_for(&self, now: DateTime<Utc>) -> Option<u64> {
+        self.locked_until
+            .and_then(|locked_until| seconds_until(locked_until, now))
+    }
+
+    // unlike a lock, only an admin lifts it
+    pub fn is_suspended(&self) -> bool {
+        self.suspended_at.is_some()
+    }
+
+    pub fn is_deleted(&self) -> bool {
+        self.deleted_at.is_some()
+    }
 }
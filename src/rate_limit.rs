@@ -0,0 +1,270 @@
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    env,
+    future::Future,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{Arc, RwLock},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{ConnectInfo, Request},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use tower::{Layer, Service};
+use uuid::Uuid;
+
+use crate::{
+    auth::{
+        jwt::validate_token,
+        middleware::extract_token_from_headers,
+        tokens::{PERSONAL_ACCESS_TOKEN_PREFIX, hash_token},
+    },
+    errors::ApiError,
+    state::AppState,
+};
+
+// counters older than this get cleaned up, so no window may be longer
+pub const MAX_WINDOW_SECONDS: u64 = 86_400;
+
+// how long a personal access token counts for its owner without being looked up again
+const PAT_OWNER_TTL: Duration = Duration::from_secs(60);
+
+// past this size expired owners get swept on insert
+const PAT_OWNER_PRUNE_THRESHOLD: usize = 10_000;
+
+// what a route group counts requests by
+#[derive(Debug, Clone, Copy)]
+pub enum RateLimitKey {
+    Ip,
+    // falls back to the IP for requests without a valid access token
+    User,
+}
+
+#[derive(Debug, Clone)]
+pub struct RateLimitPolicy {
+    pub name: &'static str,
+    pub max_requests: i64,
+    pub window: Duration,
+    pub key: RateLimitKey,
+}
+
+impl RateLimitPolicy {
+    // defaults can be overridden with RATE_LIMIT_<NAME>=<max requests>/<window seconds>
+    pub fn from_env(
+        name: &'static str,
+        max_requests: i64,
+        window_seconds: u64,
+        key: RateLimitKey,
+    ) -> Self {
+        let var = format!("RATE_LIMIT_{}", name.to_uppercase().replace('-', "_"));
+
+        let (max_requests, window_seconds) = env::var(&var)
+            .ok()
+            .and_then(|value| {
+                let (max, window) = value.split_once('/')?;
+                Some((max.trim().parse().ok()?, window.trim().parse().ok()?))
+            })
+            .unwrap_or((max_requests, window_seconds));
+
+        Self {
+            name,
+            max_requests,
+            window: Duration::from_secs(window_seconds.clamp(1, MAX_WINDOW_SECONDS)),
+            key,
+        }
+    }
+}
+
+// Fixed window rate limiting for a group of routes, e.g.
+// `.route_layer(RateLimitLayer::new(state, RateLimitPolicy::from_env(...)))`.
+// Counters live in the store picked by RATE_LIMIT_STORE, so with postgres the limits hold across
+// all instances.
+#[derive(Clone)]
+pub struct RateLimitLayer {
+    state: AppState,
+    policy: Arc<RateLimitPolicy>,
+    pat_owners: Arc<PatOwnerCache>,
+}
+
+impl RateLimitLayer {
+    pub fn new(state: &AppState, policy: RateLimitPolicy) -> Self {
+        Self {
+            state: state.clone(),
+            policy: Arc::new(policy),
+            pat_owners: Arc::new(PatOwnerCache::default()),
+        }
+    }
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimitService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimitService {
+            inner,
+            state: self.state.clone(),
+            policy: Arc::clone(&self.policy),
+            pat_owners: Arc::clone(&self.pat_owners),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RateLimitService<S> {
+    inner: S,
+    state: AppState,
+    policy: Arc<RateLimitPolicy>,
+    pat_owners: Arc<PatOwnerCache>,
+}
+
+impl<S> Service<Request> for RateLimitService<S>
+where
+    S: Service<Request, Response = Response, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        // the clone might not be ready, keep the one poll_ready was called on
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+
+        let state = self.state.clone();
+        let policy = Arc::clone(&self.policy);
+        let pat_owners = Arc::clone(&self.pat_owners);
+
+        // taken out here, the request can't be held across an await
+        let token = match policy.key {
+            RateLimitKey::User => extract_token_from_headers(request.headers()),
+            RateLimitKey::Ip => None,
+        };
+        let ip = ip_key(&state, &request);
+
+        Box::pin(async move {
+            let client = client_key(&state, &pat_owners, token, ip).await;
+
+            if let Some(retry_after_secs) = check_rate_limit(&state, &policy, &client).await {
+                return Ok(ApiError::TooManyRequests { retry_after_secs }.into_response());
+            }
+
+            inner.call(request).await
+        })
+    }
+}
+
+// seconds until the next window if the limit is exceeded
+async fn check_rate_limit(state: &AppState, policy: &RateLimitPolicy, client: &str) -> Option<u64> {
+    let key = format!("{}:{}", policy.name, client);
+
+    let now = Utc::now();
+    let window_seconds = policy.window.as_secs() as i64;
+    let window_start_ts = now.timestamp() - now.timestamp().rem_euclid(window_seconds);
+    let window_start = DateTime::from_timestamp(window_start_ts, 0)?;
+
+    let count = match state.rate_limit_repository.hit(&key, window_start).await {
+        Ok(count) => count,
+        Err(e) => {
+            // rather let requests through than take the API down with the store
+            eprintln!("Rate limit store failed for {}: {}", policy.name, e);
+            return None;
+        }
+    };
+
+    if count <= policy.max_requests {
+        return None;
+    }
+
+    Some((window_start_ts + window_seconds - now.timestamp()).max(1) as u64)
+}
+
+async fn client_key(
+    state: &AppState,
+    pat_owners: &PatOwnerCache,
+    token: Option<String>,
+    ip: String,
+) -> String {
+    let Some(token) = token else {
+        return ip;
+    };
+
+    // made-up tokens count against the IP, or every request could bring its own fresh bucket
+    if token.starts_with(PERSONAL_ACCESS_TOKEN_PREFIX) {
+        return match pat_owners.resolve(state, &token).await {
+            Some(user_id) => format!("user:{}", user_id),
+            None => ip,
+        };
+    }
+
+    // a valid signature is enough here, revocation is RequireAuth's job
+    match validate_token(&token, &state.jwt_keys, &state.jwt_config) {
+        Ok(claims) => format!("user:{}", claims.sub),
+        Err(_) => ip,
+    }
+}
+
+fn ip_key(state: &AppState, request: &Request) -> String {
+    let ip = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| state.trusted_proxies.resolve(addr.ip(), request.headers()));
+
+    match ip {
+        Some(ip) => format!("ip:{}", ip),
+        None => "ip:unknown".to_string(),
+    }
+}
+
+// Owners of real personal access tokens, by token hash, so a busy script doesn't cost a query per
+// request. Unknown tokens aren't cached, anyone can make up as many of those as they like.
+#[derive(Default)]
+struct PatOwnerCache {
+    entries: RwLock<HashMap<String, (Uuid, Instant)>>,
+}
+
+impl PatOwnerCache {
+    async fn resolve(&self, state: &AppState, token: &str) -> Option<Uuid> {
+        let token_hash = hash_token(token);
+
+        if let Ok(entries) = self.entries.read()
+            && let Some((user_id, cached_at)) = entries.get(&token_hash)
+            && cached_at.elapsed() < PAT_OWNER_TTL
+        {
+            return Some(*user_id);
+        }
+
+        let user_id = match state
+            .personal_access_token_repository
+            .find_by_token(token)
+            .await
+        {
+            Ok(access_token) => access_token
+                .filter(|access_token| !access_token.is_expired())
+                .map(|access_token| access_token.user_id)?,
+            Err(e) => {
+                eprintln!("Personal access token lookup failed: {}", e);
+                return None;
+            }
+        };
+
+        if let Ok(mut entries) = self.entries.write() {
+            if entries.len() >= PAT_OWNER_PRUNE_THRESHOLD {
+                entries.retain(|_, (_, cached_at)| cached_at.elapsed() < PAT_OWNER_TTL);
+            }
+
+            entries.insert(token_hash, (user_id, Instant::now()));
+        }
+
+        Some(user_id)
+    }
+}
@@ -0,0 +1,88 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    auth::tokens::hash_token, models::AccountUnlockToken,
+    repositories::AccountUnlockRepositoryTrait,
+};
+
+#[derive(Clone)]
+pub struct AccountUnlockRepository {
+    db: PgPool,
+}
+
+impl AccountUnlockRepository {
+    pub fn new(db: PgPool) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl AccountUnlockRepositoryTrait for AccountUnlockRepository {
+    async fn create_token(
+        &self,
+        user_id: Uuid,
+        token: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<AccountUnlockToken, sqlx::Error> {
+        let unlock_token = sqlx::query_as::<_, AccountUnlockToken>(
+            r#"
+            INSERT INTO account_unlock_tokens (user_id, token_hash, expires_at)
+            VALUES ($1, $2, $3)
+            RETURNING id, user_id, token_hash, created_at, expires_at
+            "#,
+        )
+        .bind(user_id)
+        .bind(hash_token(token))
+        .bind(expires_at)
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(unlock_token)
+    }
+
+    async fn find_by_token(&self, token: &str) -> Result<Option<AccountUnlockToken>, sqlx::Error> {
+        let unlock_token = sqlx::query_as::<_, AccountUnlockToken>(
+            r#"
+            SELECT id, user_id, token_hash, created_at, expires_at
+            FROM account_unlock_tokens
+            WHERE token_hash = $1
+            "#,
+        )
+        .bind(hash_token(token))
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(unlock_token)
+    }
+
+    async fn delete_token(&self, token: &str) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            DELETE FROM account_unlock_tokens
+            WHERE token_hash = $1
+            "#,
+        )
+        .bind(hash_token(token))
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn delete_all_user_tokens(&self, user_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            DELETE FROM account_unlock_tokens
+            WHERE user_id = $1
+            "#,
+        )
+        .bind(user_id)
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,124 @@
+use async_trait::async_trait;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    models::{AuditEvent, AuditEventFilter, NewAuditEvent},
+    repositories::AuditEventRepositoryTrait,
+};
+
+#[derive(Clone)]
+pub struct AuditEventRepository {
+    db: PgPool,
+}
+
+impl AuditEventRepository {
+    pub fn new(db: PgPool) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl AuditEventRepositoryTrait for AuditEventRepository {
+    async fn record(&self, event: &NewAuditEvent) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO audit_events (event_type, user_id, actor_id, ip_address, user_agent, metadata)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+        )
+        .bind(event.event_type)
+        .bind(event.user_id)
+        .bind(event.actor_id)
+        .bind(&event.ip_address)
+        .bind(&event.user_agent)
+        .bind(&event.metadata)
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn find_by_user(
+        &self,
+        user_id: Uuid,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<AuditEvent>, sqlx::Error> {
+        let events = sqlx::query_as::<_, AuditEvent>(
+            r#"
+            SELECT id, event_type, user_id, actor_id, ip_address, user_agent, metadata, created_at
+            FROM audit_events
+            WHERE user_id = $1
+            ORDER BY created_at DESC, id
+            LIMIT $2 OFFSET $3
+            "#,
+        )
+        .bind(user_id)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(events)
+    }
+
+    async fn search(
+        &self,
+        filter: &AuditEventFilter,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<AuditEvent>, sqlx::Error> {
+        let events = sqlx::query_as::<_, AuditEvent>(
+            r#"
+            SELECT id, event_type, user_id, actor_id, ip_address, user_agent, metadata, created_at
+            FROM audit_events
+            WHERE ($1::UUID IS NULL OR user_id = $1)
+              AND ($2::UUID IS NULL OR actor_id = $2)
+              AND ($3::TEXT IS NULL OR event_type = $3)
+              AND ($4::TEXT IS NULL OR ip_address = $4)
+              AND ($5::TIMESTAMPTZ IS NULL OR created_at >= $5)
+              AND ($6::TIMESTAMPTZ IS NULL OR created_at < $6)
+            ORDER BY created_at DESC, id
+            LIMIT $7 OFFSET $8
+            "#,
+        )
+        .bind(filter.user_id)
+        .bind(filter.actor_id)
+        .bind(&filter.event_type)
+        .bind(&filter.ip_address)
+        .bind(filter.since)
+        .bind(filter.until)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(events)
+    }
+
+    async fn count(&self, filter: &AuditEventFilter) -> Result<i64, sqlx::Error> {
+        let count = sqlx::query_scalar::<_, i64>(
+            r#"
+            SELECT COUNT(*)
+            FROM audit_events
+            WHERE ($1::UUID IS NULL OR user_id = $1)
+              AND ($2::UUID IS NULL OR actor_id = $2)
+              AND ($3::TEXT IS NULL OR event_type = $3)
+              AND ($4::TEXT IS NULL OR ip_address = $4)
+              AND ($5::TIMESTAMPTZ IS NULL OR created_at >= $5)
+              AND ($6::TIMESTAMPTZ IS NULL OR created_at < $6)
+            "#,
+        )
+        .bind(filter.user_id)
+        .bind(filter.actor_id)
+        .bind(&filter.event_type)
+        .bind(&filter.ip_address)
+        .bind(filter.since)
+        .bind(filter.until)
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(count)
+    }
+}
@@ -0,0 +1,184 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    auth::tokens::hash_token, models::EmailChangeToken, repositories::EmailChangeRepositoryTrait,
+};
+
+#[derive(Clone)]
+pub struct EmailChangeRepository {
+    db: PgPool,
+}
+
+impl EmailChangeRepository {
+    pub fn new(db: PgPool) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl EmailChangeRepositoryTrait for EmailChangeRepository {
+    async fn create_token(
+        &self,
+        user_id: Uuid,
+        purpose: &str,
+        email: &str,
+        token: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<EmailChangeToken, sqlx::Error> {
+        let change_token = sqlx::query_as::<_, EmailChangeToken>(
+            r#"
+            INSERT INTO email_change_tokens (user_id, purpose, email, token_hash, expires_at)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING id, user_id, purpose, email, token_hash, expires_at, created_at
+            "#,
+        )
+        .bind(user_id)
+        .bind(purpose)
+        .bind(email)
+        .bind(hash_token(token))
+        .bind(expires_at)
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(change_token)
+    }
+
+    async fn find_token(
+        &self,
+        token: &str,
+        purpose: &str,
+    ) -> Result<Option<EmailChangeToken>, sqlx::Error> {
+        let change_token = sqlx::query_as::<_, EmailChangeToken>(
+            r#"
+            SELECT id, user_id, purpose, email, token_hash, expires_at, created_at
+            FROM email_change_tokens
+            WHERE token_hash = $1 AND purpose = $2
+            "#,
+        )
+        .bind(hash_token(token))
+        .bind(purpose)
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(change_token)
+    }
+
+    async fn consume_token(
+        &self,
+        token: &str,
+        purpose: &str,
+    ) -> Result<Option<EmailChangeToken>, sqlx::Error> {
+        let change_token = sqlx::query_as::<_, EmailChangeToken>(
+            r#"
+            DELETE FROM email_change_tokens
+            WHERE token_hash = $1 AND purpose = $2
+            RETURNING id, user_id, purpose, email, token_hash, expires_at, created_at
+            "#,
+        )
+        .bind(hash_token(token))
+        .bind(purpose)
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(change_token)
+    }
+
+    async fn find_user_tokens(
+        &self,
+        user_id: Uuid,
+        purpose: &str,
+    ) -> Result<Vec<EmailChangeToken>, sqlx::Error> {
+        let change_tokens = sqlx::query_as::<_, EmailChangeToken>(
+            r#"
+            SELECT id, user_id, purpose, email, token_hash, expires_at, created_at
+            FROM email_change_tokens
+            WHERE user_id = $1 AND purpose = $2
+            "#,
+        )
+        .bind(user_id)
+        .bind(purpose)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(change_tokens)
+    }
+
+    async fn delete_user_tokens(&self, user_id: Uuid, purpose: &str) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            DELETE FROM email_change_tokens
+            WHERE user_id = $1 AND purpose = $2
+            "#,
+        )
+        .bind(user_id)
+        .bind(purpose)
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn set_pending_email(
+        &self,
+        user_id: Uuid,
+        email: Option<&str>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE users
+            SET pending_email = $2
+            WHERE id = $1
+            "#,
+        )
+        .bind(user_id)
+        .bind(email)
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn change_email(&self, user_id: Uuid, email: &str) -> Result<(), sqlx::Error> {
+        // whoever followed the link proved the address is theirs
+        sqlx::query(
+            r#"
+            UPDATE users
+            SET email = $2, pending_email = NULL, email_verified = TRUE
+            WHERE id = $1
+            "#,
+        )
+        .bind(user_id)
+        .bind(email)
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn delete_expired_tokens(&self) -> Result<(), sqlx::Error> {
+        // an unconfirmed address doesn't stay pending forever
+        sqlx::query(
+            r#"
+            WITH expired AS (
+                DELETE FROM email_change_tokens
+                WHERE expires_at < NOW()
+                RETURNING user_id, purpose
+            )
+            UPDATE users
+            SET pending_email = NULL
+            WHERE id IN (SELECT user_id FROM expired WHERE purpose = 'confirm')
+              AND NOT EXISTS (
+                  SELECT 1 FROM email_change_tokens t
+                  WHERE t.user_id = users.id AND t.purpose = 'confirm' AND t.expires_at >= NOW()
+              )
+            "#,
+        )
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+}
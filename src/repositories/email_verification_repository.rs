@@ -3,7 +3,10 @@ use chrono::{DateTime, Utc};
 use sqlx::PgPool;
 use uuid::Uuid;
 
-use crate::{models::EmailVerificationToken, repositories::EmailVerificationRepositoryTrait};
+use crate::{
+    auth::tokens::hash_token, models::EmailVerificationToken,
+    repositories::EmailVerificationRepositoryTrait,
+};
 
 #[derive(Clone)]
 pub struct EmailVerificationRepository {
@@ -26,13 +29,13 @@ impl EmailVerificationRepositoryTrait for EmailVerificationRepository {
     ) -> Result<EmailVerificationToken, sqlx::Error> {
         let verification_token = sqlx::query_as::<_, EmailVerificationToken>(
             r#"
-            INSERT INTO email_verification_tokens (user_id, token, expires_at)
+            INSERT INTO email_verification_tokens (user_id, token_hash, expires_at)
             VALUES ($1, $2, $3)
-            RETURNING id, user_id, token, created_at, expires_at
+            RETURNING id, user_id, token_hash, created_at, expires_at
             "#,
         )
         .bind(user_id)
-        .bind(token)
+        .bind(hash_token(token))
         .bind(expires_at)
         .fetch_one(&self.db)
         .await?;
@@ -46,12 +49,12 @@ impl EmailVerificationRepositoryTrait for EmailVerificationRepository {
     ) -> Result<Option<EmailVerificationToken>, sqlx::Error> {
         let verification_token = sqlx::query_as::<_, EmailVerificationToken>(
             r#"
-            SELECT id, user_id, token, created_at, expires_at
+            SELECT id, user_id, token_hash, created_at, expires_at
             FROM email_verification_tokens
-            WHERE token = $1
+            WHERE token_hash = $1
             "#,
         )
-        .bind(token)
+        .bind(hash_token(token))
         .fetch_optional(&self.db)
         .await?;
 
@@ -62,10 +65,10 @@ impl EmailVerificationRepositoryTrait for EmailVerificationRepository {
         sqlx::query(
             r#"
             DELETE FROM email_verification_tokens
-            WHERE token = $1
+            WHERE token_hash = $1
             "#,
         )
-        .bind(token)
+        .bind(hash_token(token))
         .execute(&self.db)
         .await?;
 
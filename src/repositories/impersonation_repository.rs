@@ -0,0 +1,109 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{models::ImpersonationSession, repositories::ImpersonationRepositoryTrait};
+
+#[derive(Clone)]
+pub struct ImpersonationRepository {
+    db: PgPool,
+}
+
+impl ImpersonationRepository {
+    pub fn new(db: PgPool) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl ImpersonationRepositoryTrait for ImpersonationRepository {
+    async fn create(
+        &self,
+        user_id: Uuid,
+        admin_id: Uuid,
+        reason: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<ImpersonationSession, sqlx::Error> {
+        let session = sqlx::query_as::<_, ImpersonationSession>(
+            r#"
+            INSERT INTO impersonation_sessions (user_id, admin_id, reason, expires_at)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id, user_id, admin_id, reason, expires_at, ended_at, created_at
+            "#,
+        )
+        .bind(user_id)
+        .bind(admin_id)
+        .bind(reason)
+        .bind(expires_at)
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(session)
+    }
+
+    async fn find_by_id(&self, id: Uuid) -> Result<Option<ImpersonationSession>, sqlx::Error> {
+        let session = sqlx::query_as::<_, ImpersonationSession>(
+            r#"
+            SELECT id, user_id, admin_id, reason, expires_at, ended_at, created_at
+            FROM impersonation_sessions
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(session)
+    }
+
+    async fn end(&self, id: Uuid) -> Result<Option<ImpersonationSession>, sqlx::Error> {
+        let session = sqlx::query_as::<_, ImpersonationSession>(
+            r#"
+            UPDATE impersonation_sessions
+            SET ended_at = NOW()
+            WHERE id = $1 AND ended_at IS NULL AND expires_at > NOW()
+            RETURNING id, user_id, admin_id, reason, expires_at, ended_at, created_at
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(session)
+    }
+
+    async fn end_all_by_admin(
+        &self,
+        admin_id: Uuid,
+    ) -> Result<Vec<ImpersonationSession>, sqlx::Error> {
+        let sessions = sqlx::query_as::<_, ImpersonationSession>(
+            r#"
+            UPDATE impersonation_sessions
+            SET ended_at = NOW()
+            WHERE admin_id = $1 AND ended_at IS NULL AND expires_at > NOW()
+            RETURNING id, user_id, admin_id, reason, expires_at, ended_at, created_at
+            "#,
+        )
+        .bind(admin_id)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(sessions)
+    }
+
+    async fn close_expired(&self) -> Result<Vec<ImpersonationSession>, sqlx::Error> {
+        let sessions = sqlx::query_as::<_, ImpersonationSession>(
+            r#"
+            UPDATE impersonation_sessions
+            SET ended_at = expires_at
+            WHERE ended_at IS NULL AND expires_at <= NOW()
+            RETURNING id, user_id, admin_id, reason, expires_at, ended_at, created_at
+            "#,
+        )
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(sessions)
+    }
+}
@@ -0,0 +1,287 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    auth::tokens::hash_token,
+    models::{Invite, InviteRedemption},
+    repositories::InviteRepositoryTrait,
+};
+
+// revoked and expired invites give back whatever they didn't use
+const RESERVED_USES: &str = r#"
+    SELECT COALESCE(SUM(
+        CASE WHEN revoked_at IS NULL AND expires_at > NOW() THEN max_uses ELSE uses END
+    ), 0)::BIGINT
+    FROM invites
+    WHERE created_by = $1
+"#;
+
+#[derive(Clone)]
+pub struct InviteRepository {
+    db: PgPool,
+}
+
+impl InviteRepository {
+    pub fn new(db: PgPool) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl InviteRepositoryTrait for InviteRepository {
+    async fn create(
+        &self,
+        created_by: Uuid,
+        code: &str,
+        max_uses: i32,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Invite, sqlx::Error> {
+        let invite = sqlx::query_as::<_, Invite>(
+            r#"
+            INSERT INTO invites (created_by, code_hash, max_uses, expires_at)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id, created_by, code_hash, max_uses, uses, expires_at, revoked_at, created_at
+            "#,
+        )
+        .bind(created_by)
+        .bind(hash_token(code))
+        .bind(max_uses)
+        .bind(expires_at)
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(invite)
+    }
+
+    async fn create_within_quota(
+        &self,
+        created_by: Uuid,
+        code: &str,
+        max_uses: i32,
+        expires_at: DateTime<Utc>,
+        default_quota: i32,
+    ) -> Result<Option<Invite>, sqlx::Error> {
+        let mut tx = self.db.begin().await?;
+
+        // The row lock lines up concurrent requests of the same user. It has to be its own
+        // statement, the count after it only sees what the one before inserted because it's a
+        // new statement with a new snapshot.
+        let quota = sqlx::query_scalar::<_, Option<i32>>(
+            r#"
+            SELECT invite_quota
+            FROM users
+            WHERE id = $1
+            FOR UPDATE
+            "#,
+        )
+        .bind(created_by)
+        .fetch_one(&mut *tx)
+        .await?
+        .unwrap_or(default_quota);
+
+        let reserved = sqlx::query_scalar::<_, i64>(RESERVED_USES)
+            .bind(created_by)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        // dropping the transaction rolls it back and lets go of the lock
+        if reserved + i64::from(max_uses) > i64::from(quota) {
+            return Ok(None);
+        }
+
+        let invite = sqlx::query_as::<_, Invite>(
+            r#"
+            INSERT INTO invites (created_by, code_hash, max_uses, expires_at)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id, created_by, code_hash, max_uses, uses, expires_at, revoked_at, created_at
+            "#,
+        )
+        .bind(created_by)
+        .bind(hash_token(code))
+        .bind(max_uses)
+        .bind(expires_at)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(Some(invite))
+    }
+
+    async fn find_by_code(&self, code: &str) -> Result<Option<Invite>, sqlx::Error> {
+        let invite = sqlx::query_as::<_, Invite>(
+            r#"
+            SELECT id, created_by, code_hash, max_uses, uses, expires_at, revoked_at, created_at
+            FROM invites
+            WHERE code_hash = $1
+            "#,
+        )
+        .bind(hash_token(code))
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(invite)
+    }
+
+    async fn redeem(&self, code: &str) -> Result<Option<Invite>, sqlx::Error> {
+        // the row lock keeps two registrations from taking the last use
+        let invite = sqlx::query_as::<_, Invite>(
+            r#"
+            UPDATE invites
+            SET uses = uses + 1
+            WHERE code_hash = $1
+              AND revoked_at IS NULL
+              AND expires_at > NOW()
+              AND uses < max_uses
+            RETURNING id, created_by, code_hash, max_uses, uses, expires_at, revoked_at, created_at
+            "#,
+        )
+        .bind(hash_token(code))
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(invite)
+    }
+
+    async fn release(&self, id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE invites
+            SET uses = uses - 1
+            WHERE id = $1 AND uses > 0
+            "#,
+        )
+        .bind(id)
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn record_redemption(&self, invite_id: Uuid, user_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO invite_redemptions (invite_id, user_id)
+            VALUES ($1, $2)
+            "#,
+        )
+        .bind(invite_id)
+        .bind(user_id)
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn find_by_creator(&self, user_id: Uuid) -> Result<Vec<Invite>, sqlx::Error> {
+        let invites = sqlx::query_as::<_, Invite>(
+            r#"
+            SELECT id, created_by, code_hash, max_uses, uses, expires_at, revoked_at, created_at
+            FROM invites
+            WHERE created_by = $1
+            ORDER BY created_at DESC
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(invites)
+    }
+
+    async fn find_redemptions_by_creator(
+        &self,
+        user_id: Uuid,
+    ) -> Result<Vec<InviteRedemption>, sqlx::Error> {
+        let redemptions = sqlx::query_as::<_, InviteRedemption>(
+            r#"
+            SELECT r.invite_id, r.user_id, u.username, r.created_at
+            FROM invite_redemptions r
+            JOIN invites i ON i.id = r.invite_id
+            JOIN users u ON u.id = r.user_id
+            WHERE i.created_by = $1
+            ORDER BY r.created_at
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(redemptions)
+    }
+
+    async fn find_inviter(&self, user_id: Uuid) -> Result<Option<InviteRedemption>, sqlx::Error> {
+        let inviter = sqlx::query_as::<_, InviteRedemption>(
+            r#"
+            SELECT r.invite_id, i.created_by AS user_id, u.username, r.created_at
+            FROM invite_redemptions r
+            JOIN invites i ON i.id = r.invite_id
+            JOIN users u ON u.id = i.created_by
+            WHERE r.user_id = $1
+            "#,
+        )
+        .bind(user_id)
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(inviter)
+    }
+
+    async fn revoke(&self, id: Uuid, created_by: Uuid) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            UPDATE invites
+            SET revoked_at = NOW()
+            WHERE id = $1 AND created_by = $2 AND revoked_at IS NULL
+            "#,
+        )
+        .bind(id)
+        .bind(created_by)
+        .execute(&self.db)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn count_reserved_uses(&self, user_id: Uuid) -> Result<i64, sqlx::Error> {
+        let reserved = sqlx::query_scalar::<_, i64>(RESERVED_USES)
+            .bind(user_id)
+            .fetch_one(&self.db)
+            .await?;
+
+        Ok(reserved)
+    }
+
+    async fn find_quota(&self, user_id: Uuid) -> Result<Option<i32>, sqlx::Error> {
+        let quota = sqlx::query_scalar::<_, Option<i32>>(
+            r#"
+            SELECT invite_quota
+            FROM users
+            WHERE id = $1
+            "#,
+        )
+        .bind(user_id)
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(quota.flatten())
+    }
+
+    async fn set_quota(&self, user_id: Uuid, quota: Option<i32>) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE users
+            SET invite_quota = $2
+            WHERE id = $1
+            "#,
+        )
+        .bind(user_id)
+        .bind(quota)
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+}
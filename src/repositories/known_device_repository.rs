@@ -0,0 +1,134 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    auth::tokens::hash_token,
+    models::{KnownDevice, SignInReportToken},
+    repositories::KnownDeviceRepositoryTrait,
+};
+
+#[derive(Clone)]
+pub struct KnownDeviceRepository {
+    db: PgPool,
+}
+
+impl KnownDeviceRepository {
+    pub fn new(db: PgPool) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl KnownDeviceRepositoryTrait for KnownDeviceRepository {
+    async fn has_devices(&self, user_id: Uuid) -> Result<bool, sqlx::Error> {
+        let has_devices = sqlx::query_scalar::<_, bool>(
+            r#"
+            SELECT EXISTS (SELECT 1 FROM known_devices WHERE user_id = $1)
+            "#,
+        )
+        .bind(user_id)
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(has_devices)
+    }
+
+    async fn find_by_user(&self, user_id: Uuid) -> Result<Vec<KnownDevice>, sqlx::Error> {
+        let devices = sqlx::query_as::<_, KnownDevice>(
+            r#"
+            SELECT id, user_id, fingerprint, ip_address, user_agent, first_seen_at, last_seen_at
+            FROM known_devices
+            WHERE user_id = $1
+            ORDER BY last_seen_at DESC
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(devices)
+    }
+
+    async fn remember(
+        &self,
+        user_id: Uuid,
+        fingerprint: &str,
+        ip_address: &str,
+        user_agent: Option<&str>,
+    ) -> Result<bool, sqlx::Error> {
+        // xmax is only set on the row when the conflict branch updated it
+        let inserted = sqlx::query_scalar::<_, bool>(
+            r#"
+            INSERT INTO known_devices (user_id, fingerprint, ip_address, user_agent)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (user_id, fingerprint) DO UPDATE SET last_seen_at = NOW()
+            RETURNING (xmax = 0)
+            "#,
+        )
+        .bind(user_id)
+        .bind(fingerprint)
+        .bind(ip_address)
+        .bind(user_agent)
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(inserted)
+    }
+
+    async fn create_report_token(
+        &self,
+        user_id: Uuid,
+        session_id: Uuid,
+        token: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<SignInReportToken, sqlx::Error> {
+        let report_token = sqlx::query_as::<_, SignInReportToken>(
+            r#"
+            INSERT INTO sign_in_report_tokens (user_id, session_id, token_hash, expires_at)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id, user_id, session_id, token_hash, expires_at, created_at
+            "#,
+        )
+        .bind(user_id)
+        .bind(session_id)
+        .bind(hash_token(token))
+        .bind(expires_at)
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(report_token)
+    }
+
+    async fn consume_report_token(
+        &self,
+        token: &str,
+    ) -> Result<Option<SignInReportToken>, sqlx::Error> {
+        let report_token = sqlx::query_as::<_, SignInReportToken>(
+            r#"
+            DELETE FROM sign_in_report_tokens
+            WHERE token_hash = $1
+            RETURNING id, user_id, session_id, token_hash, expires_at, created_at
+            "#,
+        )
+        .bind(hash_token(token))
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(report_token)
+    }
+
+    async fn delete_expired_report_tokens(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            DELETE FROM sign_in_report_tokens
+            WHERE expires_at < NOW()
+            "#,
+        )
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+}
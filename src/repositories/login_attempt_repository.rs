@@ -0,0 +1,92 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+use crate::{models::LoginFailureStats, repositories::LoginAttemptRepositoryTrait};
+
+#[derive(Clone)]
+pub struct LoginAttemptRepository {
+    db: PgPool,
+}
+
+impl LoginAttemptRepository {
+    pub fn new(db: PgPool) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl LoginAttemptRepositoryTrait for LoginAttemptRepository {
+    async fn record_failure(&self, email: &str, ip_address: &str) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO failed_login_attempts (email, ip_address)
+            VALUES ($1, $2)
+            "#,
+        )
+        .bind(email)
+        .bind(ip_address)
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn failure_stats_for_email(
+        &self,
+        email: &str,
+        since: DateTime<Utc>,
+    ) -> Result<LoginFailureStats, sqlx::Error> {
+        let stats = sqlx::query_as::<_, LoginFailureStats>(
+            r#"
+            SELECT COUNT(*) AS count,
+                   MIN(attempted_at) AS first_attempt_at,
+                   MAX(attempted_at) AS last_attempt_at
+            FROM failed_login_attempts
+            WHERE email = $1 AND attempted_at > $2
+            "#,
+        )
+        .bind(email)
+        .bind(since)
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(stats)
+    }
+
+    async fn failure_stats_for_ip(
+        &self,
+        ip_address: &str,
+        since: DateTime<Utc>,
+    ) -> Result<LoginFailureStats, sqlx::Error> {
+        let stats = sqlx::query_as::<_, LoginFailureStats>(
+            r#"
+            SELECT COUNT(*) AS count,
+                   MIN(attempted_at) AS first_attempt_at,
+                   MAX(attempted_at) AS last_attempt_at
+            FROM failed_login_attempts
+            WHERE ip_address = $1 AND attempted_at > $2
+            "#,
+        )
+        .bind(ip_address)
+        .bind(since)
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(stats)
+    }
+
+    async fn clear_failures_for_email(&self, email: &str) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            DELETE FROM failed_login_attempts
+            WHERE email = $1
+            "#,
+        )
+        .bind(email)
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,90 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    auth::tokens::hash_token, models::MagicLinkToken, repositories::MagicLinkRepositoryTrait,
+};
+
+#[derive(Clone)]
+pub struct MagicLinkRepository {
+    db: PgPool,
+}
+
+impl MagicLinkRepository {
+    pub fn new(db: PgPool) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl MagicLinkRepositoryTrait for MagicLinkRepository {
+    async fn create_token(
+        &self,
+        user_id: Uuid,
+        token: &str,
+        nonce: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<MagicLinkToken, sqlx::Error> {
+        let magic_link_token = sqlx::query_as::<_, MagicLinkToken>(
+            r#"
+            INSERT INTO magic_link_tokens (user_id, token_hash, nonce_hash, expires_at)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id, user_id, token_hash, nonce_hash, created_at, expires_at
+            "#,
+        )
+        .bind(user_id)
+        .bind(hash_token(token))
+        .bind(hash_token(nonce))
+        .bind(expires_at)
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(magic_link_token)
+    }
+
+    async fn find_by_token(&self, token: &str) -> Result<Option<MagicLinkToken>, sqlx::Error> {
+        let magic_link_token = sqlx::query_as::<_, MagicLinkToken>(
+            r#"
+            SELECT id, user_id, token_hash, nonce_hash, created_at, expires_at
+            FROM magic_link_tokens
+            WHERE token_hash = $1
+            "#,
+        )
+        .bind(hash_token(token))
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(magic_link_token)
+    }
+
+    async fn consume_token(&self, token: &str) -> Result<Option<MagicLinkToken>, sqlx::Error> {
+        let magic_link_token = sqlx::query_as::<_, MagicLinkToken>(
+            r#"
+            DELETE FROM magic_link_tokens
+            WHERE token_hash = $1
+            RETURNING id, user_id, token_hash, nonce_hash, created_at, expires_at
+            "#,
+        )
+        .bind(hash_token(token))
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(magic_link_token)
+    }
+
+    async fn delete_all_user_tokens(&self, user_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            DELETE FROM magic_link_tokens
+            WHERE user_id = $1
+            "#,
+        )
+        .bind(user_id)
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+}
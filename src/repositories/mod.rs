@@ -1,18 +1,48 @@
 // data access layer
 //
 // The repository has a single responsibility and only handles data access. Testing becomes easier because we can mock // the repository for unit tests. Multiple handlers can reuse the same repository methods, and when we need to change // database queries, we only update them in one place.
+pub mod account_unlock_repository;
+pub mod audit_event_repository;
+pub mod email_change_repository;
 pub mod email_verification_repository;
+pub mod impersonation_repository;
+pub mod invite_repository;
+pub mod known_device_repository;
+pub mod login_attempt_repository;
+pub mod magic_link_repository;
+pub mod oauth_client_repository;
 pub mod password_reset_repository;
+pub mod personal_access_token_repository;
+pub mod rate_limit_repository;
 pub mod refresh_token_repository;
+pub mod role_repository;
 pub mod traits;
+pub mod user_identity_repository;
 pub mod user_repository;
 
 pub use traits::{
-    EmailVerificationRepositoryTrait, PasswordResetRepositoryTrait, RefreshTokenRepositoryTrait,
-    UserRepositoryTrait,
+    AccountUnlockRepositoryTrait, AuditEventRepositoryTrait, EmailChangeRepositoryTrait,
+    EmailVerificationRepositoryTrait, ImpersonationRepositoryTrait, InviteRepositoryTrait,
+    KnownDeviceRepositoryTrait, LoginAttemptRepositoryTrait, MagicLinkRepositoryTrait,
+    OAuthClientRepositoryTrait, PasswordResetRepositoryTrait, PersonalAccessTokenRepositoryTrait,
+    RateLimitRepositoryTrait, RefreshTokenRepositoryTrait, RoleRepositoryTrait,
+    UserIdentityRepositoryTrait, UserRepositoryTrait,
 };
 
+pub use account_unlock_repository::AccountUnlockRepository;
+pub use audit_event_repository::AuditEventRepository;
+pub use email_change_repository::EmailChangeRepository;
 pub use email_verification_repository::EmailVerificationRepository;
+pub use impersonation_repository::ImpersonationRepository;
+pub use invite_repository::InviteRepository;
+pub use known_device_repository::KnownDeviceRepository;
+pub use login_attempt_repository::LoginAttemptRepository;
+pub use magic_link_repository::MagicLinkRepository;
+pub use oauth_client_repository::OAuthClientRepository;
 pub use password_reset_repository::PasswordResetRepository;
+pub use personal_access_token_repository::PersonalAccessTokenRepository;
+pub use rate_limit_repository::{InMemoryRateLimitRepository, RateLimitRepository};
 pub use refresh_token_repository::RefreshTokenRepository;
+pub use role_repository::RoleRepository;
+pub use user_identity_repository::UserIdentityRepository;
 pub use user_repository::UserRepository;
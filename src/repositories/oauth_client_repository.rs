@@ -0,0 +1,175 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    auth::tokens::hash_token,
+    models::{OAuthAuthorizationCode, OAuthClient},
+    repositories::OAuthClientRepositoryTrait,
+};
+
+#[derive(Clone)]
+pub struct OAuthClientRepository {
+    db: PgPool,
+}
+
+impl OAuthClientRepository {
+    pub fn new(db: PgPool) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl OAuthClientRepositoryTrait for OAuthClientRepository {
+    async fn create(
+        &self,
+        owner_id: Uuid,
+        name: &str,
+        secret: Option<&str>,
+        redirect_uris: &[String],
+        scopes: &[String],
+    ) -> Result<OAuthClient, sqlx::Error> {
+        let client = sqlx::query_as::<_, OAuthClient>(
+            r#"
+            INSERT INTO oauth_clients (owner_id, name, secret_hash, redirect_uris, scopes)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING id, owner_id, name, secret_hash, redirect_uris, scopes, created_at
+            "#,
+        )
+        .bind(owner_id)
+        .bind(name)
+        .bind(secret.map(hash_token))
+        .bind(redirect_uris)
+        .bind(scopes)
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(client)
+    }
+
+    async fn find_by_id(&self, id: Uuid) -> Result<Option<OAuthClient>, sqlx::Error> {
+        let client = sqlx::query_as::<_, OAuthClient>(
+            r#"
+            SELECT id, owner_id, name, secret_hash, redirect_uris, scopes, created_at
+            FROM oauth_clients
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(client)
+    }
+
+    async fn find_by_owner(&self, owner_id: Uuid) -> Result<Vec<OAuthClient>, sqlx::Error> {
+        let clients = sqlx::query_as::<_, OAuthClient>(
+            r#"
+            SELECT id, owner_id, name, secret_hash, redirect_uris, scopes, created_at
+            FROM oauth_clients
+            WHERE owner_id = $1
+            ORDER BY created_at
+            "#,
+        )
+        .bind(owner_id)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(clients)
+    }
+
+    async fn delete(&self, id: Uuid, owner_id: Uuid) -> Result<Option<Vec<Uuid>>, sqlx::Error> {
+        // codes and refresh tokens go with it through ON DELETE CASCADE, the select still sees
+        // the tokens as they were before the statement
+        let rows = sqlx::query_as::<_, (Uuid, Option<Uuid>)>(
+            r#"
+            WITH deleted AS (
+                DELETE FROM oauth_clients
+                WHERE id = $1 AND owner_id = $2
+                RETURNING id
+            )
+            SELECT DISTINCT deleted.id, refresh_tokens.session_id
+            FROM deleted
+            LEFT JOIN refresh_tokens ON refresh_tokens.client_id = deleted.id
+            "#,
+        )
+        .bind(id)
+        .bind(owner_id)
+        .fetch_all(&self.db)
+        .await?;
+
+        if rows.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(
+            rows.into_iter()
+                .filter_map(|(_, session_id)| session_id)
+                .collect(),
+        ))
+    }
+
+    async fn create_authorization_code(
+        &self,
+        code: &str,
+        client_id: Uuid,
+        user_id: Uuid,
+        redirect_uri: &str,
+        scope: &str,
+        code_challenge: &str,
+        token_version: i32,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO oauth_authorization_codes
+                (code_hash, client_id, user_id, redirect_uri, scope, code_challenge, token_version, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            "#,
+        )
+        .bind(hash_token(code))
+        .bind(client_id)
+        .bind(user_id)
+        .bind(redirect_uri)
+        .bind(scope)
+        .bind(code_challenge)
+        .bind(token_version)
+        .bind(expires_at)
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn consume_authorization_code(
+        &self,
+        code: &str,
+    ) -> Result<Option<OAuthAuthorizationCode>, sqlx::Error> {
+        let authorization_code = sqlx::query_as::<_, OAuthAuthorizationCode>(
+            r#"
+            DELETE FROM oauth_authorization_codes
+            WHERE code_hash = $1
+            RETURNING id, code_hash, client_id, user_id, redirect_uri, scope, code_challenge, token_version, expires_at, created_at
+            "#,
+        )
+        .bind(hash_token(code))
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(authorization_code)
+    }
+
+    async fn delete_expired_authorization_codes(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            DELETE FROM oauth_authorization_codes
+            WHERE expires_at < NOW()
+            "#,
+        )
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+}
@@ -3,7 +3,10 @@ use chrono::{DateTime, Utc};
 use sqlx::PgPool;
 use uuid::Uuid;
 
-use crate::{models::PasswordResetToken, repositories::PasswordResetRepositoryTrait};
+use crate::{
+    auth::tokens::hash_token, models::PasswordResetToken,
+    repositories::PasswordResetRepositoryTrait,
+};
 
 #[derive(Clone)]
 pub struct PasswordResetRepository {
@@ -26,13 +29,13 @@ impl PasswordResetRepositoryTrait for PasswordResetRepository {
     ) -> Result<PasswordResetToken, sqlx::Error> {
         let reset_token = sqlx::query_as::<_, PasswordResetToken>(
             r#"
-            INSERT INTO password_reset_tokens (user_id, token, expires_at)
+            INSERT INTO password_reset_tokens (user_id, token_hash, expires_at)
             VALUES ($1, $2, $3)
-            RETURNING id, user_id, token, created_at, expires_at
+            RETURNING id, user_id, token_hash, created_at, expires_at
             "#,
         )
         .bind(user_id)
-        .bind(token)
+        .bind(hash_token(token))
         .bind(expires_at)
         .fetch_one(&self.db)
         .await?;
@@ -43,12 +46,12 @@ impl PasswordResetRepositoryTrait for PasswordResetRepository {
     async fn find_by_token(&self, token: &str) -> Result<Option<PasswordResetToken>, sqlx::Error> {
         let reset_token = sqlx::query_as::<_, PasswordResetToken>(
             r#"
-            SELECT id, user_id, token, created_at, expires_at
+            SELECT id, user_id, token_hash, created_at, expires_at
             FROM password_reset_tokens
-            WHERE token = $1
+            WHERE token_hash = $1
             "#,
         )
-        .bind(token)
+        .bind(hash_token(token))
         .fetch_optional(&self.db)
         .await?;
 
@@ -59,10 +62,10 @@ impl PasswordResetRepositoryTrait for PasswordResetRepository {
         sqlx::query(
             r#"
             DELETE FROM password_reset_tokens
-            WHERE token = $1
+            WHERE token_hash = $1
             "#,
         )
-        .bind(token)
+        .bind(hash_token(token))
         .execute(&self.db)
         .await?;
 
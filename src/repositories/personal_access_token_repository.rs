@@ -0,0 +1,124 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    auth::tokens::hash_token, models::PersonalAccessToken,
+    repositories::PersonalAccessTokenRepositoryTrait,
+};
+
+#[derive(Clone)]
+pub struct PersonalAccessTokenRepository {
+    db: PgPool,
+}
+
+impl PersonalAccessTokenRepository {
+    pub fn new(db: PgPool) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl PersonalAccessTokenRepositoryTrait for PersonalAccessTokenRepository {
+    async fn create(
+        &self,
+        user_id: Uuid,
+        name: &str,
+        token: &str,
+        scopes: &[String],
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<PersonalAccessToken, sqlx::Error> {
+        let access_token = sqlx::query_as::<_, PersonalAccessToken>(
+            r#"
+            INSERT INTO personal_access_tokens (user_id, name, token_hash, scopes, expires_at)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING id, user_id, name, token_hash, scopes, expires_at, last_used_at, created_at
+            "#,
+        )
+        .bind(user_id)
+        .bind(name)
+        .bind(hash_token(token))
+        .bind(scopes)
+        .bind(expires_at)
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(access_token)
+    }
+
+    async fn find_by_token(&self, token: &str) -> Result<Option<PersonalAccessToken>, sqlx::Error> {
+        let access_token = sqlx::query_as::<_, PersonalAccessToken>(
+            r#"
+            SELECT id, user_id, name, token_hash, scopes, expires_at, last_used_at, created_at
+            FROM personal_access_tokens
+            WHERE token_hash = $1
+            "#,
+        )
+        .bind(hash_token(token))
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(access_token)
+    }
+
+    async fn find_by_user(&self, user_id: Uuid) -> Result<Vec<PersonalAccessToken>, sqlx::Error> {
+        let access_tokens = sqlx::query_as::<_, PersonalAccessToken>(
+            r#"
+            SELECT id, user_id, name, token_hash, scopes, expires_at, last_used_at, created_at
+            FROM personal_access_tokens
+            WHERE user_id = $1
+            ORDER BY created_at DESC
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(access_tokens)
+    }
+
+    async fn update_last_used(&self, id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE personal_access_tokens
+            SET last_used_at = NOW()
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn delete(&self, id: Uuid, user_id: Uuid) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM personal_access_tokens
+            WHERE id = $1 AND user_id = $2
+            "#,
+        )
+        .bind(id)
+        .bind(user_id)
+        .execute(&self.db)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_all_user_tokens(&self, user_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            DELETE FROM personal_access_tokens
+            WHERE user_id = $1
+            "#,
+        )
+        .bind(user_id)
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+}
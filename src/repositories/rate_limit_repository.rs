@@ -0,0 +1,112 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use sqlx::PgPool;
+
+use crate::{rate_limit::MAX_WINDOW_SECONDS, repositories::RateLimitRepositoryTrait};
+
+// past this size stale windows get swept on insert
+const PRUNE_THRESHOLD: usize = 10_000;
+
+// shared between all instances
+#[derive(Clone)]
+pub struct RateLimitRepository {
+    db: PgPool,
+}
+
+impl RateLimitRepository {
+    pub fn new(db: PgPool) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl RateLimitRepositoryTrait for RateLimitRepository {
+    async fn hit(&self, key: &str, window_start: DateTime<Utc>) -> Result<i64, sqlx::Error> {
+        let count = sqlx::query_scalar::<_, i64>(
+            r#"
+            INSERT INTO rate_limit_counters (key, window_start, count)
+            VALUES ($1, $2, 1)
+            ON CONFLICT (key, window_start)
+            DO UPDATE SET count = rate_limit_counters.count + 1
+            RETURNING count
+            "#,
+        )
+        .bind(key)
+        .bind(window_start)
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(count)
+    }
+
+    async fn delete_expired(&self, before: DateTime<Utc>) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            DELETE FROM rate_limit_counters
+            WHERE window_start < $1
+            "#,
+        )
+        .bind(before)
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+}
+
+// per instance only, good enough for a single server and for development
+pub struct InMemoryRateLimitRepository {
+    counters: Mutex<HashMap<String, (DateTime<Utc>, i64)>>,
+}
+
+impl InMemoryRateLimitRepository {
+    pub fn new() -> Self {
+        Self {
+            counters: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl Default for InMemoryRateLimitRepository {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl RateLimitRepositoryTrait for InMemoryRateLimitRepository {
+    async fn hit(&self, key: &str, window_start: DateTime<Utc>) -> Result<i64, sqlx::Error> {
+        let mut counters = self
+            .counters
+            .lock()
+            .map_err(|_| sqlx::Error::Protocol("rate limit counters poisoned".to_string()))?;
+
+        // by the longest window there is, this key's window would drop the live counters of
+        // longer ones and reset e.g. the hourly limits
+        if counters.len() >= PRUNE_THRESHOLD {
+            let oldest_live = Utc::now() - Duration::seconds(MAX_WINDOW_SECONDS as i64);
+            counters.retain(|_, (start, _)| *start >= oldest_live);
+        }
+
+        let counter = counters.entry(key.to_string()).or_insert((window_start, 0));
+
+        // a new window starts counting from zero again
+        if counter.0 != window_start {
+            *counter = (window_start, 0);
+        }
+
+        counter.1 += 1;
+
+        Ok(counter.1)
+    }
+
+    async fn delete_expired(&self, before: DateTime<Utc>) -> Result<(), sqlx::Error> {
+        if let Ok(mut counters) = self.counters.lock() {
+            counters.retain(|_, (start, _)| *start >= before);
+        }
+
+        Ok(())
+    }
+}
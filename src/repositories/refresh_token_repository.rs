@@ -1,9 +1,11 @@
 use async_trait::async_trait;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use sqlx::PgPool;
 use uuid::Uuid;
 
-use crate::{models::RefreshToken, repositories::RefreshTokenRepositoryTrait};
+use crate::{
+    auth::tokens::hash_token, models::RefreshToken, repositories::RefreshTokenRepositoryTrait,
+};
 
 #[derive(Clone)]
 pub struct RefreshTokenRepository {
@@ -18,16 +20,50 @@ impl RefreshTokenRepository {
 
 #[async_trait]
 impl RefreshTokenRepositoryTrait for RefreshTokenRepository {
-    async fn create_token(&self, user_id: Uuid, token: &str) -> Result<RefreshToken, sqlx::Error> {
+    async fn create_token(
+        &self,
+        user_id: Uuid,
+        session_id: Uuid,
+        token: &str,
+        authenticated_at: DateTime<Utc>,
+    ) -> Result<RefreshToken, sqlx::Error> {
         let refresh_token = sqlx::query_as::<_, RefreshToken>(
             r#"
-            INSERT INTO refresh_tokens (user_id, token)
-            VALUES ($1, $2)
-            RETURNING id, user_id, token, expires_at, is_used, used_at, created_at, last_used_at
+            INSERT INTO refresh_tokens (user_id, session_id, token_hash, authenticated_at)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id, user_id, session_id, token_hash, expires_at, is_used, used_at, created_at, last_used_at, client_id, scope, authenticated_at
             "#,
         )
         .bind(user_id)
-        .bind(token)
+        .bind(session_id)
+        .bind(hash_token(token))
+        .bind(authenticated_at)
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(refresh_token)
+    }
+
+    async fn create_client_token(
+        &self,
+        user_id: Uuid,
+        session_id: Uuid,
+        client_id: Uuid,
+        scope: &str,
+        token: &str,
+    ) -> Result<RefreshToken, sqlx::Error> {
+        let refresh_token = sqlx::query_as::<_, RefreshToken>(
+            r#"
+            INSERT INTO refresh_tokens (user_id, session_id, token_hash, client_id, scope)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING id, user_id, session_id, token_hash, expires_at, is_used, used_at, created_at, last_used_at, client_id, scope, authenticated_at
+            "#,
+        )
+        .bind(user_id)
+        .bind(session_id)
+        .bind(hash_token(token))
+        .bind(client_id)
+        .bind(scope)
         .fetch_one(&self.db)
         .await?;
 
@@ -39,10 +75,10 @@ impl RefreshTokenRepositoryTrait for RefreshTokenRepository {
             r#"
             UPDATE refresh_tokens
             SET last_used_at = $2
-            WHERE token = $1
+            WHERE token_hash = $1
         "#,
         )
-        .bind(token)
+        .bind(hash_token(token))
         .bind(Utc::now())
         .execute(&self.db)
         .await?;
@@ -53,12 +89,12 @@ impl RefreshTokenRepositoryTrait for RefreshTokenRepository {
     async fn find_by_token(&self, token: &str) -> Result<Option<RefreshToken>, sqlx::Error> {
         let refresh_token = sqlx::query_as::<_, RefreshToken>(
             r#"
-            SELECT id, user_id, token, expires_at, is_used, used_at, created_at, last_used_at
+            SELECT id, user_id, session_id, token_hash, expires_at, is_used, used_at, created_at, last_used_at, client_id, scope, authenticated_at
             FROM refresh_tokens
-            WHERE token = $1
+            WHERE token_hash = $1
             "#,
         )
-        .bind(token)
+        .bind(hash_token(token))
         .fetch_optional(&self.db)
         .await?;
 
@@ -69,10 +105,10 @@ impl RefreshTokenRepositoryTrait for RefreshTokenRepository {
         sqlx::query(
             r#"
             DELETE FROM refresh_tokens
-            WHERE token = $1
+            WHERE token_hash = $1
             "#,
         )
-        .bind(token)
+        .bind(hash_token(token))
         .execute(&self.db)
         .await?;
 
@@ -98,14 +134,102 @@ impl RefreshTokenRepositoryTrait for RefreshTokenRepository {
             r#"
             UPDATE refresh_tokens
             SET is_used = TRUE, used_at = $1
-            WHERE token = $2
+            WHERE token_hash = $2
             "#,
         )
         .bind(Utc::now())
-        .bind(token)
+        .bind(hash_token(token))
         .execute(&self.db)
         .await?;
 
         Ok(())
     }
+
+    async fn is_session_active(&self, session_id: Uuid) -> Result<bool, sqlx::Error> {
+        // the newest token of a rotation chain is the only unused one
+        let active = sqlx::query_scalar::<_, bool>(
+            r#"
+            SELECT EXISTS (
+                SELECT 1
+                FROM refresh_tokens
+                WHERE session_id = $1 AND is_used = FALSE AND expires_at > $2
+            )
+            "#,
+        )
+        .bind(session_id)
+        .bind(Utc::now())
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(active)
+    }
+
+    async fn mark_reauthenticated(&self, session_id: Uuid) -> Result<DateTime<Utc>, sqlx::Error> {
+        let now = Utc::now();
+
+        sqlx::query(
+            r#"
+            UPDATE refresh_tokens
+            SET authenticated_at = $2
+            WHERE session_id = $1
+            "#,
+        )
+        .bind(session_id)
+        .bind(now)
+        .execute(&self.db)
+        .await?;
+
+        Ok(now)
+    }
+
+    async fn delete_session(&self, session_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            DELETE FROM refresh_tokens
+            WHERE session_id = $1
+            "#,
+        )
+        .bind(session_id)
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn delete_other_sessions(
+        &self,
+        user_id: Uuid,
+        keep_session_id: Uuid,
+    ) -> Result<Vec<Uuid>, sqlx::Error> {
+        let session_ids = sqlx::query_scalar::<_, Uuid>(
+            r#"
+            DELETE FROM refresh_tokens
+            WHERE user_id = $1 AND session_id <> $2
+            RETURNING session_id
+            "#,
+        )
+        .bind(user_id)
+        .bind(keep_session_id)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(session_ids)
+    }
+
+    async fn find_active_sessions(&self, user_id: Uuid) -> Result<Vec<RefreshToken>, sqlx::Error> {
+        let refresh_tokens = sqlx::query_as::<_, RefreshToken>(
+            r#"
+            SELECT id, user_id, session_id, token_hash, expires_at, is_used, used_at, created_at, last_used_at, client_id, scope, authenticated_at
+            FROM refresh_tokens
+            WHERE user_id = $1 AND is_used = FALSE AND expires_at > $2
+            ORDER BY last_used_at DESC
+            "#,
+        )
+        .bind(user_id)
+        .bind(Utc::now())
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(refresh_tokens)
+    }
 }
@@ -0,0 +1,171 @@
+use async_trait::async_trait;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{models::Role, repositories::RoleRepositoryTrait};
+
+#[derive(Clone)]
+pub struct RoleRepository {
+    db: PgPool,
+}
+
+impl RoleRepository {
+    pub fn new(db: PgPool) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl RoleRepositoryTrait for RoleRepository {
+    async fn find_all(&self) -> Result<Vec<Role>, sqlx::Error> {
+        let roles = sqlx::query_as::<_, Role>(
+            r#"
+            SELECT r.id, r.name, r.description, r.created_at,
+                   COALESCE(ARRAY_AGG(rp.permission ORDER BY rp.permission)
+                            FILTER (WHERE rp.permission IS NOT NULL), '{}') AS permissions
+            FROM roles r
+            LEFT JOIN role_permissions rp ON rp.role_id = r.id
+            GROUP BY r.id
+            ORDER BY r.name
+            "#,
+        )
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(roles)
+    }
+
+    async fn find_by_name(&self, name: &str) -> Result<Option<Role>, sqlx::Error> {
+        let role = sqlx::query_as::<_, Role>(
+            r#"
+            SELECT r.id, r.name, r.description, r.created_at,
+                   COALESCE(ARRAY_AGG(rp.permission ORDER BY rp.permission)
+                            FILTER (WHERE rp.permission IS NOT NULL), '{}') AS permissions
+            FROM roles r
+            LEFT JOIN role_permissions rp ON rp.role_id = r.id
+            WHERE r.name = $1
+            GROUP BY r.id
+            "#,
+        )
+        .bind(name)
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(role)
+    }
+
+    async fn find_role_names_for_user(&self, user_id: Uuid) -> Result<Vec<String>, sqlx::Error> {
+        let roles = sqlx::query_scalar::<_, String>(
+            r#"
+            SELECT r.name
+            FROM user_roles ur
+            JOIN roles r ON r.id = ur.role_id
+            WHERE ur.user_id = $1
+            ORDER BY r.name
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(roles)
+    }
+
+    async fn user_has_role(&self, user_id: Uuid, role: &str) -> Result<bool, sqlx::Error> {
+        let has_role = sqlx::query_scalar::<_, bool>(
+            r#"
+            SELECT EXISTS (
+                SELECT 1
+                FROM user_roles ur
+                JOIN roles r ON r.id = ur.role_id
+                WHERE ur.user_id = $1 AND r.name = $2
+            )
+            "#,
+        )
+        .bind(user_id)
+        .bind(role)
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(has_role)
+    }
+
+    async fn user_has_permission(
+        &self,
+        user_id: Uuid,
+        permission: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let has_permission = sqlx::query_scalar::<_, bool>(
+            r#"
+            SELECT EXISTS (
+                SELECT 1
+                FROM user_roles ur
+                JOIN role_permissions rp ON rp.role_id = ur.role_id
+                WHERE ur.user_id = $1 AND rp.permission = $2
+            )
+            "#,
+        )
+        .bind(user_id)
+        .bind(permission)
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(has_permission)
+    }
+
+    async fn count_users_with_role(&self, role: &str) -> Result<i64, sqlx::Error> {
+        let count = sqlx::query_scalar::<_, i64>(
+            r#"
+            SELECT COUNT(*)
+            FROM user_roles ur
+            JOIN roles r ON r.id = ur.role_id
+            WHERE r.name = $1
+            "#,
+        )
+        .bind(role)
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(count)
+    }
+
+    async fn grant_role(
+        &self,
+        user_id: Uuid,
+        role: &str,
+        granted_by: Option<Uuid>,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO user_roles (user_id, role_id, granted_by)
+            SELECT $1, id, $3
+            FROM roles
+            WHERE name = $2
+            ON CONFLICT (user_id, role_id) DO NOTHING
+            "#,
+        )
+        .bind(user_id)
+        .bind(role)
+        .bind(granted_by)
+        .execute(&self.db)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn revoke_role(&self, user_id: Uuid, role: &str) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM user_roles
+            WHERE user_id = $1
+              AND role_id = (SELECT id FROM roles WHERE name = $2)
+            "#,
+        )
+        .bind(user_id)
+        .bind(role)
+        .execute(&self.db)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
@@ -2,7 +2,12 @@ use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use uuid::Uuid;
 
-use crate::models::{EmailVerificationToken, PasswordResetToken, RefreshToken, User};
+use crate::models::{
+    AccountUnlockToken, AuditEvent, AuditEventFilter, EmailChangeToken, EmailVerificationToken,
+    ImpersonationSession, Invite, InviteRedemption, KnownDevice, LoginFailureStats, MagicLinkToken,
+    NewAuditEvent, OAuthAuthorizationCode, OAuthClient, OAuthLoginState, PasswordResetToken,
+    PersonalAccessToken, RefreshToken, Role, SignInReportToken, User, UserIdentity,
+};
 
 #[async_trait]
 pub trait UserRepositoryTrait: Send + Sync {
@@ -19,6 +24,13 @@ pub trait UserRepositoryTrait: Send + Sync {
 
     async fn find_by_username(&self, username: &str) -> Result<Option<User>, sqlx::Error>;
 
+    // case-insensitive, and also true for a name that only looks like an existing one
+    async fn username_taken(&self, username: &str) -> Result<bool, sqlx::Error>;
+
+    // fills in username_skeleton for accounts from before it existed, returns the usernames
+    // that look like another account's
+    async fn backfill_username_skeletons(&self) -> Result<Vec<String>, sqlx::Error>;
+
     async fn reset_password(
         &self,
         user_id: Uuid,
@@ -33,6 +45,34 @@ pub trait UserRepositoryTrait: Send + Sync {
         bio: Option<&str>,
         image: Option<&str>,
     ) -> Result<Option<User>, sqlx::Error>;
+
+    async fn increment_token_version(&self, id: Uuid) -> Result<i32, sqlx::Error>;
+
+    async fn lock_until(&self, id: Uuid, locked_until: DateTime<Utc>) -> Result<(), sqlx::Error>;
+
+    async fn unlock(&self, id: Uuid) -> Result<(), sqlx::Error>;
+
+    // newest first, query matches anywhere in the username or email
+    async fn search(
+        &self,
+        query: Option<&str>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<User>, sqlx::Error>;
+
+    async fn count(&self, query: Option<&str>) -> Result<i64, sqlx::Error>;
+
+    async fn suspend(&self, id: Uuid, reason: Option<&str>) -> Result<(), sqlx::Error>;
+
+    async fn unsuspend(&self, id: Uuid) -> Result<(), sqlx::Error>;
+
+    // soft delete, returns when it happened
+    async fn schedule_deletion(&self, id: Uuid) -> Result<DateTime<Utc>, sqlx::Error>;
+
+    async fn restore(&self, id: Uuid) -> Result<(), sqlx::Error>;
+
+    // deletes accounts whose grace period is over for good, returns their ids
+    async fn purge_deleted(&self, deleted_before: DateTime<Utc>) -> Result<Vec<Uuid>, sqlx::Error>;
 }
 
 #[async_trait]
@@ -54,6 +94,51 @@ pub trait EmailVerificationRepositoryTrait: Send + Sync {
     async fn verify_user_email(&self, user_id: Uuid) -> Result<(), sqlx::Error>;
 }
 
+#[async_trait]
+pub trait EmailChangeRepositoryTrait: Send + Sync {
+    async fn create_token(
+        &self,
+        user_id: Uuid,
+        purpose: &str,
+        email: &str,
+        token: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<EmailChangeToken, sqlx::Error>;
+
+    // leaves the token in place, a token of the other purpose isn't found
+    async fn find_token(
+        &self,
+        token: &str,
+        purpose: &str,
+    ) -> Result<Option<EmailChangeToken>, sqlx::Error>;
+
+    // single use, deleted on the way out. A token of the other purpose isn't found
+    async fn consume_token(
+        &self,
+        token: &str,
+        purpose: &str,
+    ) -> Result<Option<EmailChangeToken>, sqlx::Error>;
+
+    async fn find_user_tokens(
+        &self,
+        user_id: Uuid,
+        purpose: &str,
+    ) -> Result<Vec<EmailChangeToken>, sqlx::Error>;
+
+    async fn delete_user_tokens(&self, user_id: Uuid, purpose: &str) -> Result<(), sqlx::Error>;
+
+    async fn set_pending_email(
+        &self,
+        user_id: Uuid,
+        email: Option<&str>,
+    ) -> Result<(), sqlx::Error>;
+
+    // swaps users.email and clears the pending one
+    async fn change_email(&self, user_id: Uuid, email: &str) -> Result<(), sqlx::Error>;
+
+    async fn delete_expired_tokens(&self) -> Result<(), sqlx::Error>;
+}
+
 #[async_trait]
 pub trait PasswordResetRepositoryTrait: Send + Sync {
     async fn create_token(
@@ -72,7 +157,24 @@ pub trait PasswordResetRepositoryTrait: Send + Sync {
 
 #[async_trait]
 pub trait RefreshTokenRepositoryTrait: Send + Sync {
-    async fn create_token(&self, user_id: Uuid, token: &str) -> Result<RefreshToken, sqlx::Error>;
+    async fn create_token(
+        &self,
+        user_id: Uuid,
+        session_id: Uuid,
+        token: &str,
+        // when the user last proved who they are, rotation passes the old token's on
+        authenticated_at: DateTime<Utc>,
+    ) -> Result<RefreshToken, sqlx::Error>;
+
+    // same as create_token, for a session granted to a third-party client
+    async fn create_client_token(
+        &self,
+        user_id: Uuid,
+        session_id: Uuid,
+        client_id: Uuid,
+        scope: &str,
+        token: &str,
+    ) -> Result<RefreshToken, sqlx::Error>;
 
     async fn find_by_token(&self, token: &str) -> Result<Option<RefreshToken>, sqlx::Error>;
 
@@ -83,4 +185,349 @@ pub trait RefreshTokenRepositoryTrait: Send + Sync {
     async fn delete_all_user_tokens(&self, user_id: Uuid) -> Result<(), sqlx::Error>;
 
     async fn mark_token_as_used(&self, token: &str) -> Result<(), sqlx::Error>;
+
+    async fn is_session_active(&self, session_id: Uuid) -> Result<bool, sqlx::Error>;
+
+    // the user entered their password again, returns the new time for the access token
+    async fn mark_reauthenticated(&self, session_id: Uuid) -> Result<DateTime<Utc>, sqlx::Error>;
+
+    async fn delete_session(&self, session_id: Uuid) -> Result<(), sqlx::Error>;
+
+    // returns the ids of the sessions that were ended
+    async fn delete_other_sessions(
+        &self,
+        user_id: Uuid,
+        keep_session_id: Uuid,
+    ) -> Result<Vec<Uuid>, sqlx::Error>;
+
+    // the current refresh token of every session that can still be refreshed
+    async fn find_active_sessions(&self, user_id: Uuid) -> Result<Vec<RefreshToken>, sqlx::Error>;
+}
+
+#[async_trait]
+pub trait LoginAttemptRepositoryTrait: Send + Sync {
+    async fn record_failure(&self, email: &str, ip_address: &str) -> Result<(), sqlx::Error>;
+
+    async fn failure_stats_for_email(
+        &self,
+        email: &str,
+        since: DateTime<Utc>,
+    ) -> Result<LoginFailureStats, sqlx::Error>;
+
+    async fn failure_stats_for_ip(
+        &self,
+        ip_address: &str,
+        since: DateTime<Utc>,
+    ) -> Result<LoginFailureStats, sqlx::Error>;
+
+    async fn clear_failures_for_email(&self, email: &str) -> Result<(), sqlx::Error>;
+}
+
+#[async_trait]
+pub trait AccountUnlockRepositoryTrait: Send + Sync {
+    async fn create_token(
+        &self,
+        user_id: Uuid,
+        token: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<AccountUnlockToken, sqlx::Error>;
+
+    async fn find_by_token(&self, token: &str) -> Result<Option<AccountUnlockToken>, sqlx::Error>;
+
+    async fn delete_token(&self, token: &str) -> Result<(), sqlx::Error>;
+
+    async fn delete_all_user_tokens(&self, user_id: Uuid) -> Result<(), sqlx::Error>;
+}
+
+#[async_trait]
+pub trait MagicLinkRepositoryTrait: Send + Sync {
+    async fn create_token(
+        &self,
+        user_id: Uuid,
+        token: &str,
+        nonce: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<MagicLinkToken, sqlx::Error>;
+
+    async fn find_by_token(&self, token: &str) -> Result<Option<MagicLinkToken>, sqlx::Error>;
+
+    // deletes and returns the token in one go, so a link can't be used twice concurrently
+    async fn consume_token(&self, token: &str) -> Result<Option<MagicLinkToken>, sqlx::Error>;
+
+    async fn delete_all_user_tokens(&self, user_id: Uuid) -> Result<(), sqlx::Error>;
+}
+
+#[async_trait]
+pub trait UserIdentityRepositoryTrait: Send + Sync {
+    async fn create(
+        &self,
+        user_id: Uuid,
+        provider: &str,
+        subject: &str,
+        email: Option<&str>,
+    ) -> Result<UserIdentity, sqlx::Error>;
+
+    async fn find_by_subject(
+        &self,
+        provider: &str,
+        subject: &str,
+    ) -> Result<Option<UserIdentity>, sqlx::Error>;
+
+    async fn find_by_user(&self, user_id: Uuid) -> Result<Vec<UserIdentity>, sqlx::Error>;
+
+    // keeps the last email the provider told us about
+    async fn update_last_login(&self, id: Uuid, email: Option<&str>) -> Result<(), sqlx::Error>;
+
+    async fn delete(&self, user_id: Uuid, provider: &str) -> Result<bool, sqlx::Error>;
+
+    async fn create_login_state(
+        &self,
+        state: &str,
+        provider: &str,
+        code_verifier: &str,
+        nonce: &str,
+        link_user_id: Option<Uuid>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error>;
+
+    // single use like the magic link tokens
+    async fn consume_login_state(
+        &self,
+        state: &str,
+    ) -> Result<Option<OAuthLoginState>, sqlx::Error>;
+
+    async fn delete_expired_login_states(&self) -> Result<(), sqlx::Error>;
+}
+
+#[async_trait]
+pub trait OAuthClientRepositoryTrait: Send + Sync {
+    async fn create(
+        &self,
+        owner_id: Uuid,
+        name: &str,
+        secret: Option<&str>,
+        redirect_uris: &[String],
+        scopes: &[String],
+    ) -> Result<OAuthClient, sqlx::Error>;
+
+    async fn find_by_id(&self, id: Uuid) -> Result<Option<OAuthClient>, sqlx::Error>;
+
+    async fn find_by_owner(&self, owner_id: Uuid) -> Result<Vec<OAuthClient>, sqlx::Error>;
+
+    // the sessions the client had, None if there was no such client
+    async fn delete(&self, id: Uuid, owner_id: Uuid) -> Result<Option<Vec<Uuid>>, sqlx::Error>;
+
+    #[allow(clippy::too_many_arguments)]
+    async fn create_authorization_code(
+        &self,
+        code: &str,
+        client_id: Uuid,
+        user_id: Uuid,
+        redirect_uri: &str,
+        scope: &str,
+        code_challenge: &str,
+        token_version: i32,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error>;
+
+    // single use, a second exchange finds nothing
+    async fn consume_authorization_code(
+        &self,
+        code: &str,
+    ) -> Result<Option<OAuthAuthorizationCode>, sqlx::Error>;
+
+    async fn delete_expired_authorization_codes(&self) -> Result<(), sqlx::Error>;
+}
+
+#[async_trait]
+pub trait PersonalAccessTokenRepositoryTrait: Send + Sync {
+    async fn create(
+        &self,
+        user_id: Uuid,
+        name: &str,
+        token: &str,
+        scopes: &[String],
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<PersonalAccessToken, sqlx::Error>;
+
+    async fn find_by_token(&self, token: &str) -> Result<Option<PersonalAccessToken>, sqlx::Error>;
+
+    async fn find_by_user(&self, user_id: Uuid) -> Result<Vec<PersonalAccessToken>, sqlx::Error>;
+
+    async fn update_last_used(&self, id: Uuid) -> Result<(), sqlx::Error>;
+
+    async fn delete(&self, id: Uuid, user_id: Uuid) -> Result<bool, sqlx::Error>;
+
+    // whenever every session of the user ends, a token would outlive the reason for ending them
+    async fn delete_all_user_tokens(&self, user_id: Uuid) -> Result<(), sqlx::Error>;
+}
+
+#[async_trait]
+pub trait RoleRepositoryTrait: Send + Sync {
+    async fn find_all(&self) -> Result<Vec<Role>, sqlx::Error>;
+
+    async fn find_by_name(&self, name: &str) -> Result<Option<Role>, sqlx::Error>;
+
+    async fn find_role_names_for_user(&self, user_id: Uuid) -> Result<Vec<String>, sqlx::Error>;
+
+    async fn user_has_role(&self, user_id: Uuid, role: &str) -> Result<bool, sqlx::Error>;
+
+    async fn user_has_permission(
+        &self,
+        user_id: Uuid,
+        permission: &str,
+    ) -> Result<bool, sqlx::Error>;
+
+    async fn count_users_with_role(&self, role: &str) -> Result<i64, sqlx::Error>;
+
+    // false if the user already had it
+    async fn grant_role(
+        &self,
+        user_id: Uuid,
+        role: &str,
+        granted_by: Option<Uuid>,
+    ) -> Result<bool, sqlx::Error>;
+
+    async fn revoke_role(&self, user_id: Uuid, role: &str) -> Result<bool, sqlx::Error>;
+}
+
+#[async_trait]
+pub trait AuditEventRepositoryTrait: Send + Sync {
+    async fn record(&self, event: &NewAuditEvent) -> Result<(), sqlx::Error>;
+
+    // newest first
+    async fn find_by_user(
+        &self,
+        user_id: Uuid,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<AuditEvent>, sqlx::Error>;
+
+    // newest first, every filter that is set has to match
+    async fn search(
+        &self,
+        filter: &AuditEventFilter,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<AuditEvent>, sqlx::Error>;
+
+    async fn count(&self, filter: &AuditEventFilter) -> Result<i64, sqlx::Error>;
+}
+
+#[async_trait]
+pub trait KnownDeviceRepositoryTrait: Send + Sync {
+    async fn has_devices(&self, user_id: Uuid) -> Result<bool, sqlx::Error>;
+
+    // most recently seen first
+    async fn find_by_user(&self, user_id: Uuid) -> Result<Vec<KnownDevice>, sqlx::Error>;
+
+    // true if the device wasn't known yet, otherwise just bumps last_seen_at
+    async fn remember(
+        &self,
+        user_id: Uuid,
+        fingerprint: &str,
+        ip_address: &str,
+        user_agent: Option<&str>,
+    ) -> Result<bool, sqlx::Error>;
+
+    async fn create_report_token(
+        &self,
+        user_id: Uuid,
+        session_id: Uuid,
+        token: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<SignInReportToken, sqlx::Error>;
+
+    // single use, deleted on the way out
+    async fn consume_report_token(
+        &self,
+        token: &str,
+    ) -> Result<Option<SignInReportToken>, sqlx::Error>;
+
+    async fn delete_expired_report_tokens(&self) -> Result<(), sqlx::Error>;
+}
+
+#[async_trait]
+pub trait ImpersonationRepositoryTrait: Send + Sync {
+    async fn create(
+        &self,
+        user_id: Uuid,
+        admin_id: Uuid,
+        reason: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<ImpersonationSession, sqlx::Error>;
+
+    async fn find_by_id(&self, id: Uuid) -> Result<Option<ImpersonationSession>, sqlx::Error>;
+
+    // None if it already ended or expired
+    async fn end(&self, id: Uuid) -> Result<Option<ImpersonationSession>, sqlx::Error>;
+
+    // every session the admin still has open
+    async fn end_all_by_admin(
+        &self,
+        admin_id: Uuid,
+    ) -> Result<Vec<ImpersonationSession>, sqlx::Error>;
+
+    // marks the ones that ran out as ended at their expiry, each is returned once
+    async fn close_expired(&self) -> Result<Vec<ImpersonationSession>, sqlx::Error>;
+}
+
+#[async_trait]
+pub trait InviteRepositoryTrait: Send + Sync {
+    async fn create(
+        &self,
+        created_by: Uuid,
+        code: &str,
+        max_uses: i32,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Invite, sqlx::Error>;
+
+    // like create, None if the invite doesn't fit in the user's quota, default_quota is used when
+    // they don't have one of their own
+    async fn create_within_quota(
+        &self,
+        created_by: Uuid,
+        code: &str,
+        max_uses: i32,
+        expires_at: DateTime<Utc>,
+        default_quota: i32,
+    ) -> Result<Option<Invite>, sqlx::Error>;
+
+    async fn find_by_code(&self, code: &str) -> Result<Option<Invite>, sqlx::Error>;
+
+    // takes one use, None if the code is unknown, revoked, expired or used up
+    async fn redeem(&self, code: &str) -> Result<Option<Invite>, sqlx::Error>;
+
+    // gives back a use when the registration failed after redeem
+    async fn release(&self, id: Uuid) -> Result<(), sqlx::Error>;
+
+    async fn record_redemption(&self, invite_id: Uuid, user_id: Uuid) -> Result<(), sqlx::Error>;
+
+    async fn find_by_creator(&self, user_id: Uuid) -> Result<Vec<Invite>, sqlx::Error>;
+
+    // everyone who joined with one of the user's invites
+    async fn find_redemptions_by_creator(
+        &self,
+        user_id: Uuid,
+    ) -> Result<Vec<InviteRedemption>, sqlx::Error>;
+
+    // user_id and username are the inviter's
+    async fn find_inviter(&self, user_id: Uuid) -> Result<Option<InviteRedemption>, sqlx::Error>;
+
+    async fn revoke(&self, id: Uuid, created_by: Uuid) -> Result<bool, sqlx::Error>;
+
+    // people the user's invites can still bring in plus the ones they did
+    async fn count_reserved_uses(&self, user_id: Uuid) -> Result<i64, sqlx::Error>;
+
+    async fn find_quota(&self, user_id: Uuid) -> Result<Option<i32>, sqlx::Error>;
+
+    async fn set_quota(&self, user_id: Uuid, quota: Option<i32>) -> Result<(), sqlx::Error>;
+}
+
+#[async_trait]
+pub trait RateLimitRepositoryTrait: Send + Sync {
+    // counts one request in the given window and returns the total so far
+    async fn hit(&self, key: &str, window_start: DateTime<Utc>) -> Result<i64, sqlx::Error>;
+
+    async fn delete_expired(&self, before: DateTime<Utc>) -> Result<(), sqlx::Error>;
 }
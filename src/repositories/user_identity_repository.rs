@@ -0,0 +1,173 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    auth::tokens::hash_token,
+    models::{OAuthLoginState, UserIdentity},
+    repositories::UserIdentityRepositoryTrait,
+};
+
+#[derive(Clone)]
+pub struct UserIdentityRepository {
+    db: PgPool,
+}
+
+impl UserIdentityRepository {
+    pub fn new(db: PgPool) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl UserIdentityRepositoryTrait for UserIdentityRepository {
+    async fn create(
+        &self,
+        user_id: Uuid,
+        provider: &str,
+        subject: &str,
+        email: Option<&str>,
+    ) -> Result<UserIdentity, sqlx::Error> {
+        let identity = sqlx::query_as::<_, UserIdentity>(
+            r#"
+            INSERT INTO user_identities (user_id, provider, subject, email, last_login_at)
+            VALUES ($1, $2, $3, $4, NOW())
+            RETURNING id, user_id, provider, subject, email, created_at, last_login_at
+            "#,
+        )
+        .bind(user_id)
+        .bind(provider)
+        .bind(subject)
+        .bind(email)
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(identity)
+    }
+
+    async fn find_by_subject(
+        &self,
+        provider: &str,
+        subject: &str,
+    ) -> Result<Option<UserIdentity>, sqlx::Error> {
+        let identity = sqlx::query_as::<_, UserIdentity>(
+            r#"
+            SELECT id, user_id, provider, subject, email, created_at, last_login_at
+            FROM user_identities
+            WHERE provider = $1 AND subject = $2
+            "#,
+        )
+        .bind(provider)
+        .bind(subject)
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(identity)
+    }
+
+    async fn find_by_user(&self, user_id: Uuid) -> Result<Vec<UserIdentity>, sqlx::Error> {
+        let identities = sqlx::query_as::<_, UserIdentity>(
+            r#"
+            SELECT id, user_id, provider, subject, email, created_at, last_login_at
+            FROM user_identities
+            WHERE user_id = $1
+            ORDER BY created_at
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(identities)
+    }
+
+    async fn update_last_login(&self, id: Uuid, email: Option<&str>) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE user_identities
+            SET last_login_at = NOW(), email = COALESCE($2, email)
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .bind(email)
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn delete(&self, user_id: Uuid, provider: &str) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM user_identities
+            WHERE user_id = $1 AND provider = $2
+            "#,
+        )
+        .bind(user_id)
+        .bind(provider)
+        .execute(&self.db)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn create_login_state(
+        &self,
+        state: &str,
+        provider: &str,
+        code_verifier: &str,
+        nonce: &str,
+        link_user_id: Option<Uuid>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO oauth_login_states (state_hash, provider, code_verifier, nonce, link_user_id, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+        )
+        .bind(hash_token(state))
+        .bind(provider)
+        .bind(code_verifier)
+        .bind(nonce)
+        .bind(link_user_id)
+        .bind(expires_at)
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn consume_login_state(
+        &self,
+        state: &str,
+    ) -> Result<Option<OAuthLoginState>, sqlx::Error> {
+        let login_state = sqlx::query_as::<_, OAuthLoginState>(
+            r#"
+            DELETE FROM oauth_login_states
+            WHERE state_hash = $1
+            RETURNING id, state_hash, provider, code_verifier, nonce, link_user_id, expires_at, created_at
+            "#,
+        )
+        .bind(hash_token(state))
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(login_state)
+    }
+
+    async fn delete_expired_login_states(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            DELETE FROM oauth_login_states
+            WHERE expires_at < NOW()
+            "#,
+        )
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+}
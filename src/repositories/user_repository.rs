@@ -1,8 +1,12 @@
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use sqlx::PgPool;
 use uuid::Uuid;
 
-use crate::{models::User, repositories::traits::UserRepositoryTrait};
+use crate::{
+    auth::username_policy::username_skeleton, models::User,
+    repositories::traits::UserRepositoryTrait,
+};
 
 #[derive(Clone)]
 pub struct UserRepository {
@@ -25,14 +29,15 @@ impl UserRepositoryTrait for UserRepository {
     ) -> Result<User, sqlx::Error> {
         let user = sqlx::query_as::<_, User>(
             r#"
-            INSERT INTO users (username, email, password_hash)
-            VALUES ($1, $2, $3)
-            RETURNING id, username, email, password_hash, bio, image, email_verified, created_at, updated_at
+            INSERT INTO users (username, email, password_hash, username_skeleton)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id, username, email, password_hash, bio, image, email_verified, token_version, locked_until, suspended_at, suspension_reason, deleted_at, pending_email, created_at, updated_at
             "#,
         )
         .bind(username)
         .bind(email)
         .bind(password_hash)
+        .bind(username_skeleton(username))
         .fetch_one(&self.db)
         .await?;
 
@@ -42,7 +47,7 @@ impl UserRepositoryTrait for UserRepository {
     async fn find_by_id(&self, id: Uuid) -> Result<Option<User>, sqlx::Error> {
         let user = sqlx::query_as::<_, User>(
             r#"
-            SELECT id, username, email, password_hash, bio, image, email_verified, created_at, updated_at
+            SELECT id, username, email, password_hash, bio, image, email_verified, token_version, locked_until, suspended_at, suspension_reason, deleted_at, pending_email, created_at, updated_at
             FROM users
             WHERE id = $1
             "#,
@@ -57,9 +62,9 @@ impl UserRepositoryTrait for UserRepository {
     async fn find_by_email(&self, email: &str) -> Result<Option<User>, sqlx::Error> {
         let user = sqlx::query_as::<_, User>(
             r#"
-            SELECT id, username, email, password_hash, bio, image, email_verified,created_at, updated_at
+            SELECT id, username, email, password_hash, bio, image, email_verified, token_version, locked_until, suspended_at, suspension_reason, deleted_at, pending_email, created_at, updated_at
             FROM users
-            WHERE email = $1
+            WHERE LOWER(email) = LOWER($1)
             "#,
         )
         .bind(email)
@@ -72,9 +77,9 @@ impl UserRepositoryTrait for UserRepository {
     async fn find_by_username(&self, username: &str) -> Result<Option<User>, sqlx::Error> {
         let user = sqlx::query_as::<_, User>(
             r#"
-            SELECT id, username, email, password_hash, bio, image, email_verified, created_at, updated_at
+            SELECT id, username, email, password_hash, bio, image, email_verified, token_version, locked_until, suspended_at, suspension_reason, deleted_at, pending_email, created_at, updated_at
             FROM users
-            WHERE username = $1
+            WHERE LOWER(username) = LOWER($1)
             "#,
         )
         .bind(username)
@@ -84,6 +89,63 @@ impl UserRepositoryTrait for UserRepository {
         Ok(user)
     }
 
+    async fn username_taken(&self, username: &str) -> Result<bool, sqlx::Error> {
+        let taken = sqlx::query_scalar::<_, bool>(
+            r#"
+            SELECT EXISTS (
+                SELECT 1
+                FROM users
+                WHERE LOWER(username) = LOWER($1) OR username_skeleton = $2
+            )
+            "#,
+        )
+        .bind(username)
+        .bind(username_skeleton(username))
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(taken)
+    }
+
+    async fn backfill_username_skeletons(&self) -> Result<Vec<String>, sqlx::Error> {
+        let users = sqlx::query_as::<_, (Uuid, String)>(
+            r#"
+            SELECT id, username
+            FROM users
+            WHERE username_skeleton IS NULL
+            ORDER BY created_at
+            "#,
+        )
+        .fetch_all(&self.db)
+        .await?;
+
+        // the older account keeps the skeleton, the newer look-alike is left without one
+        let mut collisions = Vec::new();
+        for (id, username) in users {
+            let result = sqlx::query(
+                r#"
+                UPDATE users
+                SET username_skeleton = $2
+                WHERE id = $1
+                "#,
+            )
+            .bind(id)
+            .bind(username_skeleton(&username))
+            .execute(&self.db)
+            .await;
+
+            match result {
+                Ok(_) => {}
+                Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+                    collisions.push(username)
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(collisions)
+    }
+
     async fn reset_password(&self, id: Uuid, password_hash: &str) -> Result<(), sqlx::Error> {
         sqlx::query(
             r#"
@@ -116,7 +178,7 @@ impl UserRepositoryTrait for UserRepository {
                 bio = COALESCE($4, bio),
                 image = COALESCE($5, image),
             WHERE id = $id
-            RETURNING id, username, email, password_hash, bio, image, email_verified, created_at, updated_at
+            RETURNING id, username, email, password_hash, bio, image, email_verified, token_version, locked_until, suspended_at, suspension_reason, deleted_at, pending_email, created_at, updated_at
             "#,
         )
         .bind(id)
@@ -129,4 +191,190 @@ impl UserRepositoryTrait for UserRepository {
 
         Ok(user)
     }
+
+    async fn increment_token_version(&self, id: Uuid) -> Result<i32, sqlx::Error> {
+        let token_version = sqlx::query_scalar::<_, i32>(
+            r#"
+            UPDATE users
+            SET token_version = token_version + 1
+            WHERE id = $1
+            RETURNING token_version
+            "#,
+        )
+        .bind(id)
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(token_version)
+    }
+
+    async fn lock_until(&self, id: Uuid, locked_until: DateTime<Utc>) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE users
+            SET locked_until = $2
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .bind(locked_until)
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn unlock(&self, id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE users
+            SET locked_until = NULL
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn search(
+        &self,
+        query: Option<&str>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<User>, sqlx::Error> {
+        let users = sqlx::query_as::<_, User>(
+            r#"
+            SELECT id, username, email, password_hash, bio, image, email_verified, token_version, locked_until, suspended_at, suspension_reason, deleted_at, pending_email, created_at, updated_at
+            FROM users
+            WHERE $1::TEXT IS NULL
+               OR username ILIKE '%' || $1 || '%'
+               OR email ILIKE '%' || $1 || '%'
+            ORDER BY created_at DESC, id
+            LIMIT $2 OFFSET $3
+            "#,
+        )
+        .bind(query.map(escape_like))
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(users)
+    }
+
+    async fn count(&self, query: Option<&str>) -> Result<i64, sqlx::Error> {
+        let count = sqlx::query_scalar::<_, i64>(
+            r#"
+            SELECT COUNT(*)
+            FROM users
+            WHERE $1::TEXT IS NULL
+               OR username ILIKE '%' || $1 || '%'
+               OR email ILIKE '%' || $1 || '%'
+            "#,
+        )
+        .bind(query.map(escape_like))
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(count)
+    }
+
+    async fn suspend(&self, id: Uuid, reason: Option<&str>) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE users
+            SET suspended_at = NOW(), suspension_reason = $2
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .bind(reason)
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn unsuspend(&self, id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE users
+            SET suspended_at = NULL, suspension_reason = NULL
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn schedule_deletion(&self, id: Uuid) -> Result<DateTime<Utc>, sqlx::Error> {
+        let deleted_at = sqlx::query_scalar::<_, DateTime<Utc>>(
+            r#"
+            UPDATE users
+            SET deleted_at = NOW()
+            WHERE id = $1
+            RETURNING deleted_at
+            "#,
+        )
+        .bind(id)
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(deleted_at)
+    }
+
+    async fn restore(&self, id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE users
+            SET deleted_at = NULL
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn purge_deleted(&self, deleted_before: DateTime<Utc>) -> Result<Vec<Uuid>, sqlx::Error> {
+        // the foreign keys take care of everything keyed by user_id, failed logins and the
+        // failures logged for the email before the account was found only know the address
+        let purged = sqlx::query_scalar::<_, Uuid>(
+            r#"
+            WITH purged AS (
+                DELETE FROM users
+                WHERE deleted_at IS NOT NULL AND deleted_at < $1
+                RETURNING id, LOWER(email) AS email
+            ), failed_logins AS (
+                DELETE FROM failed_login_attempts
+                WHERE email IN (SELECT email FROM purged)
+            ), anonymous_events AS (
+                DELETE FROM audit_events
+                WHERE user_id IS NULL AND LOWER(metadata->>'email') IN (SELECT email FROM purged)
+            )
+            SELECT id FROM purged
+            "#,
+        )
+        .bind(deleted_before)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(purged)
+    }
+}
+
+// a search for "a_b" shouldn't match "axb"
+fn escape_like(query: &str) -> String {
+    query
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
 }
@@ -0,0 +1,78 @@
+use axum::{
+    Router,
+    routing::{delete, get, post, put},
+};
+
+use crate::{
+    handlers::{
+        get_user, get_user_invites, get_user_roles, grant_role, impersonate_user,
+        list_audit_events, list_roles, list_users, logout_user, revoke_role,
+        send_user_password_reset, set_invite_quota, stop_impersonation, suspend_user,
+        unsuspend_user, verify_user_email,
+    },
+    rate_limit::{RateLimitKey, RateLimitLayer, RateLimitPolicy},
+    state::AppState,
+};
+
+// every handler checks its own role or permission
+pub fn admin_routes(state: &AppState) -> Router<AppState> {
+    let role_routes = Router::new()
+        .route("/roles", get(list_roles))
+        .route("/users/{user_id}/roles", get(get_user_roles))
+        .route(
+            "/users/{user_id}/roles/{role}",
+            put(grant_role).delete(revoke_role),
+        )
+        .route_layer(RateLimitLayer::new(
+            state,
+            RateLimitPolicy::from_env("admin-roles", 60, 60, RateLimitKey::User),
+        ));
+
+    let user_routes = Router::new()
+        .route("/users", get(list_users))
+        .route("/users/{user_id}", get(get_user))
+        .route("/users/{user_id}/verify-email", post(verify_user_email))
+        .route(
+            "/users/{user_id}/password-reset",
+            post(send_user_password_reset),
+        )
+        .route(
+            "/users/{user_id}/suspension",
+            put(suspend_user).delete(unsuspend_user),
+        )
+        .route("/users/{user_id}/logout", post(logout_user))
+        .route_layer(RateLimitLayer::new(
+            state,
+            RateLimitPolicy::from_env("admin-users", 120, 60, RateLimitKey::User),
+        ));
+
+    let impersonation_routes = Router::new()
+        .route("/users/{user_id}/impersonate", post(impersonate_user))
+        .route("/impersonations/{session_id}", delete(stop_impersonation))
+        .route_layer(RateLimitLayer::new(
+            state,
+            RateLimitPolicy::from_env("admin-impersonation", 20, 60, RateLimitKey::User),
+        ));
+
+    let invite_routes = Router::new()
+        .route("/users/{user_id}/invites", get(get_user_invites))
+        .route("/users/{user_id}/invite-quota", put(set_invite_quota))
+        .route_layer(RateLimitLayer::new(
+            state,
+            RateLimitPolicy::from_env("admin-invites", 60, 60, RateLimitKey::User),
+        ));
+
+    let audit_routes = Router::new()
+        .route("/audit-events", get(list_audit_events))
+        .route_layer(RateLimitLayer::new(
+            state,
+            RateLimitPolicy::from_env("admin-audit", 60, 60, RateLimitKey::User),
+        ));
+
+    Router::new()
+        .merge(role_routes)
+        .merge(user_routes)
+        .merge(impersonation_routes)
+        .merge(invite_routes)
+        .merge(audit_routes)
+}
@@ -1,18 +1,90 @@
 use axum::{
-    Router,
+    Extension, Router,
     routing::{get, post},
 };
 
 use crate::{
-    handlers::{forgot_password, logout, refresh_token, reset_password, verify_email},
+    auth::impersonation::NoImpersonation,
+    handlers::{
+        confirm_email_change, consume_magic_link, forgot_password, logout, logout_all,
+        oauth_authorize, oauth_callback, reauthenticate, refresh_token, report_sign_in,
+        request_magic_link, resend_verification, reset_password, revert_email_change,
+        unlock_account, verify_email,
+    },
+    rate_limit::{RateLimitKey, RateLimitLayer, RateLimitPolicy},
     state::AppState,
 };
 
-pub fn auth_routes() -> Router<AppState> {
-    Router::new()
-        .route("/verify-email", get(verify_email))
+pub fn auth_routes(state: &AppState) -> Router<AppState> {
+    // every request sends an email, keep it from being used to spam inboxes
+    let email_routes = Router::new()
         .route("/forgot-password", post(forgot_password))
+        .route_layer(RateLimitLayer::new(
+            state,
+            RateLimitPolicy::from_env("forgot-password", 5, 3600, RateLimitKey::Ip),
+        ));
+
+    let verification_routes = Router::new()
+        .route("/resend-verification", post(resend_verification))
+        .route_layer(RateLimitLayer::new(
+            state,
+            RateLimitPolicy::from_env("resend-verification", 5, 3600, RateLimitKey::Ip),
+        ));
+
+    let magic_link_routes = Router::new()
+        .route("/magic-link", post(request_magic_link))
+        .route_layer(RateLimitLayer::new(
+            state,
+            RateLimitPolicy::from_env("magic-link", 5, 3600, RateLimitKey::Ip),
+        ));
+
+    let token_routes = Router::new()
+        .route("/verify-email", get(verify_email))
+        .route("/unlock-account", get(unlock_account))
+        .route("/report-sign-in", get(report_sign_in))
+        .route("/confirm-email-change", get(confirm_email_change))
+        .route("/revert-email-change", get(revert_email_change))
+        .route("/magic-link/consume", get(consume_magic_link))
         .route("/reset-password", post(reset_password))
         .route("/refresh", post(refresh_token))
         .route("/logout", post(logout))
+        .route_layer(RateLimitLayer::new(
+            state,
+            RateLimitPolicy::from_env("auth-tokens", 30, 60, RateLimitKey::Ip),
+        ));
+
+    // each authorize call stores a pending login
+    let oauth_routes = Router::new()
+        .route("/oauth/{provider}", get(oauth_authorize))
+        .route("/oauth/{provider}/callback", get(oauth_callback))
+        .route_layer(RateLimitLayer::new(
+            state,
+            RateLimitPolicy::from_env("oauth", 30, 60, RateLimitKey::Ip),
+        ));
+
+    let session_routes = Router::new()
+        .route("/logout-all", post(logout_all))
+        .route_layer(RateLimitLayer::new(
+            state,
+            RateLimitPolicy::from_env("auth-sessions", 10, 60, RateLimitKey::User),
+        ))
+        .route_layer(Extension(NoImpersonation));
+
+    // password guesses with a stolen access token, the login throttle applies on top
+    let reauthentication_routes = Router::new()
+        .route("/reauthenticate", post(reauthenticate))
+        .route_layer(RateLimitLayer::new(
+            state,
+            RateLimitPolicy::from_env("reauthenticate", 10, 900, RateLimitKey::User),
+        ))
+        .route_layer(Extension(NoImpersonation));
+
+    Router::new()
+        .merge(email_routes)
+        .merge(verification_routes)
+        .merge(magic_link_routes)
+        .merge(token_routes)
+        .merge(oauth_routes)
+        .merge(session_routes)
+        .merge(reauthentication_routes)
 }
@@ -1,7 +1,11 @@
+pub mod admin;
 pub mod auth;
+pub mod oauth;
 pub mod static_assets;
 pub mod user;
 
+pub use admin::admin_routes;
 pub use auth::auth_routes;
+pub use oauth::oauth_routes;
 pub use static_assets::create_static_asset_router;
 pub use user::user_routes;
@@ -0,0 +1,48 @@
+use axum::{
+    Extension, Router,
+    routing::{delete, get, post},
+};
+
+use crate::{
+    auth::impersonation::NoImpersonation,
+    handlers::{
+        authorize, authorize_page, delete_client, introspect, list_clients, register_client,
+        revoke, token,
+    },
+    rate_limit::{RateLimitKey, RateLimitLayer, RateLimitPolicy},
+    state::AppState,
+};
+
+// us as the authorization server for third-party clients, /api/auth/oauth is the other way round
+pub fn oauth_routes(state: &AppState) -> Router<AppState> {
+    // the consent form checks passwords, login throttling applies on top
+    let authorize_routes = Router::new()
+        .route("/authorize", get(authorize_page).post(authorize))
+        .route_layer(RateLimitLayer::new(
+            state,
+            RateLimitPolicy::from_env("oauth-authorize", 30, 60, RateLimitKey::Ip),
+        ));
+
+    let token_routes = Router::new()
+        .route("/token", post(token))
+        .route("/revoke", post(revoke))
+        .route("/introspect", post(introspect))
+        .route_layer(RateLimitLayer::new(
+            state,
+            RateLimitPolicy::from_env("oauth-token", 120, 60, RateLimitKey::Ip),
+        ));
+
+    let client_routes = Router::new()
+        .route("/clients", get(list_clients).post(register_client))
+        .route("/clients/{client_id}", delete(delete_client))
+        .route_layer(RateLimitLayer::new(
+            state,
+            RateLimitPolicy::from_env("oauth-clients", 30, 60, RateLimitKey::User),
+        ))
+        .route_layer(Extension(NoImpersonation));
+
+    Router::new()
+        .merge(authorize_routes)
+        .merge(token_routes)
+        .merge(client_routes)
+}
@@ -1,16 +1,121 @@
 use axum::{
-    Router,
-    routing::{get, post},
+    Extension, Router,
+    routing::{delete, get, post, put},
 };
 
 use crate::{
-    handlers::{current_user, login, register},
+    auth::{impersonation::NoImpersonation, oauth_server::RequiredScope},
+    handlers::{
+        change_password, create_access_token, create_invite, current_user, delete_access_token,
+        delete_account, export_account, link_identity, list_access_tokens, list_identities,
+        list_invites, list_security_events, login, register, request_email_change, revoke_invite,
+        unlink_identity,
+    },
+    rate_limit::{RateLimitKey, RateLimitLayer, RateLimitPolicy},
     state::AppState,
 };
 
-pub fn user_routes() -> Router<AppState> {
-    Router::new()
-        .route("/users", post(register))
+pub fn user_routes(state: &AppState) -> Router<AppState> {
+    // sends a verification email per account
+    let registration_routes =
+        Router::new()
+            .route("/users", post(register))
+            .route_layer(RateLimitLayer::new(
+                state,
+                RateLimitPolicy::from_env("register", 5, 3600, RateLimitKey::Ip),
+            ));
+
+    // failed logins are throttled per account on top of this
+    let login_routes = Router::new()
         .route("/users/login", post(login))
+        .route_layer(RateLimitLayer::new(
+            state,
+            RateLimitPolicy::from_env("login", 20, 60, RateLimitKey::Ip),
+        ));
+
+    // also open to third-party clients granted profile:read
+    let profile_routes = Router::new()
         .route("/user", get(current_user))
+        .route_layer(RateLimitLayer::new(
+            state,
+            RateLimitPolicy::from_env("user", 120, 60, RateLimitKey::User),
+        ))
+        .route_layer(Extension(RequiredScope("profile:read")));
+
+    // guesses at the current password with a stolen access token
+    let password_routes = Router::new()
+        .route("/user/password", post(change_password))
+        .route_layer(RateLimitLayer::new(
+            state,
+            RateLimitPolicy::from_env("change-password", 5, 900, RateLimitKey::User),
+        ))
+        .route_layer(Extension(NoImpersonation));
+
+    let identity_routes = Router::new()
+        .route("/user/identities", get(list_identities))
+        .route("/user/identities/{provider}", post(link_identity))
+        .route("/user/identities/{provider}", delete(unlink_identity))
+        .route_layer(RateLimitLayer::new(
+            state,
+            RateLimitPolicy::from_env("identities", 30, 60, RateLimitKey::User),
+        ))
+        .route_layer(Extension(NoImpersonation));
+
+    let access_token_routes = Router::new()
+        .route(
+            "/user/tokens",
+            get(list_access_tokens).post(create_access_token),
+        )
+        .route("/user/tokens/{token_id}", delete(delete_access_token))
+        .route_layer(RateLimitLayer::new(
+            state,
+            RateLimitPolicy::from_env("access-tokens", 30, 60, RateLimitKey::User),
+        ))
+        .route_layer(Extension(NoImpersonation));
+
+    // an export reads everything the account has, deleting sends an email
+    let account_routes = Router::new()
+        .route("/user", delete(delete_account))
+        .route("/user/export", post(export_account))
+        .route_layer(RateLimitLayer::new(
+            state,
+            RateLimitPolicy::from_env("account", 5, 3600, RateLimitKey::User),
+        ))
+        .route_layer(Extension(NoImpersonation));
+
+    // sends two emails, one of them to an address that isn't verified yet
+    let email_change_routes = Router::new()
+        .route("/user/email", put(request_email_change))
+        .route_layer(RateLimitLayer::new(
+            state,
+            RateLimitPolicy::from_env("email-change", 5, 3600, RateLimitKey::User),
+        ))
+        .route_layer(Extension(NoImpersonation));
+
+    let invite_routes = Router::new()
+        .route("/user/invites", get(list_invites).post(create_invite))
+        .route("/user/invites/{invite_id}", delete(revoke_invite))
+        .route_layer(RateLimitLayer::new(
+            state,
+            RateLimitPolicy::from_env("invites", 30, 60, RateLimitKey::User),
+        ));
+
+    let security_event_routes = Router::new()
+        .route("/user/security-events", get(list_security_events))
+        .route_layer(RateLimitLayer::new(
+            state,
+            RateLimitPolicy::from_env("security-events", 30, 60, RateLimitKey::User),
+        ));
+
+    Router::new()
+        .merge(registration_routes)
+        .merge(login_routes)
+        .merge(profile_routes)
+        .merge(password_routes)
+        .merge(identity_routes)
+        .merge(access_token_routes)
+        .merge(account_routes)
+        .merge(email_change_routes)
+        .merge(invite_routes)
+        .merge(security_event_routes)
 }
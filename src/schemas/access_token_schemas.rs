@@ -0,0 +1,58 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::models::PersonalAccessToken;
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateAccessTokenRequest {
+    #[validate(length(
+        min = 1,
+        max = 100,
+        message = "Name must be between 1 and 100 characters"
+    ))]
+    pub name: String,
+
+    #[validate(length(min = 1, message = "At least one scope is required"))]
+    pub scopes: Vec<String>,
+
+    // 90 days when left out
+    #[validate(range(min = 1, max = 365, message = "Tokens expire after 1 to 365 days"))]
+    pub expires_in_days: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AccessTokenData {
+    pub id: Uuid,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl AccessTokenData {
+    pub fn from_access_token(access_token: PersonalAccessToken) -> Self {
+        Self {
+            id: access_token.id,
+            name: access_token.name,
+            scopes: access_token.scopes,
+            expires_at: access_token.expires_at,
+            last_used_at: access_token.last_used_at,
+            created_at: access_token.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreateAccessTokenResponse {
+    pub access_token: AccessTokenData,
+    // shown this one time only, we keep nothing but its hash
+    pub token: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AccessTokensResponse {
+    pub access_tokens: Vec<AccessTokenData>,
+}
@@ -0,0 +1,118 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    models::{KnownDevice, RefreshToken, User},
+    schemas::{
+        AccessTokenData, InviteData, InviteUserData, OAuthClientData, SecurityEventData,
+        UserIdentityData,
+    },
+};
+
+#[derive(Debug, Serialize)]
+pub struct DeleteAccountResponse {
+    pub message: String,
+    // logging in before then restores the account
+    pub purge_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct ChangeEmailRequest {
+    #[validate(email(message = "Invalid email format"))]
+    pub email: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChangeEmailResponse {
+    pub message: String,
+    // users.email stays the same until this one is confirmed
+    pub pending_email: String,
+}
+
+// everything we store about a user, for POST /api/user/export
+#[derive(Debug, Serialize)]
+pub struct AccountExport {
+    pub exported_at: DateTime<Utc>,
+    pub profile: ExportProfileData,
+    pub roles: Vec<String>,
+    pub sessions: Vec<ExportSessionData>,
+    pub identities: Vec<UserIdentityData>,
+    pub access_tokens: Vec<AccessTokenData>,
+    pub oauth_clients: Vec<OAuthClientData>,
+    pub known_devices: Vec<ExportDeviceData>,
+    pub invites: Vec<InviteData>,
+    pub invited_by: Option<InviteUserData>,
+    pub security_events: Vec<SecurityEventData>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExportProfileData {
+    pub id: Uuid,
+    pub username: String,
+    pub email: String,
+    pub bio: Option<String>,
+    pub image: Option<String>,
+    pub email_verified: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl ExportProfileData {
+    pub fn from_user(user: User) -> Self {
+        Self {
+            id: user.id,
+            username: user.username,
+            email: user.email,
+            bio: user.bio,
+            image: user.image,
+            email_verified: user.email_verified,
+            created_at: user.created_at,
+            updated_at: user.updated_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExportSessionData {
+    pub session_id: Uuid,
+    // set when the session belongs to a third-party app
+    pub client_id: Option<Uuid>,
+    pub scope: Option<String>,
+    pub authenticated_at: DateTime<Utc>,
+    pub last_used_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl ExportSessionData {
+    pub fn from_refresh_token(refresh_token: RefreshToken) -> Self {
+        Self {
+            session_id: refresh_token.session_id,
+            client_id: refresh_token.client_id,
+            scope: refresh_token.scope,
+            authenticated_at: refresh_token.authenticated_at,
+            last_used_at: refresh_token.last_used_at,
+            expires_at: refresh_token.expires_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExportDeviceData {
+    pub ip_address: String,
+    pub user_agent: Option<String>,
+    pub first_seen_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+}
+
+impl ExportDeviceData {
+    pub fn from_device(device: KnownDevice) -> Self {
+        Self {
+            ip_address: device.ip_address,
+            user_agent: device.user_agent,
+            first_seen_at: device.first_seen_at,
+            last_seen_at: device.last_seen_at,
+        }
+    }
+}
@@ -0,0 +1,186 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::models::{AuditEvent, RefreshToken, Role, User};
+
+#[derive(Debug, Serialize)]
+pub struct RoleData {
+    pub name: String,
+    pub description: String,
+    pub permissions: Vec<String>,
+}
+
+impl RoleData {
+    pub fn from_role(role: Role) -> Self {
+        Self {
+            name: role.name,
+            description: role.description,
+            permissions: role.permissions,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct RolesResponse {
+    pub roles: Vec<RoleData>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UserRolesResponse {
+    pub roles: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UserSearchParams {
+    // part of a username or email
+    pub q: Option<String>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+// everything support may look at, still without the password hash
+#[derive(Debug, Serialize)]
+pub struct AdminUserData {
+    pub id: Uuid,
+    pub username: String,
+    pub email: String,
+    pub email_verified: bool,
+    pub locked_until: Option<DateTime<Utc>>,
+    pub suspended_at: Option<DateTime<Utc>>,
+    pub suspension_reason: Option<String>,
+    // deleted by the user, purged once the grace period is over
+    pub deleted_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl AdminUserData {
+    pub fn from_user(user: User) -> Self {
+        Self {
+            id: user.id,
+            username: user.username,
+            email: user.email,
+            email_verified: user.email_verified,
+            locked_until: user.locked_until,
+            suspended_at: user.suspended_at,
+            suspension_reason: user.suspension_reason,
+            deleted_at: user.deleted_at,
+            created_at: user.created_at,
+            updated_at: user.updated_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct AdminUsersResponse {
+    pub users: Vec<AdminUserData>,
+    pub total: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AdminSessionData {
+    pub session_id: Uuid,
+    // set when the session belongs to a third-party app
+    pub client_id: Option<Uuid>,
+    pub last_used_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl AdminSessionData {
+    pub fn from_refresh_token(refresh_token: RefreshToken) -> Self {
+        Self {
+            session_id: refresh_token.session_id,
+            client_id: refresh_token.client_id,
+            last_used_at: refresh_token.last_used_at,
+            expires_at: refresh_token.expires_at,
+        }
+    }
+}
+
+// the whole record, users get the trimmed down SecurityEventData
+#[derive(Debug, Serialize)]
+pub struct AuditEventData {
+    pub id: Uuid,
+    pub event_type: String,
+    pub user_id: Option<Uuid>,
+    pub actor_id: Option<Uuid>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub metadata: Value,
+    pub created_at: DateTime<Utc>,
+}
+
+impl AuditEventData {
+    pub fn from_event(event: AuditEvent) -> Self {
+        Self {
+            id: event.id,
+            event_type: event.event_type,
+            user_id: event.user_id,
+            actor_id: event.actor_id,
+            ip_address: event.ip_address,
+            user_agent: event.user_agent,
+            metadata: event.metadata,
+            created_at: event.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct AdminUserResponse {
+    pub user: AdminUserData,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AdminUserDetailResponse {
+    pub user: AdminUserData,
+    pub roles: Vec<String>,
+    pub sessions: Vec<AdminSessionData>,
+    // most recent first
+    pub events: Vec<AuditEventData>,
+}
+
+#[derive(Debug, Default, Deserialize, Validate)]
+pub struct SuspendUserRequest {
+    #[validate(length(max = 500, message = "Reason cannot exceed 500 characters"))]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuditEventParams {
+    pub user_id: Option<Uuid>,
+    pub actor_id: Option<Uuid>,
+    pub event_type: Option<String>,
+    pub ip_address: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuditEventsResponse {
+    pub events: Vec<AuditEventData>,
+    pub total: i64,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct ImpersonateUserRequest {
+    // ends up in the audit log, e.g. the support ticket
+    #[validate(length(
+        min = 1,
+        max = 500,
+        message = "Reason must be between 1 and 500 characters"
+    ))]
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImpersonationResponse {
+    pub session_id: Uuid,
+    pub access_token: String,
+    pub expires_at: DateTime<Utc>,
+    pub user: AdminUserData,
+}
@@ -18,8 +18,11 @@ pub struct RegisterUserData {
     #[validate(email(message = "Invalid Email format"))]
     pub email: String,
 
-    #[validate(length(min = 8, message = "Password must be at least 8 characters"))]
+    // checked against the password policy in the handler
     pub password: String,
+
+    // required with REGISTRATION_MODE=invite, recorded as who invited whom in any mode
+    pub invite_code: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -40,7 +43,21 @@ pub struct LoginUserData {
 pub struct LoginUserResponse {
     pub user: UserData,
     pub access_token: String,
-    pub refresh_token: String,
+    // left out when it is delivered as a cookie
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+}
+
+// registration with enumeration protection, looks the same whether the email was taken or not
+#[derive(Debug, Serialize)]
+pub struct RegistrationPendingResponse {
+    pub message: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct ResendVerificationRequest {
+    #[validate(email(message = "Invalid email format"))]
+    pub email: String,
 }
 
 #[derive(Debug, Serialize)]
@@ -55,6 +72,8 @@ pub struct UserData {
     pub bio: String,           // empty string if none in db
     pub image: Option<String>, // null in json if none
     pub email_verified: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pending_email: Option<String>, // waiting for confirmation
 }
 
 impl UserData {
@@ -65,6 +84,18 @@ impl UserData {
             bio: user.bio.unwrap_or_default(),
             image: user.image,
             email_verified: user.email_verified,
+            pending_email: user.pending_email,
         }
     }
 }
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct MagicLinkRequest {
+    #[validate(email(message = "Invalid email format"))]
+    pub email: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MagicLinkResponse {
+    pub message: String,
+}
@@ -0,0 +1,41 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::models::UserIdentity;
+
+#[derive(Debug, Deserialize)]
+pub struct OAuthCallbackParams {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    // set instead of code when the user declined or the provider failed
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuthorizationUrlResponse {
+    pub authorization_url: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UserIdentityData {
+    pub provider: String,
+    pub email: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_login_at: Option<DateTime<Utc>>,
+}
+
+impl UserIdentityData {
+    pub fn from_identity(identity: UserIdentity) -> Self {
+        Self {
+            provider: identity.provider,
+            email: identity.email,
+            created_at: identity.created_at,
+            last_login_at: identity.last_login_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct UserIdentitiesResponse {
+    pub identities: Vec<UserIdentityData>,
+}
@@ -0,0 +1,82 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::models::{Invite, InviteRedemption};
+
+#[derive(Debug, Default, Deserialize, Validate)]
+pub struct CreateInviteRequest {
+    // 1 if left out
+    #[validate(range(min = 1, max = 100, message = "An invite is good for 1 to 100 people"))]
+    pub max_uses: Option<i32>,
+
+    // INVITE_TTL_DAYS if left out, and at most that without invites:manage
+    #[validate(range(min = 1, max = 365, message = "Invites expire after 1 to 365 days"))]
+    pub expires_in_days: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InviteUserData {
+    pub id: Uuid,
+    pub username: String,
+    // when the invite was used to register
+    pub joined_at: DateTime<Utc>,
+}
+
+impl InviteUserData {
+    pub fn from_redemption(redemption: InviteRedemption) -> Self {
+        Self {
+            id: redemption.user_id,
+            username: redemption.username,
+            joined_at: redemption.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct InviteData {
+    pub id: Uuid,
+    pub max_uses: i32,
+    pub uses: i32,
+    pub expires_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub invited_users: Vec<InviteUserData>,
+}
+
+impl InviteData {
+    pub fn from_invite(invite: Invite, invited_users: Vec<InviteUserData>) -> Self {
+        Self {
+            id: invite.id,
+            max_uses: invite.max_uses,
+            uses: invite.uses,
+            expires_at: invite.expires_at,
+            revoked_at: invite.revoked_at,
+            created_at: invite.created_at,
+            invited_users,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreateInviteResponse {
+    pub invite: InviteData,
+    // shown this one time only, we keep nothing but its hash
+    pub code: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InvitesResponse {
+    pub invites: Vec<InviteData>,
+    // how many more people can be invited, null without a quota (invites:manage)
+    pub remaining_invites: Option<i64>,
+    pub invited_by: Option<InviteUserData>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct InviteQuotaRequest {
+    // null goes back to INVITE_DEFAULT_QUOTA
+    #[validate(range(min = 0, max = 10000, message = "Quota must be between 0 and 10000"))]
+    pub quota: Option<i32>,
+}
@@ -5,12 +5,26 @@
 // creating), API responses might exclude sensitive fields (like password_hash), and validation happens on DTOs,
 // not database models (because this is the structure that we are using for request and response, the database model
 // structure is used for storage and retrieval of data)
+pub mod access_token_schemas;
+pub mod account_schemas;
+pub mod admin_schemas;
 pub mod auth_schemas;
+pub mod identity_schemas;
+pub mod invite_schemas;
+pub mod oauth_schemas;
 pub mod password_reset_schemas;
+pub mod security_event_schemas;
 pub mod token_schemas;
 pub mod user_schemas;
 
+pub use access_token_schemas::*;
+pub use account_schemas::*;
+pub use admin_schemas::*;
 pub use auth_schemas::*;
+pub use identity_schemas::*;
+pub use invite_schemas::*;
+pub use oauth_schemas::*;
 pub use password_reset_schemas::*;
+pub use security_event_schemas::*;
 pub use token_schemas::*;
 pub use user_schemas::{CreateUserRequest, UpdateUserRequest, UserResponse};
@@ -0,0 +1,136 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::models::OAuthClient;
+
+// query of GET /api/oauth/authorize, repeated as hidden fields in the consent form
+#[derive(Debug, Clone, Deserialize)]
+pub struct AuthorizeParams {
+    pub response_type: Option<String>,
+    pub client_id: Option<String>,
+    pub redirect_uri: Option<String>,
+    pub scope: Option<String>,
+    pub state: Option<String>,
+    pub code_challenge: Option<String>,
+    pub code_challenge_method: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuthorizeDecision {
+    #[serde(flatten)]
+    pub params: AuthorizeParams,
+    pub email: String,
+    pub password: String,
+    // "allow" or "deny", whichever button was pressed
+    pub decision: String,
+}
+
+// form encoded, fields depend on grant_type
+#[derive(Debug, Deserialize)]
+pub struct TokenRequest {
+    pub grant_type: String,
+    pub code: Option<String>,
+    pub redirect_uri: Option<String>,
+    pub code_verifier: Option<String>,
+    pub refresh_token: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TokenResponse {
+    pub access_token: String,
+    pub token_type: &'static str,
+    pub expires_in: i64,
+    pub refresh_token: String,
+    pub scope: String,
+}
+
+// used for both revocation and introspection
+#[derive(Debug, Deserialize)]
+pub struct TokenLookupRequest {
+    pub token: String,
+    // access_token or refresh_token, we find out either way
+    pub token_type_hint: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct IntrospectionResponse {
+    pub active: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_type: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exp: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iat: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct RegisterClientRequest {
+    #[validate(length(
+        min = 1,
+        max = 100,
+        message = "Name must be between 1 and 100 characters"
+    ))]
+    pub name: String,
+
+    #[validate(length(
+        min = 1,
+        max = 10,
+        message = "Between 1 and 10 redirect URIs are required"
+    ))]
+    pub redirect_uris: Vec<String>,
+
+    #[validate(length(min = 1, message = "At least one scope is required"))]
+    pub scopes: Vec<String>,
+
+    // false for apps that can't keep a secret, like SPAs or mobile apps
+    #[serde(default)]
+    pub confidential: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OAuthClientData {
+    pub client_id: Uuid,
+    pub name: String,
+    pub redirect_uris: Vec<String>,
+    pub scopes: Vec<String>,
+    pub confidential: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+impl OAuthClientData {
+    pub fn from_client(client: OAuthClient) -> Self {
+        Self {
+            client_id: client.id,
+            confidential: client.is_confidential(),
+            name: client.name,
+            redirect_uris: client.redirect_uris,
+            scopes: client.scopes,
+            created_at: client.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct RegisterClientResponse {
+    pub client: OAuthClientData,
+    // only ever shown here, we keep nothing but its hash
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_secret: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OAuthClientsResponse {
+    pub clients: Vec<OAuthClientData>,
+}
@@ -16,7 +16,6 @@ pub struct ForgotPasswordResponse {
 pub struct ResetPasswordRequest {
     #[validate(length(max = 128, message = "Token limit exceeded"))]
     pub token: String,
-    #[validate(length(min = 8, message = "Password must be at least 8 characters"))]
     pub new_password: String,
 }
 
@@ -24,3 +23,15 @@ pub struct ResetPasswordRequest {
 pub struct ResetPasswordResponse {
     pub message: String,
 }
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct ChangePasswordRequest {
+    #[validate(length(max = 128, message = "Password limit exceeded"))]
+    pub current_password: String,
+    pub new_password: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChangePasswordResponse {
+    pub message: String,
+}
@@ -0,0 +1,43 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::models::AuditEvent;
+
+#[derive(Debug, Deserialize)]
+pub struct SecurityEventParams {
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+// a user's own view of the audit log, without internal ids
+#[derive(Debug, Serialize)]
+pub struct SecurityEventData {
+    pub event_type: String,
+    // true when an admin did it, not the user
+    pub by_admin: bool,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub metadata: Value,
+    pub created_at: DateTime<Utc>,
+}
+
+impl SecurityEventData {
+    pub fn from_event(event: AuditEvent) -> Self {
+        Self {
+            by_admin: event
+                .actor_id
+                .is_some_and(|actor_id| Some(actor_id) != event.user_id),
+            event_type: event.event_type,
+            ip_address: event.ip_address,
+            user_agent: event.user_agent,
+            metadata: event.metadata,
+            created_at: event.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SecurityEventsResponse {
+    pub events: Vec<SecurityEventData>,
+}
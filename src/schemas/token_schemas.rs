@@ -1,4 +1,6 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use validator::Validate;
 
 #[derive(Debug, Deserialize)]
 pub struct RefreshTokenRequest {
@@ -8,7 +10,8 @@ pub struct RefreshTokenRequest {
 #[derive(Debug, Serialize)]
 pub struct RefreshTokenResponse {
     pub access_token: String,
-    pub refresh_token: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -20,3 +23,16 @@ pub struct LogoutRequest {
 pub struct LogoutResponse {
     pub message: String,
 }
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct ReauthenticateRequest {
+    #[validate(length(max = 128, message = "Password limit exceeded"))]
+    pub password: String,
+}
+
+// the refresh token stays the same, its session remembers the new time
+#[derive(Debug, Serialize)]
+pub struct ReauthenticateResponse {
+    pub access_token: String,
+    pub auth_time: DateTime<Utc>,
+}
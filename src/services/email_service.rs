@@ -1,6 +1,6 @@
-use std::env;
+use std::{env, net::IpAddr};
 
-use chrono::{Datelike, Local};
+use chrono::{DateTime, Datelike, Local, Utc};
 use lettre::{
     Message, SmtpTransport, Transport,
     message::{Mailbox, header::ContentType},
@@ -271,4 +271,538 @@ impl EmailService {
 
         Ok(())
     }
+
+    pub async fn send_account_locked_email(
+        &self,
+        to_email: &str,
+        username: &str,
+        unlock_token: &str,
+        locked_minutes: i64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let base_var = env::var("BASE_URL").unwrap_or_else(|_| "http://localhost:3000".to_string());
+        let unlock_link = format!(
+            "{}/api/auth/unlock-account?token={}",
+            base_var, unlock_token
+        );
+
+        let app_name = env::var("APP_NAME").unwrap_or_else(|_| "MyApp".to_string());
+        let current_year = Local::now().date_naive().year().to_string();
+
+        let html_body = format!(
+            r#"
+            <!DOCTYPE html>
+            <html>
+            <head>
+                <style>
+                    body {{ font-family: Arial, sans-serif; line-height: 1.6; color: #333; }}
+                    .container {{ max-width: 600px; margin: 0 auto; padding: 20px; }}
+                    .header {{ background-color: #dc3545; color: white; padding: 20px; text-align: center; }}
+                    .content {{ background-color: #f9f9f9; padding: 30px; border-radius: 5px; margin-top: 20px; }}
+                    .button {{ display: inline-block; padding: 12px 24px; background-color: #dc3545; color: white; text-decoration: none; border-radius: 5px; margin: 20px 0; }}
+                    .alert-box {{ background-color: #fff3cd; border-left: 4px solid #ffc107; padding: 15px; margin: 20px 0; }}
+                    .footer {{ text-align: center; margin-top: 20px; color: #666; font-size: 12px; }}
+                </style>
+            </head>
+            <body>
+                <div class="container">
+                    <div class="header">
+                        <h1>Account Temporarily Locked</h1>
+                    </div>
+                    <div class="content">
+                        <h2>Hi {}!</h2>
+                        <p>There were too many failed sign-in attempts on your account, so we locked it for {} minutes.</p>
+
+                        <div class="alert-box">
+                            <p>If these attempts weren't you, someone may be trying to guess your password. Consider changing it once you are signed in again.</p>
+                        </div>
+
+                        <p>If it was you, you can unlock your account right away:</p>
+                        <div style="text-align: center;">
+                            <a href="{}" class="button">Unlock Account</a>
+                        </div>
+                        <p>Or copy and paste this link into your browser:</p>
+                        <p style="background-color: #eee; padding: 10px; word-break: break-all;">{}</p>
+                    </div>
+                    <div class="footer">
+                        <p>© {} {}. All rights reserved.</p>
+                        <p>This is an automated security alert. Please do not reply to this email.</p>
+                    </div>
+                </div>
+            </body>
+            </html>
+            "#,
+            username, locked_minutes, unlock_link, unlock_link, current_year, app_name
+        );
+
+        let email = Message::builder()
+            .from(self.from_email.clone())
+            .to(to_email.parse()?)
+            .subject("Your account has been temporarily locked")
+            .header(ContentType::TEXT_HTML)
+            .body(html_body)?;
+
+        self.mailer.send(&email)?;
+
+        println!("Account locked email sent to {}", to_email);
+
+        Ok(())
+    }
+
+    pub async fn send_account_exists_email(
+        &self,
+        to_email: &str,
+        username: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let base_var = env::var("BASE_URL").unwrap_or_else(|_| "http://localhost:3000".to_string());
+        let app_name = env::var("APP_NAME").unwrap_or_else(|_| "MyApp".to_string());
+        let current_year = Local::now().date_naive().year().to_string();
+
+        let html_body = format!(
+            r#"
+            <!DOCTYPE html>
+            <html>
+            <head>
+                <style>
+                    body {{ font-family: Arial, sans-serif; line-height: 1.6; color: #333; }}
+                    .container {{ max-width: 600px; margin: 0 auto; padding: 20px; }}
+                    .footer {{ text-align: center; margin-top: 20px; color: #666; font-size: 12px; }}
+                </style>
+            </head>
+            <body>
+                <div class="container">
+                    <div class="header">
+                        <h1>You already have an account</h1>
+                    </div>
+                    <div class="content">
+                        <h2>Hi {}!</h2>
+                        <p>Someone, hopefully you, just tried to sign up for {} with this email address. You already have an account, so nothing was changed.</p>
+                        <p>You can log in at <a href="{}">{}</a>. If you forgot your password, use "Forgot password" there to reset it.</p>
+                        <p>If this wasn't you, you can safely ignore this email.</p>
+                    </div>
+                    <div class="footer">
+                        <p>© {} {}. All rights reserved.</p>
+                    </div>
+                </div>
+            </body>
+            </html>
+            "#,
+            username, app_name, base_var, base_var, current_year, app_name
+        );
+
+        let email = Message::builder()
+            .from(self.from_email.clone())
+            .to(to_email.parse()?)
+            .subject("You already have an account")
+            .header(ContentType::TEXT_HTML)
+            .body(html_body)?;
+
+        self.mailer.send(&email)?;
+
+        println!("Account exists email sent to {}", to_email);
+
+        Ok(())
+    }
+
+    pub async fn send_password_changed_email(
+        &self,
+        to_email: &str,
+        username: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let base_var = env::var("BASE_URL").unwrap_or_else(|_| "http://localhost:3000".to_string());
+        let app_name = env::var("APP_NAME").unwrap_or_else(|_| "MyApp".to_string());
+        let current_year = Local::now().date_naive().year().to_string();
+        let forgot_password_link = format!("{}/forgot-password", base_var);
+
+        let html_body = format!(
+            r#"
+            <!DOCTYPE html>
+            <html>
+            <head>
+                <style>
+                    body {{ font-family: Arial, sans-serif; line-height: 1.6; color: #333; }}
+                    .container {{ max-width: 600px; margin: 0 auto; padding: 20px; }}
+                    .alert-box {{ background-color: #fff3cd; border-left: 4px solid #ffc107; padding: 15px; margin: 20px 0; }}
+                    .footer {{ text-align: center; margin-top: 20px; color: #666; font-size: 12px; }}
+                </style>
+            </head>
+            <body>
+                <div class="container">
+                    <div class="header">
+                        <h1>Your password was changed</h1>
+                    </div>
+                    <div class="content">
+                        <h2>Hi {}!</h2>
+                        <p>The password for your {} account was just changed. All other devices have been logged out.</p>
+                        <div class="alert-box">
+                            <p>If you didn't do this, reset your password right away: <a href="{}">{}</a></p>
+                        </div>
+                    </div>
+                    <div class="footer">
+                        <p>© {} {}. All rights reserved.</p>
+                    </div>
+                </div>
+            </body>
+            </html>
+            "#,
+            username, app_name, forgot_password_link, forgot_password_link, current_year, app_name
+        );
+
+        let email = Message::builder()
+            .from(self.from_email.clone())
+            .to(to_email.parse()?)
+            .subject("Your password was changed")
+            .header(ContentType::TEXT_HTML)
+            .body(html_body)?;
+
+        self.mailer.send(&email)?;
+
+        println!("Password changed email sent to {}", to_email);
+
+        Ok(())
+    }
+
+    pub async fn send_magic_link_email(
+        &self,
+        to_email: &str,
+        username: &str,
+        magic_link_token: &str,
+        valid_minutes: i64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let base_var = env::var("BASE_URL").unwrap_or_else(|_| "http://localhost:3000".to_string());
+        let sign_in_link = format!(
+            "{}/api/auth/magic-link/consume?token={}",
+            base_var, magic_link_token
+        );
+
+        let app_name = env::var("APP_NAME").unwrap_or_else(|_| "MyApp".to_string());
+        let current_year = Local::now().date_naive().year().to_string();
+
+        let html_body = format!(
+            r#"
+            <!DOCTYPE html>
+            <html>
+            <head>
+                <style>
+                    body {{ font-family: Arial, sans-serif; line-height: 1.6; color: #333; }}
+                    .container {{ max-width: 600px; margin: 0 auto; padding: 20px; }}
+                    .content {{ background-color: #fff; padding: 30px; border: 1px solid #ddd; }}
+                    .button {{ display: inline-block; padding: 12px 24px; background-color: #5cb85c; color: white; text-decoration: none; border-radius: 5px; margin: 20px 0; }}
+                    .footer {{ text-align: center; margin-top: 20px; color: #666; font-size: 12px; }}
+                </style>
+            </head>
+            <body>
+                <div class="container">
+                    <div class="header">
+                        <h1>Sign in to {}</h1>
+                    </div>
+                    <div class="content">
+                        <h2>Hi {}!</h2>
+                        <p>Click the button below to sign in. No password needed.</p>
+                        <div style="text-align: center;">
+                            <a href="{}" class="button">Sign in</a>
+                        </div>
+                        <p>Or copy and paste this link into your browser:</p>
+                        <p style="background-color: #eee; padding: 10px; word-break: break-all;">{}</p>
+                        <p>The link expires in {} minutes, works only once and only in the browser you requested it from.</p>
+                        <p>If you didn't ask to sign in, you can safely ignore this email.</p>
+                    </div>
+                    <div class="footer">
+                        <p>© {} {}. All rights reserved.</p>
+                    </div>
+                </div>
+            </body>
+            </html>
+            "#,
+            app_name, username, sign_in_link, sign_in_link, valid_minutes, current_year, app_name
+        );
+
+        let email = Message::builder()
+            .from(self.from_email.clone())
+            .to(to_email.parse()?)
+            .subject(format!("Your sign-in link for {}", app_name))
+            .header(ContentType::TEXT_HTML)
+            .body(html_body)?;
+
+        self.mailer.send(&email)?;
+
+        println!("Magic link email sent to {}", to_email);
+
+        Ok(())
+    }
+
+    pub async fn send_new_sign_in_email(
+        &self,
+        to_email: &str,
+        username: &str,
+        signed_in_at: DateTime<Utc>,
+        device: &str,
+        ip_address: IpAddr,
+        report_token: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let base_var = env::var("BASE_URL").unwrap_or_else(|_| "http://localhost:3000".to_string());
+        let report_link = format!(
+            "{}/api/auth/report-sign-in?token={}",
+            base_var, report_token
+        );
+
+        let app_name = env::var("APP_NAME").unwrap_or_else(|_| "MyApp".to_string());
+        let current_year = Local::now().date_naive().year().to_string();
+        let signed_in_at = signed_in_at.format("%B %-d, %Y at %H:%M UTC");
+
+        let html_body = format!(
+            r#"
+            <!DOCTYPE html>
+            <html>
+            <head>
+                <style>
+                    body {{ font-family: Arial, sans-serif; line-height: 1.6; color: #333; }}
+                    .container {{ max-width: 600px; margin: 0 auto; padding: 20px; }}
+                    .content {{ background-color: #f9f9f9; padding: 30px; border-radius: 5px; margin-top: 20px; }}
+                    .details {{ background-color: #eee; padding: 15px; margin: 20px 0; }}
+                    .button {{ display: inline-block; padding: 12px 24px; background-color: #dc3545; color: white; text-decoration: none; border-radius: 5px; margin: 20px 0; }}
+                    .footer {{ text-align: center; margin-top: 20px; color: #666; font-size: 12px; }}
+                </style>
+            </head>
+            <body>
+                <div class="container">
+                    <div class="content">
+                        <h2>Hi {}!</h2>
+                        <p>Your {} account was just signed in to from a device we haven't seen before.</p>
+
+                        <div class="details">
+                            <p><strong>When:</strong> {}<br>
+                            <strong>Device:</strong> {}<br>
+                            <strong>IP address:</strong> {}</p>
+                        </div>
+
+                        <p>If this was you, there's nothing to do.</p>
+                        <p>If it wasn't, sign that device out and we'll send you a link to pick a new password:</p>
+                        <div style="text-align: center;">
+                            <a href="{}" class="button">This wasn't me</a>
+                        </div>
+                    </div>
+                    <div class="footer">
+                        <p>© {} {}. All rights reserved.</p>
+                        <p>This is an automated security alert. Please do not reply to this email.</p>
+                    </div>
+                </div>
+            </body>
+            </html>
+            "#,
+            username,
+            app_name,
+            signed_in_at,
+            device,
+            ip_address,
+            report_link,
+            current_year,
+            app_name
+        );
+
+        let email = Message::builder()
+            .from(self.from_email.clone())
+            .to(to_email.parse()?)
+            .subject(format!("New sign-in to your {} account", app_name))
+            .header(ContentType::TEXT_HTML)
+            .body(html_body)?;
+
+        self.mailer.send(&email)?;
+
+        println!("New sign-in email sent to {}", to_email);
+
+        Ok(())
+    }
+
+    pub async fn send_account_deletion_email(
+        &self,
+        to_email: &str,
+        username: &str,
+        purge_at: DateTime<Utc>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let app_name = env::var("APP_NAME").unwrap_or_else(|_| "MyApp".to_string());
+        let current_year = Local::now().date_naive().year().to_string();
+        let purge_at = purge_at.format("%B %-d, %Y");
+
+        let html_body = format!(
+            r#"
+            <!DOCTYPE html>
+            <html>
+            <head>
+                <style>
+                    body {{ font-family: Arial, sans-serif; line-height: 1.6; color: #333; }}
+                    .container {{ max-width: 600px; margin: 0 auto; padding: 20px; }}
+                    .alert-box {{ background-color: #fff3cd; border-left: 4px solid #ffc107; padding: 15px; margin: 20px 0; }}
+                    .footer {{ text-align: center; margin-top: 20px; color: #666; font-size: 12px; }}
+                </style>
+            </head>
+            <body>
+                <div class="container">
+                    <div class="header">
+                        <h1>Your account is scheduled for deletion</h1>
+                    </div>
+                    <div class="content">
+                        <h2>Hi {}!</h2>
+                        <p>Your {} account was deleted and all devices have been logged out. Everything stored about it will be removed for good on {}.</p>
+                        <div class="alert-box">
+                            <p>Changed your mind? Just sign in again before then and your account is restored.</p>
+                        </div>
+                    </div>
+                    <div class="footer">
+                        <p>© {} {}. All rights reserved.</p>
+                    </div>
+                </div>
+            </body>
+            </html>
+            "#,
+            username, app_name, purge_at, current_year, app_name
+        );
+
+        let email = Message::builder()
+            .from(self.from_email.clone())
+            .to(to_email.parse()?)
+            .subject(format!(
+                "Your {} account is scheduled for deletion",
+                app_name
+            ))
+            .header(ContentType::TEXT_HTML)
+            .body(html_body)?;
+
+        self.mailer.send(&email)?;
+
+        println!("Account deletion email sent to {}", to_email);
+
+        Ok(())
+    }
+
+    pub async fn send_email_change_confirmation(
+        &self,
+        to_email: &str,
+        username: &str,
+        confirm_token: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let base_var = env::var("BASE_URL").unwrap_or_else(|_| "http://localhost:3000".to_string());
+        let confirm_link = format!(
+            "{}/api/auth/confirm-email-change?token={}",
+            base_var, confirm_token
+        );
+
+        let app_name = env::var("APP_NAME").unwrap_or_else(|_| "MyApp".to_string());
+        let current_year = Local::now().date_naive().year().to_string();
+
+        let html_body = format!(
+            r#"
+            <!DOCTYPE html>
+            <html>
+            <head>
+                <style>
+                    body {{ font-family: Arial, sans-serif; line-height: 1.6; color: #333; }}
+                    .container {{ max-width: 600px; margin: 0 auto; padding: 20px; }}
+                    .content {{ background-color: #f9f9f9; padding: 30px; border-radius: 5px; margin-top: 20px; }}
+                    .button {{ display: inline-block; padding: 12px 24px; background-color: #4CAF50; color: white; text-decoration: none; border-radius: 5px; margin: 20px 0; }}
+                    .footer {{ text-align: center; margin-top: 20px; color: #666; font-size: 12px; }}
+                </style>
+            </head>
+            <body>
+                <div class="container">
+                    <div class="content">
+                        <h2>Hi {}!</h2>
+                        <p>You asked to use this address for your {} account. Confirm it and it becomes the address you log in with:</p>
+                        <div style="text-align: center;">
+                            <a href="{}" class="button">Confirm email address</a>
+                        </div>
+                        <p>This link expires in 24 hours. If you didn't ask for this, just ignore this email.</p>
+                    </div>
+                    <div class="footer">
+                        <p>© {} {}. All rights reserved.</p>
+                    </div>
+                </div>
+            </body>
+            </html>
+            "#,
+            username, app_name, confirm_link, current_year, app_name
+        );
+
+        let email = Message::builder()
+            .from(self.from_email.clone())
+            .to(to_email.parse()?)
+            .subject(format!("Confirm your new {} email address", app_name))
+            .header(ContentType::TEXT_HTML)
+            .body(html_body)?;
+
+        self.mailer.send(&email)?;
+
+        println!("Email change confirmation sent to {}", to_email);
+
+        Ok(())
+    }
+
+    // goes to the old address, the revert link works even after the change was confirmed
+    pub async fn send_email_change_notice(
+        &self,
+        to_email: &str,
+        username: &str,
+        new_email: &str,
+        revert_token: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let base_var = env::var("BASE_URL").unwrap_or_else(|_| "http://localhost:3000".to_string());
+        let revert_link = format!(
+            "{}/api/auth/revert-email-change?token={}",
+            base_var, revert_token
+        );
+
+        let app_name = env::var("APP_NAME").unwrap_or_else(|_| "MyApp".to_string());
+        let current_year = Local::now().date_naive().year().to_string();
+
+        let html_body = format!(
+            r#"
+            <!DOCTYPE html>
+            <html>
+            <head>
+                <style>
+                    body {{ font-family: Arial, sans-serif; line-height: 1.6; color: #333; }}
+                    .container {{ max-width: 600px; margin: 0 auto; padding: 20px; }}
+                    .content {{ background-color: #f9f9f9; padding: 30px; border-radius: 5px; margin-top: 20px; }}
+                    .details {{ background-color: #eee; padding: 15px; margin: 20px 0; }}
+                    .button {{ display: inline-block; padding: 12px 24px; background-color: #dc3545; color: white; text-decoration: none; border-radius: 5px; margin: 20px 0; }}
+                    .footer {{ text-align: center; margin-top: 20px; color: #666; font-size: 12px; }}
+                </style>
+            </head>
+            <body>
+                <div class="container">
+                    <div class="content">
+                        <h2>Hi {}!</h2>
+                        <p>Someone asked to change the email address of your {} account to:</p>
+                        <div class="details">
+                            <p><strong>{}</strong></p>
+                        </div>
+                        <p>Nothing changes until the new address is confirmed. If this wasn't you, keep this address, log out every device and get a link to pick a new password:</p>
+                        <div style="text-align: center;">
+                            <a href="{}" class="button">This wasn't me</a>
+                        </div>
+                        <p>The link works for 7 days, even if the change was confirmed in the meantime.</p>
+                    </div>
+                    <div class="footer">
+                        <p>© {} {}. All rights reserved.</p>
+                        <p>This is an automated security alert. Please do not reply to this email.</p>
+                    </div>
+                </div>
+            </body>
+            </html>
+            "#,
+            username, app_name, new_email, revert_link, current_year, app_name
+        );
+
+        let email = Message::builder()
+            .from(self.from_email.clone())
+            .to(to_email.parse()?)
+            .subject(format!("Your {} email address is being changed", app_name))
+            .header(ContentType::TEXT_HTML)
+            .body(html_body)?;
+
+        self.mailer.send(&email)?;
+
+        println!("Email change notice sent to {}", to_email);
+
+        Ok(())
+    }
 }
@@ -1,14 +1,34 @@
 use sqlx::PgPool;
 
-use std::{env, sync::Arc};
+use std::{env, sync::Arc, time::Duration};
+
+use chrono::Utc;
 
 use crate::{
+    auth::{
+        account_deletion::AccountDeletionConfig, audit, cookies::CookieConfig, jwt::JwtConfig,
+        keys::JwtKeyStore, magic_link::MagicLinkConfig, password_policy::PasswordPolicy,
+        registration::RegistrationConfig, revocation::SessionRevocationCache,
+        social_login::SocialLoginConfig, throttle::LoginThrottleConfig,
+        username_policy::UsernamePolicy, verified_email::VerifiedEmailPolicy,
+    },
+    models::NewAuditEvent,
+    rate_limit::MAX_WINDOW_SECONDS,
     repositories::{
-        EmailVerificationRepository, EmailVerificationRepositoryTrait, PasswordResetRepository,
-        PasswordResetRepositoryTrait, RefreshTokenRepository, RefreshTokenRepositoryTrait,
-        UserRepository, UserRepositoryTrait,
+        AccountUnlockRepository, AccountUnlockRepositoryTrait, AuditEventRepository,
+        AuditEventRepositoryTrait, EmailChangeRepository, EmailChangeRepositoryTrait,
+        EmailVerificationRepository, EmailVerificationRepositoryTrait, ImpersonationRepository,
+        ImpersonationRepositoryTrait, InMemoryRateLimitRepository, InviteRepository,
+        InviteRepositoryTrait, KnownDeviceRepository, KnownDeviceRepositoryTrait,
+        LoginAttemptRepository, LoginAttemptRepositoryTrait, MagicLinkRepository,
+        MagicLinkRepositoryTrait, OAuthClientRepository, OAuthClientRepositoryTrait,
+        PasswordResetRepository, PasswordResetRepositoryTrait, PersonalAccessTokenRepository,
+        PersonalAccessTokenRepositoryTrait, RateLimitRepository, RateLimitRepositoryTrait,
+        RefreshTokenRepository, RefreshTokenRepositoryTrait, RoleRepository, RoleRepositoryTrait,
+        UserIdentityRepository, UserIdentityRepositoryTrait, UserRepository, UserRepositoryTrait,
     },
     services::EmailService,
+    utils::client_ip::TrustedProxies,
 };
 
 #[derive(Clone)]
@@ -18,9 +38,35 @@ pub struct AppState {
     pub static_asset_dir: String,
     pub user_repository: Arc<dyn UserRepositoryTrait>,
     pub email_verification_repository: Arc<dyn EmailVerificationRepositoryTrait>,
+    pub email_change_repository: Arc<dyn EmailChangeRepositoryTrait>,
     pub password_reset_respository: Arc<dyn PasswordResetRepositoryTrait>,
     pub email_service: Arc<EmailService>,
     pub refresh_token_repository: Arc<dyn RefreshTokenRepositoryTrait>,
+    pub session_cache: Arc<SessionRevocationCache>,
+    pub jwt_keys: Arc<JwtKeyStore>,
+    pub jwt_config: Arc<JwtConfig>,
+    pub cookie_config: Arc<CookieConfig>,
+    pub login_attempt_repository: Arc<dyn LoginAttemptRepositoryTrait>,
+    pub account_unlock_repository: Arc<dyn AccountUnlockRepositoryTrait>,
+    pub login_throttle: Arc<LoginThrottleConfig>,
+    pub rate_limit_repository: Arc<dyn RateLimitRepositoryTrait>,
+    pub trusted_proxies: Arc<TrustedProxies>,
+    pub registration_config: Arc<RegistrationConfig>,
+    pub verified_email_policy: Arc<VerifiedEmailPolicy>,
+    pub username_policy: Arc<UsernamePolicy>,
+    pub password_policy: Arc<PasswordPolicy>,
+    pub magic_link_repository: Arc<dyn MagicLinkRepositoryTrait>,
+    pub magic_link_config: Arc<MagicLinkConfig>,
+    pub account_deletion: Arc<AccountDeletionConfig>,
+    pub user_identity_repository: Arc<dyn UserIdentityRepositoryTrait>,
+    pub social_login: Arc<SocialLoginConfig>,
+    pub oauth_client_repository: Arc<dyn OAuthClientRepositoryTrait>,
+    pub personal_access_token_repository: Arc<dyn PersonalAccessTokenRepositoryTrait>,
+    pub role_repository: Arc<dyn RoleRepositoryTrait>,
+    pub audit_event_repository: Arc<dyn AuditEventRepositoryTrait>,
+    pub known_device_repository: Arc<dyn KnownDeviceRepositoryTrait>,
+    pub impersonation_repository: Arc<dyn ImpersonationRepositoryTrait>,
+    pub invite_repository: Arc<dyn InviteRepositoryTrait>,
 }
 
 impl AppState {
@@ -42,12 +88,147 @@ impl AppState {
         let email_verification_repository: Arc<dyn EmailVerificationRepositoryTrait> =
             Arc::new(EmailVerificationRepository::new(db.clone()));
 
+        let email_change_repository: Arc<dyn EmailChangeRepositoryTrait> =
+            Arc::new(EmailChangeRepository::new(db.clone()));
+
+        spawn_email_change_cleanup(email_change_repository.clone());
+
         let password_reset_respository: Arc<dyn PasswordResetRepositoryTrait> =
             Arc::new(PasswordResetRepository::new(db.clone()));
 
         let refresh_token_repository: Arc<dyn RefreshTokenRepositoryTrait> =
             Arc::new(RefreshTokenRepository::new(db.clone()));
 
+        let login_attempt_repository: Arc<dyn LoginAttemptRepositoryTrait> =
+            Arc::new(LoginAttemptRepository::new(db.clone()));
+
+        let account_unlock_repository: Arc<dyn AccountUnlockRepositoryTrait> =
+            Arc::new(AccountUnlockRepository::new(db.clone()));
+
+        let magic_link_repository: Arc<dyn MagicLinkRepositoryTrait> =
+            Arc::new(MagicLinkRepository::new(db.clone()));
+
+        let user_identity_repository: Arc<dyn UserIdentityRepositoryTrait> =
+            Arc::new(UserIdentityRepository::new(db.clone()));
+
+        spawn_login_state_cleanup(user_identity_repository.clone());
+
+        let oauth_client_repository: Arc<dyn OAuthClientRepositoryTrait> =
+            Arc::new(OAuthClientRepository::new(db.clone()));
+
+        spawn_authorization_code_cleanup(oauth_client_repository.clone());
+
+        let personal_access_token_repository: Arc<dyn PersonalAccessTokenRepositoryTrait> =
+            Arc::new(PersonalAccessTokenRepository::new(db.clone()));
+
+        let role_repository: Arc<dyn RoleRepositoryTrait> =
+            Arc::new(RoleRepository::new(db.clone()));
+
+        let audit_event_repository: Arc<dyn AuditEventRepositoryTrait> =
+            Arc::new(AuditEventRepository::new(db.clone()));
+
+        let known_device_repository: Arc<dyn KnownDeviceRepositoryTrait> =
+            Arc::new(KnownDeviceRepository::new(db.clone()));
+
+        spawn_report_token_cleanup(known_device_repository.clone());
+
+        let impersonation_repository: Arc<dyn ImpersonationRepositoryTrait> =
+            Arc::new(ImpersonationRepository::new(db.clone()));
+
+        spawn_impersonation_expiry(
+            impersonation_repository.clone(),
+            audit_event_repository.clone(),
+        );
+
+        let invite_repository: Arc<dyn InviteRepositoryTrait> =
+            Arc::new(InviteRepository::new(db.clone()));
+
+        let login_throttle = Arc::new(LoginThrottleConfig::from_env());
+
+        // postgres shares the counters between instances, memory is per process
+        let rate_limit_repository: Arc<dyn RateLimitRepositoryTrait> =
+            match env::var("RATE_LIMIT_STORE").as_deref() {
+                Ok("postgres") => Arc::new(RateLimitRepository::new(db.clone())),
+                _ => Arc::new(InMemoryRateLimitRepository::new()),
+            };
+
+        spawn_rate_limit_cleanup(Arc::clone(&rate_limit_repository));
+
+        let trusted_proxies = Arc::new(TrustedProxies::from_env());
+
+        let registration_config = Arc::new(RegistrationConfig::from_env());
+
+        let verified_email_policy = Arc::new(VerifiedEmailPolicy::from_env());
+
+        let username_policy = Arc::new(UsernamePolicy::from_env());
+
+        spawn_username_skeleton_backfill(user_repository.clone());
+
+        let magic_link_config = Arc::new(MagicLinkConfig::from_env());
+
+        let account_deletion = Arc::new(AccountDeletionConfig::from_env());
+
+        spawn_account_purge(
+            user_repository.clone(),
+            audit_event_repository.clone(),
+            account_deletion.clone(),
+        );
+
+        let social_login: Arc<SocialLoginConfig> = match SocialLoginConfig::from_env() {
+            Ok(config) => Arc::new(config),
+            Err(e) => {
+                eprintln!("Failed to configure social login: {}", e);
+                eprintln!("Check OAUTH_PROVIDERS and the OAUTH_<NAME>_* variables in .env");
+                panic!("Social login initialization failed");
+            }
+        };
+
+        let password_policy: Arc<PasswordPolicy> = match PasswordPolicy::from_env() {
+            Ok(policy) => Arc::new(policy),
+            Err(e) => {
+                eprintln!("Failed to load password policy: {}", e);
+                eprintln!("Check PASSWORD_BREACHED_FILE in .env");
+                panic!("Password policy initialization failed");
+            }
+        };
+
+        // how long another instance may keep accepting a revoked session
+        let session_cache_ttl: u64 = env::var("SESSION_CACHE_TTL_SECONDS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(30);
+
+        let session_cache = Arc::new(SessionRevocationCache::new(Duration::from_secs(
+            session_cache_ttl,
+        )));
+
+        let jwt_keys: Arc<JwtKeyStore> = match JwtKeyStore::from_env() {
+            Ok(keys) => Arc::new(keys),
+            Err(e) => {
+                eprintln!("Failed to load JWT keys: {}", e);
+                eprintln!("Set JWT_KEY_DIR to a directory of key pairs or JWT_SECRET in .env");
+                panic!("JWT key initialization failed");
+            }
+        };
+
+        let key_reload_interval: u64 = env::var("JWT_KEY_RELOAD_SECONDS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(300);
+
+        jwt_keys.spawn_rotation(Duration::from_secs(key_reload_interval.max(1)));
+
+        let jwt_config: Arc<JwtConfig> = match JwtConfig::from_env() {
+            Ok(config) => Arc::new(config),
+            Err(e) => {
+                eprintln!("Failed to configure JWT claims: {}", e);
+                eprintln!("Set JWT_ISSUER and JWT_AUDIENCE in .env, different per environment");
+                panic!("JWT configuration failed");
+            }
+        };
+
+        let cookie_config = Arc::new(CookieConfig::from_env());
+
         let email_service: Arc<EmailService> = match EmailService::new() {
             Ok(service) => Arc::new(service),
             Err(e) => {
@@ -63,9 +244,197 @@ impl AppState {
             static_asset_dir,
             user_repository,
             email_verification_repository,
+            email_change_repository,
             password_reset_respository,
             refresh_token_repository,
             email_service,
+            session_cache,
+            jwt_keys,
+            jwt_config,
+            cookie_config,
+            login_attempt_repository,
+            account_unlock_repository,
+            login_throttle,
+            rate_limit_repository,
+            trusted_proxies,
+            registration_config,
+            verified_email_policy,
+            username_policy,
+            password_policy,
+            magic_link_repository,
+            magic_link_config,
+            account_deletion,
+            user_identity_repository,
+            social_login,
+            oauth_client_repository,
+            personal_access_token_repository,
+            role_repository,
+            audit_event_repository,
+            known_device_repository,
+            impersonation_repository,
+            invite_repository,
         })
     }
 }
+
+// no window is longer than MAX_WINDOW_SECONDS, older counters are of no use anymore
+fn spawn_rate_limit_cleanup(repository: Arc<dyn RateLimitRepositoryTrait>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(600));
+
+        loop {
+            interval.tick().await;
+
+            let before = Utc::now() - chrono::Duration::seconds(MAX_WINDOW_SECONDS as i64);
+            if let Err(e) = repository.delete_expired(before).await {
+                eprintln!("Failed to clean up rate limit counters: {}", e);
+            }
+        }
+    });
+}
+
+// abandoned sign-ins leave their authorization requests behind
+fn spawn_login_state_cleanup(repository: Arc<dyn UserIdentityRepositoryTrait>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(600));
+
+        loop {
+            interval.tick().await;
+
+            if let Err(e) = repository.delete_expired_login_states().await {
+                eprintln!("Failed to clean up OAuth login states: {}", e);
+            }
+        }
+    });
+}
+
+// codes live for minutes, the ones never redeemed pile up otherwise
+fn spawn_authorization_code_cleanup(repository: Arc<dyn OAuthClientRepositoryTrait>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(600));
+
+        loop {
+            interval.tick().await;
+
+            if let Err(e) = repository.delete_expired_authorization_codes().await {
+                eprintln!("Failed to clean up OAuth authorization codes: {}", e);
+            }
+        }
+    });
+}
+
+// confirm and revert links nobody followed
+fn spawn_email_change_cleanup(repository: Arc<dyn EmailChangeRepositoryTrait>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(3600));
+
+        loop {
+            interval.tick().await;
+
+            if let Err(e) = repository.delete_expired_tokens().await {
+                eprintln!("Failed to clean up email change tokens: {}", e);
+            }
+        }
+    });
+}
+
+// "this wasn't me" links nobody clicked
+fn spawn_report_token_cleanup(repository: Arc<dyn KnownDeviceRepositoryTrait>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(3600));
+
+        loop {
+            interval.tick().await;
+
+            if let Err(e) = repository.delete_expired_report_tokens().await {
+                eprintln!("Failed to clean up sign-in report tokens: {}", e);
+            }
+        }
+    });
+}
+
+// nobody ends most impersonations, without this the audit log never says they're over
+fn spawn_impersonation_expiry(
+    impersonation_repository: Arc<dyn ImpersonationRepositoryTrait>,
+    audit_event_repository: Arc<dyn AuditEventRepositoryTrait>,
+) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(60));
+
+        loop {
+            interval.tick().await;
+
+            let expired = match impersonation_repository.close_expired().await {
+                Ok(expired) => expired,
+                Err(e) => {
+                    eprintln!("Failed to close expired impersonations: {}", e);
+                    continue;
+                }
+            };
+
+            for session in expired {
+                let event = NewAuditEvent::new(audit::ADMIN_IMPERSONATION_EXPIRED)
+                    .user(session.user_id)
+                    .actor(session.admin_id)
+                    .metadata(serde_json::json!({
+                        "session_id": session.id,
+                        "expires_at": session.expires_at,
+                    }));
+
+                if let Err(e) = audit_event_repository.record(&event).await {
+                    eprintln!("Failed to record {} audit event: {}", event.event_type, e);
+                }
+            }
+        }
+    });
+}
+
+// accounts created before migration 0024, once per start until none are left
+fn spawn_username_skeleton_backfill(user_repository: Arc<dyn UserRepositoryTrait>) {
+    tokio::spawn(async move {
+        match user_repository.backfill_username_skeletons().await {
+            Ok(collisions) if !collisions.is_empty() => {
+                eprintln!(
+                    "Usernames that look like another account's, rename them: {}",
+                    collisions.join(", ")
+                );
+            }
+            Ok(_) => {}
+            Err(e) => eprintln!("Failed to backfill username skeletons: {}", e),
+        }
+    });
+}
+
+// deleted accounts whose grace period ran out
+fn spawn_account_purge(
+    user_repository: Arc<dyn UserRepositoryTrait>,
+    audit_event_repository: Arc<dyn AuditEventRepositoryTrait>,
+    config: Arc<AccountDeletionConfig>,
+) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(3600));
+
+        loop {
+            interval.tick().await;
+
+            let deleted_before = Utc::now() - config.grace_period;
+            let purged = match user_repository.purge_deleted(deleted_before).await {
+                Ok(purged) => purged,
+                Err(e) => {
+                    eprintln!("Failed to purge deleted accounts: {}", e);
+                    continue;
+                }
+            };
+
+            // the user's own events are gone with them, this one only keeps the id
+            for user_id in purged {
+                let event = NewAuditEvent::new(audit::USER_PURGED)
+                    .metadata(serde_json::json!({ "user_id": user_id }));
+
+                if let Err(e) = audit_event_repository.record(&event).await {
+                    eprintln!("Failed to record {} audit event: {}", event.event_type, e);
+                }
+            }
+        }
+    });
+}
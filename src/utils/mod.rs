@@ -1,3 +1,7 @@
+pub mod client_ip;
+pub mod request_meta;
 pub mod token_generator;
 
+pub use client_ip::ClientIp;
+pub use request_meta::RequestMeta;
 pub use token_generator::generate_verification_token;
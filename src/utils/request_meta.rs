@@ -0,0 +1,41 @@
+use std::net::IpAddr;
+
+use axum::{
+    extract::{FromRef, FromRequestParts},
+    http::{StatusCode, header::USER_AGENT, request::Parts},
+};
+
+use crate::{state::AppState, utils::ClientIp};
+
+// browsers stay well below this, anything longer is someone stuffing the audit log
+const MAX_USER_AGENT_LENGTH: usize = 512;
+
+// where a request came from, for the audit log and sign-in notifications
+#[derive(Debug, Clone)]
+pub struct RequestMeta {
+    pub ip_address: IpAddr,
+    pub user_agent: Option<String>,
+}
+
+impl<S> FromRequestParts<S> for RequestMeta
+where
+    AppState: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let ClientIp(ip_address) = ClientIp::from_request_parts(parts, state).await?;
+
+        let user_agent = parts
+            .headers
+            .get(USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.chars().take(MAX_USER_AGENT_LENGTH).collect());
+
+        Ok(RequestMeta {
+            ip_address,
+            user_agent,
+        })
+    }
+}
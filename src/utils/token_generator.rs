@@ -1,6 +1,6 @@
-use uuid::Uuid;
+use crate::auth::tokens::generate_secure_token;
 
 pub fn generate_verification_token() -> String {
-    // create token as UUID without hyphens
-    Uuid::new_v4().as_simple().to_string()
+    // url safe, so it can be dropped into email links as is
+    generate_secure_token()
 }
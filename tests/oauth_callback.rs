@@ -0,0 +1,425 @@
+// oauth_callback against a local OpenID Connect provider and a real database. Needs DATABASE_URL
+// and the rest of .env like the server does, run them with `cargo test -- --ignored`.
+
+mod common;
+
+use std::{env, net::IpAddr, sync::Arc};
+
+use axum::{
+    Json,
+    body::to_bytes,
+    extract::{Path, Query, State},
+    http::{StatusCode, header::LOCATION},
+    response::{IntoResponse, Response},
+};
+use axum_extra::extract::cookie::{Cookie, CookieJar};
+use chrono::Duration;
+use rw_axum_api::{
+    auth::{
+        cookies::OAUTH_STATE_COOKIE,
+        middleware::RequireAuth,
+        registration::{RegistrationConfig, RegistrationMode},
+        social_login::SocialLoginConfig,
+        verified_email::{UnverifiedAccess, VerifiedEmailPolicy},
+    },
+    handlers::{link_identity, oauth_authorize, oauth_callback},
+    models::User,
+    schemas::OAuthCallbackParams,
+    state::AppState,
+    utils::RequestMeta,
+};
+use serde_json::{Value, json};
+use uuid::Uuid;
+
+use common::{MockOidc, SigningKey};
+
+const PROVIDER: &str = "mock";
+
+struct Fixture {
+    mock: MockOidc,
+    state: AppState,
+    // everything here is deleted again by cleanup
+    emails: Vec<String>,
+}
+
+impl Fixture {
+    async fn start() -> Self {
+        dotenvy::dotenv().ok();
+
+        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set");
+
+        let mock = MockOidc::start().await;
+        let mut state = AppState::new(&database_url).await.unwrap();
+
+        // the same no matter what .env says
+        state.social_login = Arc::new(
+            SocialLoginConfig::new(vec![mock.provider(PROVIDER)], "http://localhost:3000").unwrap(),
+        );
+        state.registration_config = Arc::new(open_registration(false));
+        state.verified_email_policy = Arc::new(VerifiedEmailPolicy {
+            unverified_access: UnverifiedAccess::Full,
+        });
+
+        Self {
+            mock,
+            state,
+            emails: Vec::new(),
+        }
+    }
+
+    fn enable_enumeration_protection(&mut self) {
+        self.state.registration_config = Arc::new(open_registration(true));
+    }
+
+    fn email(&mut self) -> String {
+        let email = format!("oauth-{}@example.com", Uuid::new_v4().simple());
+        self.emails.push(email.clone());
+        email
+    }
+
+    async fn user(&mut self, username: &str) -> User {
+        let email = self.email();
+
+        self.state
+            .user_repository
+            .create(username, &email, "not-a-bcrypt-hash")
+            .await
+            .unwrap()
+    }
+
+    // returns the state cookie and the URL the browser is sent to
+    async fn start_login(&self, link_user: Option<User>) -> (String, String) {
+        let (jar, authorization_url) = match link_user {
+            Some(user) => {
+                let (jar, Json(response)) = link_identity(
+                    State(self.state.clone()),
+                    RequireAuth(user),
+                    Path(PROVIDER.to_string()),
+                    CookieJar::new(),
+                )
+                .await
+                .unwrap();
+
+                (jar, response.authorization_url)
+            }
+            None => {
+                let (jar, redirect) = oauth_authorize(
+                    State(self.state.clone()),
+                    Path(PROVIDER.to_string()),
+                    CookieJar::new(),
+                )
+                .await
+                .unwrap();
+
+                let response = redirect.into_response();
+                let location = response.headers()[LOCATION].to_str().unwrap().to_string();
+
+                (jar, location)
+            }
+        };
+
+        let cookie = jar.get(OAUTH_STATE_COOKIE).unwrap().value().to_string();
+
+        (cookie, authorization_url)
+    }
+
+    // the browser coming back from the provider
+    async fn callback(
+        &self,
+        cookie: Option<&str>,
+        code: &str,
+        authorization_url: &str,
+    ) -> Response {
+        let login_state = url::Url::parse(authorization_url)
+            .unwrap()
+            .query_pairs()
+            .find(|(name, _)| name == "state")
+            .map(|(_, value)| value.into_owned());
+
+        let jar = match cookie {
+            Some(cookie) => {
+                CookieJar::new().add(Cookie::new(OAUTH_STATE_COOKIE, cookie.to_string()))
+            }
+            None => CookieJar::new(),
+        };
+
+        let request = RequestMeta {
+            ip_address: IpAddr::from([127, 0, 0, 1]),
+            user_agent: Some("oauth-callback-test".to_string()),
+        };
+
+        oauth_callback(
+            State(self.state.clone()),
+            request,
+            Path(PROVIDER.to_string()),
+            jar,
+            Query(OAuthCallbackParams {
+                code: Some(code.to_string()),
+                state: login_state,
+                error: None,
+            }),
+        )
+        .await
+        .into_response()
+    }
+
+    // start to finish, the provider vouching for subject and email
+    async fn sign_in(
+        &self,
+        link_user: Option<User>,
+        subject: &str,
+        email: &str,
+        preferred_username: &str,
+    ) -> Response {
+        let (cookie, authorization_url) = self.start_login(link_user).await;
+        let code = self
+            .mock
+            .approve(&authorization_url, SigningKey::K1, |claims| {
+                claims["sub"] = json!(subject);
+                claims["email"] = json!(email);
+                claims["preferred_username"] = json!(preferred_username);
+            });
+
+        self.callback(Some(&cookie), &code, &authorization_url)
+            .await
+    }
+
+    async fn identity_owner(&self, subject: &str) -> Option<Uuid> {
+        self.state
+            .user_identity_repository
+            .find_by_subject(PROVIDER, subject)
+            .await
+            .unwrap()
+            .map(|identity| identity.user_id)
+    }
+
+    async fn cleanup(self) {
+        sqlx::query("DELETE FROM users WHERE email = ANY($1)")
+            .bind(&self.emails)
+            .execute(&self.state.db)
+            .await
+            .unwrap();
+    }
+}
+
+fn open_registration(enumeration_protection: bool) -> RegistrationConfig {
+    RegistrationConfig {
+        enumeration_protection,
+        mode: RegistrationMode::Open,
+        allowed_domains: Vec::new(),
+        default_invite_quota: 0,
+        invite_ttl: Duration::days(7),
+    }
+}
+
+async fn body(response: Response) -> Value {
+    let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    serde_json::from_slice(&bytes).unwrap_or(Value::Null)
+}
+
+fn subject() -> String {
+    format!("subject-{}", Uuid::new_v4())
+}
+
+fn username() -> String {
+    format!("oauth{}", &Uuid::new_v4().simple().to_string()[..12])
+}
+
+#[tokio::test]
+#[ignore = "needs DATABASE_URL"]
+async fn state_cookie_must_match() {
+    let fixture = Fixture::start().await;
+
+    let (_, authorization_url) = fixture.start_login(None).await;
+    let code = fixture
+        .mock
+        .approve(&authorization_url, SigningKey::K1, |_| {});
+
+    let response = fixture
+        .callback(Some("someone-elses-state"), &code, &authorization_url)
+        .await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let response = fixture.callback(None, &code, &authorization_url).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    fixture.cleanup().await;
+}
+
+#[tokio::test]
+#[ignore = "needs DATABASE_URL"]
+async fn new_identity_creates_an_account_and_signs_in_again() {
+    let mut fixture = Fixture::start().await;
+
+    let subject = subject();
+    let email = fixture.email();
+
+    let response = fixture.sign_in(None, &subject, &email, &username()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = body(response).await;
+    assert_eq!(response["user"]["email"], email);
+    assert_eq!(response["user"]["email_verified"], true);
+    assert!(response["access_token"].is_string());
+
+    let user_id = fixture.identity_owner(&subject).await.unwrap();
+
+    // same subject again is the same account
+    let response = fixture.sign_in(None, &subject, &email, &username()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(fixture.identity_owner(&subject).await, Some(user_id));
+
+    fixture.cleanup().await;
+}
+
+#[tokio::test]
+#[ignore = "needs DATABASE_URL"]
+async fn email_of_an_existing_account_is_a_conflict() {
+    let mut fixture = Fixture::start().await;
+
+    let user = fixture.user(&username()).await;
+    let subject = subject();
+
+    let response = fixture
+        .sign_in(None, &subject, &user.email.to_uppercase(), &username())
+        .await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    assert_eq!(fixture.identity_owner(&subject).await, None);
+
+    fixture.cleanup().await;
+}
+
+#[tokio::test]
+#[ignore = "needs DATABASE_URL"]
+async fn enumeration_protection_hides_existing_accounts() {
+    let mut fixture = Fixture::start().await;
+    fixture.enable_enumeration_protection();
+
+    let user = fixture.user(&username()).await;
+    let subject = subject();
+
+    let (cookie, authorization_url) = fixture.start_login(None).await;
+    let code = fixture
+        .mock
+        .approve(&authorization_url, SigningKey::K1, |claims| {
+            claims["sub"] = json!(subject);
+            claims["email"] = json!(user.email);
+            claims["email_verified"] = json!(false);
+        });
+    let existing = fixture
+        .callback(Some(&cookie), &code, &authorization_url)
+        .await;
+    assert_eq!(existing.status(), StatusCode::ACCEPTED);
+    assert_eq!(fixture.identity_owner(&subject).await, None);
+
+    // a new account the provider didn't verify the address of gets the same answer
+    let new_subject = self::subject();
+    let new_email = fixture.email();
+    let (cookie, authorization_url) = fixture.start_login(None).await;
+    let code = fixture
+        .mock
+        .approve(&authorization_url, SigningKey::K1, |claims| {
+            claims["sub"] = json!(new_subject);
+            claims["email"] = json!(new_email);
+            claims["email_verified"] = json!(false);
+        });
+    let new = fixture
+        .callback(Some(&cookie), &code, &authorization_url)
+        .await;
+    assert_eq!(new.status(), StatusCode::ACCEPTED);
+    assert_eq!(body(existing).await, body(new).await);
+    assert!(fixture.identity_owner(&new_subject).await.is_some());
+
+    // a verified address is the caller's own, signing in tells them nothing new
+    let verified_email = fixture.email();
+    let response = fixture
+        .sign_in(None, &self::subject(), &verified_email, &username())
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    fixture.cleanup().await;
+}
+
+#[tokio::test]
+#[ignore = "needs DATABASE_URL"]
+async fn linking_adds_the_identity_once() {
+    let mut fixture = Fixture::start().await;
+
+    let user = fixture.user(&username()).await;
+    let subject = subject();
+    let provider_email = fixture.email();
+
+    let response = fixture
+        .sign_in(Some(user.clone()), &subject, &provider_email, &username())
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(body(response).await["message"], "Linked mock");
+    assert_eq!(fixture.identity_owner(&subject).await, Some(user.id));
+
+    let response = fixture
+        .sign_in(Some(user.clone()), &subject, &provider_email, &username())
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(body(response).await["message"], "Already linked");
+
+    fixture.cleanup().await;
+}
+
+#[tokio::test]
+#[ignore = "needs DATABASE_URL"]
+async fn identity_linked_to_another_user_is_a_conflict() {
+    let mut fixture = Fixture::start().await;
+
+    let owner = fixture.user(&username()).await;
+    let other = fixture.user(&username()).await;
+    let subject = subject();
+    let provider_email = fixture.email();
+
+    let response = fixture
+        .sign_in(Some(owner.clone()), &subject, &provider_email, &username())
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = fixture
+        .sign_in(Some(other), &subject, &provider_email, &username())
+        .await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    assert_eq!(fixture.identity_owner(&subject).await, Some(owner.id));
+
+    fixture.cleanup().await;
+}
+
+#[tokio::test]
+#[ignore = "needs DATABASE_URL"]
+async fn taken_usernames_get_a_number() {
+    let mut fixture = Fixture::start().await;
+
+    let taken = username();
+    fixture.user(&taken).await;
+
+    let mut usernames = Vec::new();
+    for _ in 0..2 {
+        let email = fixture.email();
+        let response = fixture.sign_in(None, &subject(), &email, &taken).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        usernames.push(body(response).await["user"]["username"].clone());
+    }
+
+    assert_eq!(
+        usernames,
+        vec![json!(format!("{}2", taken)), json!(format!("{}3", taken))]
+    );
+
+    // a name that only differs in case counts as taken as well
+    let email = fixture.email();
+    let response = fixture
+        .sign_in(None, &subject(), &email, &taken.to_uppercase())
+        .await;
+    assert_eq!(
+        body(response).await["user"]["username"],
+        format!("{}4", taken.to_uppercase())
+    );
+
+    fixture.cleanup().await;
+}
@@ -0,0 +1,245 @@
+// ID token and PKCE checks of the social login client against a local OpenID Connect provider.
+
+mod common;
+
+use std::collections::HashMap;
+
+use chrono::Utc;
+use rw_axum_api::auth::social_login::{
+    ExternalIdentity, OAuthProvider, SocialLoginConfig, SocialLoginError, pkce_challenge,
+};
+use serde_json::{Value, json};
+use url::Url;
+
+use common::{MockOidc, SigningKey};
+
+const CODE_VERIFIER: &str = "test-code-verifier-0123456789-abcdefghijklmnopqrstuvwxyz";
+const NONCE: &str = "test-nonce";
+
+struct Client {
+    mock: MockOidc,
+    config: SocialLoginConfig,
+}
+
+impl Client {
+    async fn start() -> Self {
+        let mock = MockOidc::start().await;
+        let config =
+            SocialLoginConfig::new(vec![mock.provider("mock")], "http://localhost:3000").unwrap();
+
+        Self { mock, config }
+    }
+
+    fn provider(&self) -> std::sync::Arc<OAuthProvider> {
+        self.config.provider("mock").unwrap()
+    }
+
+    async fn authorization_url(&self) -> Result<String, SocialLoginError> {
+        self.config
+            .authorization_url(&self.provider(), "test-state", CODE_VERIFIER, NONCE)
+            .await
+    }
+
+    // a whole sign-in, with the ID token the provider hands out changed by customize
+    async fn sign_in(
+        &self,
+        signing_key: SigningKey,
+        customize: impl FnOnce(&mut Value),
+    ) -> Result<ExternalIdentity, SocialLoginError> {
+        let authorization_url = self.authorization_url().await?;
+        let code = self
+            .mock
+            .approve(&authorization_url, signing_key, customize);
+
+        self.config
+            .exchange_code(&self.provider(), &code, CODE_VERIFIER, NONCE)
+            .await
+    }
+}
+
+fn assert_rejected(result: Result<ExternalIdentity, SocialLoginError>, reason: &str) {
+    match result {
+        Err(SocialLoginError::Rejected(actual)) if actual.contains(reason) => {}
+        other => panic!("expected a rejection for {}, got {:?}", reason, other),
+    }
+}
+
+#[tokio::test]
+async fn authorization_url_comes_from_discovery() {
+    let client = Client::start().await;
+
+    let authorization_url = client.authorization_url().await.unwrap();
+    let url = Url::parse(&authorization_url).unwrap();
+    let params: HashMap<String, String> = url.query_pairs().into_owned().collect();
+
+    assert_eq!(
+        format!("{}{}", url.origin().ascii_serialization(), url.path()),
+        format!("{}/authorize", client.mock.issuer)
+    );
+    assert_eq!(params["state"], "test-state");
+    assert_eq!(params["nonce"], NONCE);
+    assert_eq!(params["code_challenge"], pkce_challenge(CODE_VERIFIER));
+    assert_eq!(
+        params["redirect_uri"],
+        "http://localhost:3000/api/auth/oauth/mock/callback"
+    );
+}
+
+#[tokio::test]
+async fn discovery_document_for_another_issuer_is_refused() {
+    let client = Client::start().await;
+    client.mock.serve_discovery_for("https://evil.example.com");
+
+    match client.authorization_url().await {
+        Err(SocialLoginError::Provider(_)) => {}
+        other => panic!("expected a provider error, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn valid_id_token_gives_the_identity() {
+    let client = Client::start().await;
+
+    let identity = client
+        .sign_in(SigningKey::K1, |claims| {
+            claims["sub"] = json!("subject-42");
+            claims["email_verified"] = json!("true");
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(identity.subject, "subject-42");
+    assert_eq!(identity.email.as_deref(), Some("someone@example.com"));
+    assert!(identity.email_verified);
+    assert_eq!(identity.preferred_username.as_deref(), Some("someone"));
+}
+
+#[tokio::test]
+async fn pkce_verifier_is_forwarded_to_the_token_endpoint() {
+    let client = Client::start().await;
+
+    client.sign_in(SigningKey::K1, |_| {}).await.unwrap();
+    assert_eq!(
+        client.mock.code_verifiers(),
+        vec![CODE_VERIFIER.to_string()]
+    );
+
+    // a verifier that doesn't match the challenge in the authorization URL
+    let authorization_url = client.authorization_url().await.unwrap();
+    let code = client
+        .mock
+        .approve(&authorization_url, SigningKey::K1, |_| {});
+    let result = client
+        .config
+        .exchange_code(&client.provider(), &code, "some-other-verifier", NONCE)
+        .await;
+
+    assert_rejected(result, "token endpoint answered 400");
+}
+
+#[tokio::test]
+async fn unknown_kid_refetches_the_jwks() {
+    let client = Client::start().await;
+
+    client.sign_in(SigningKey::K1, |_| {}).await.unwrap();
+    assert_eq!(client.mock.jwks_fetches(), 1);
+
+    // cached keys are enough for the next sign-in
+    client.sign_in(SigningKey::K1, |_| {}).await.unwrap();
+    assert_eq!(client.mock.jwks_fetches(), 1);
+
+    client.mock.publish_k2();
+    client.sign_in(SigningKey::K2, |_| {}).await.unwrap();
+    assert_eq!(client.mock.jwks_fetches(), 2);
+}
+
+#[tokio::test]
+async fn kid_missing_after_refetch_is_rejected() {
+    let client = Client::start().await;
+
+    // k2 is never published
+    assert_rejected(
+        client.sign_in(SigningKey::K2, |_| {}).await,
+        "unknown ID token key",
+    );
+    assert_eq!(client.mock.jwks_fetches(), 1);
+}
+
+#[tokio::test]
+async fn forged_signature_is_rejected() {
+    let client = Client::start().await;
+
+    assert_rejected(
+        client.sign_in(SigningKey::Forged, |_| {}).await,
+        "InvalidSignature",
+    );
+}
+
+#[tokio::test]
+async fn wrong_issuer_is_rejected() {
+    let client = Client::start().await;
+
+    assert_rejected(
+        client
+            .sign_in(SigningKey::K1, |claims| {
+                claims["iss"] = json!("https://evil.example.com");
+            })
+            .await,
+        "InvalidIssuer",
+    );
+}
+
+#[tokio::test]
+async fn wrong_audience_is_rejected() {
+    let client = Client::start().await;
+
+    assert_rejected(
+        client
+            .sign_in(SigningKey::K1, |claims| {
+                claims["aud"] = json!("some-other-client");
+            })
+            .await,
+        "InvalidAudience",
+    );
+}
+
+#[tokio::test]
+async fn wrong_nonce_is_rejected() {
+    let client = Client::start().await;
+
+    assert_rejected(
+        client
+            .sign_in(SigningKey::K1, |claims| {
+                claims["nonce"] = json!("replayed-nonce");
+            })
+            .await,
+        "nonce mismatch",
+    );
+
+    assert_rejected(
+        client
+            .sign_in(SigningKey::K1, |claims| {
+                claims.as_object_mut().unwrap().remove("nonce");
+            })
+            .await,
+        "nonce mismatch",
+    );
+}
+
+#[tokio::test]
+async fn expired_id_token_is_rejected() {
+    let client = Client::start().await;
+
+    // well past the 60 seconds of leeway
+    let expired = Utc::now().timestamp() - 600;
+
+    assert_rejected(
+        client
+            .sign_in(SigningKey::K1, |claims| {
+                claims["iat"] = json!(expired - 300);
+                claims["exp"] = json!(expired);
+            })
+            .await,
+        "ExpiredSignature",
+    );
+}
@@ -0,0 +1,26 @@
+// The in-memory counters, no database needed.
+
+use chrono::{Duration, DurationRound, Utc};
+use rw_axum_api::repositories::{InMemoryRateLimitRepository, RateLimitRepositoryTrait};
+
+#[tokio::test]
+async fn sprayed_keys_dont_reset_longer_windows() {
+    let repository = InMemoryRateLimitRepository::new();
+    let now = Utc::now();
+    let hour = now.duration_trunc(Duration::hours(1)).unwrap();
+    let minute = now.duration_trunc(Duration::minutes(1)).unwrap();
+
+    for _ in 0..3 {
+        repository.hit("forgot-password:ip", hour).await.unwrap();
+    }
+
+    // enough fresh keys of a short window to trigger the sweep
+    for i in 0..10_001 {
+        repository
+            .hit(&format!("login:spray-{}", i), minute)
+            .await
+            .unwrap();
+    }
+
+    assert_eq!(repository.hit("forgot-password:ip", hour).await.unwrap(), 4);
+}